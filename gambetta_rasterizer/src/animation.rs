@@ -0,0 +1,330 @@
+//! Keyframe animation of instances, evaluated at an explicit time in seconds.
+//!
+//! A track holds keyframes of one property: the position, the orientation or the scale.
+//! Between two keyframes the value follows the interpolation of the first one. An animation
+//! groups the tracks of one instance and decides what happens after the last keyframe.
+//!
+//! Nothing depends on the frame rate or the clock: the same time always gives the same pose,
+//! so a window can pass the time since it started and an exporter the number of the frame
+//! divided by the frame rate.
+
+use crate::instance::Instance;
+use crate::quaternion::Quaternion;
+use common::{vectors, Vector3f};
+
+/// How the value changes from a keyframe to the next one
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Keeps the value of the keyframe until the next one
+    Step,
+    /// At a constant speed
+    Linear,
+    /// Along the timing curve from (0, 0) to (1, 1) with the control points (x1, y1) and (x2, y2),
+    /// like the CSS `cubic-bezier`: (0.42, 0.0, 0.58, 1.0) eases in and out
+    CubicBezier(f64, f64, f64, f64),
+}
+
+impl Interpolation {
+    pub fn ease_in_out() -> Self {
+        Interpolation::CubicBezier(0.42, 0.0, 0.58, 1.0)
+    }
+
+    /// Progress from the value of a keyframe to the next one at the fraction of the time between them
+    fn progress(&self, t: f64) -> f64 {
+        match *self {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => t,
+            Interpolation::CubicBezier(x1, y1, x2, y2) => {
+                // the curve is monotonic in x for x1 and x2 within [0, 1], bisection finds where it is at t
+                let x1 = x1.clamp(0.0, 1.0);
+                let x2 = x2.clamp(0.0, 1.0);
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..50 {
+                    let middle = (low + high) / 2.0;
+                    if cubic_bezier(x1, x2, middle) < t {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                cubic_bezier(y1, y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+/// Coordinate of the cubic Bézier curve from 0 to 1 with the control points p1 and p2
+fn cubic_bezier(p1: f64, p2: f64, s: f64) -> f64 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s
+}
+
+/// Value that can be animated
+pub trait Interpolate: Copy {
+    /// This value at 0, the other one at 1
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vector3f {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        vectors::sum(*self, vectors::scale(t, vectors::difference(*other, *self)))
+    }
+}
+
+/// Along the shorter arc, see `Quaternion::slerp`
+impl Interpolate for Quaternion {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T> {
+    /// In seconds
+    pub time: f64,
+    pub value: T,
+    /// Towards the next keyframe
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes of one property, sorted by time
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self { keyframes: vec![] }
+    }
+
+    /// A keyframe at the same time as an existing one replaces it
+    pub fn with_keyframe(mut self, time: f64, value: T, interpolation: Interpolation) -> Self {
+        let keyframe = Keyframe { time, value, interpolation };
+        match self.keyframes.binary_search_by(|existing| existing.time.total_cmp(&time)) {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    /// Time of the last keyframe
+    pub fn end_time(&self) -> f64 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Value at the time; before the first keyframe it is the first value, after the last one
+    /// the last value, and `None` without keyframes
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes.first().map(|keyframe| keyframe.value);
+        }
+        let current = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return Some(current.value);
+        };
+
+        let t = (time - current.time) / (next.time - current.time);
+        Some(current.value.interpolate(&next.value, current.interpolation.progress(t)))
+    }
+}
+
+impl<T: Interpolate> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What an animation does after its last keyframe
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Playback {
+    /// Stays at the last keyframe
+    Once,
+    /// Starts over from the beginning
+    Loop,
+    /// Plays backwards to the beginning, then forwards again
+    PingPong,
+}
+
+/// Tracks of the position, orientation and scale of an instance; the properties without
+/// a track are left as they are
+#[derive(Clone)]
+pub struct Animation {
+    pub position: Option<Track<Vector3f>>,
+    pub orientation: Option<Track<Quaternion>>,
+    /// Along the x, y and z axes of the model
+    pub scale: Option<Track<Vector3f>>,
+    pub playback: Playback,
+}
+
+impl Animation {
+    pub fn new(playback: Playback) -> Self {
+        Self {
+            position: None,
+            orientation: None,
+            scale: None,
+            playback,
+        }
+    }
+
+    pub fn with_position(mut self, track: Track<Vector3f>) -> Self {
+        self.position = Some(track);
+        self
+    }
+
+    pub fn with_orientation(mut self, track: Track<Quaternion>) -> Self {
+        self.orientation = Some(track);
+        self
+    }
+
+    pub fn with_scale(mut self, track: Track<Vector3f>) -> Self {
+        self.scale = Some(track);
+        self
+    }
+
+    /// Time of the last keyframe of all the tracks; playback repeats from 0 to it
+    pub fn duration(&self) -> f64 {
+        let position = self.position.as_ref().map_or(0.0, Track::end_time);
+        let orientation = self.orientation.as_ref().map_or(0.0, Track::end_time);
+        let scale = self.scale.as_ref().map_or(0.0, Track::end_time);
+        position.max(orientation).max(scale)
+    }
+
+    /// Time within the tracks that is played at the time since the start
+    pub fn local_time(&self, time: f64) -> f64 {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        match self.playback {
+            Playback::Once => time.clamp(0.0, duration),
+            Playback::Loop => time.rem_euclid(duration),
+            Playback::PingPong => {
+                let time = time.rem_euclid(2.0 * duration);
+                if time > duration {
+                    2.0 * duration - time
+                } else {
+                    time
+                }
+            }
+        }
+    }
+
+    /// Moves the instance to its pose at the time since the start
+    pub fn apply(&self, instance: &mut Instance, time: f64) {
+        let time = self.local_time(time);
+        if let Some(position) = self.position.as_ref().and_then(|track| track.sample(time)) {
+            instance.set_position(position);
+        }
+        if let Some(orientation) = self.orientation.as_ref().and_then(|track| track.sample(time)) {
+            instance.set_orientation(orientation);
+        }
+        if let Some(scale) = self.scale.as_ref().and_then(|track| track.sample(time)) {
+            instance.set_scale(scale);
+        }
+    }
+}
+
+#[test]
+fn test_interpolations() {
+    let track = Track::new()
+        .with_keyframe(1.0, 0.0, Interpolation::Step)
+        .with_keyframe(2.0, 10.0, Interpolation::Linear)
+        .with_keyframe(4.0, 20.0, Interpolation::ease_in_out());
+
+    assert_eq!(track.sample(0.0), Some(0.0));
+    assert_eq!(track.sample(1.9), Some(0.0));
+    assert_eq!(track.sample(2.0), Some(10.0));
+    assert_eq!(track.sample(3.0), Some(15.0));
+    assert_eq!(track.sample(5.0), Some(20.0));
+    assert_eq!(Track::<f64>::new().sample(1.0), None);
+
+    // the ease in and out is slow at the ends and symmetric around the middle
+    let ease = Interpolation::ease_in_out();
+    assert!(ease.progress(0.1) < 0.1 && ease.progress(0.9) > 0.9);
+    assert!((ease.progress(0.5) - 0.5).abs() < 1e-9);
+    assert!((ease.progress(0.3) + ease.progress(0.7) - 1.0).abs() < 1e-9);
+    assert!(ease.progress(0.0).abs() < 1e-9 && (ease.progress(1.0) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_playback() {
+    let animation = |playback| {
+        Animation::new(playback)
+            .with_position(
+                Track::new()
+                    .with_keyframe(0.0, Vector3f::zero_vector(), Interpolation::Linear)
+                    .with_keyframe(1.0, Vector3f { x: 1.0, y: 0.0, z: 0.0 }, Interpolation::Linear),
+            )
+            .with_scale(Track::new().with_keyframe(
+                2.0,
+                Vector3f { x: 1.0, y: 1.0, z: 1.0 },
+                Interpolation::Step,
+            ))
+    };
+    // the longest track sets the duration
+    assert_eq!(animation(Playback::Once).duration(), 2.0);
+
+    assert_eq!(animation(Playback::Once).local_time(5.0), 2.0);
+    assert_eq!(animation(Playback::Once).local_time(-1.0), 0.0);
+    assert_eq!(animation(Playback::Loop).local_time(5.0), 1.0);
+    assert_eq!(animation(Playback::Loop).local_time(-0.5), 1.5);
+    assert_eq!(animation(Playback::PingPong).local_time(3.5), 0.5);
+    assert_eq!(animation(Playback::PingPong).local_time(4.5), 0.5);
+    assert_eq!(Animation::new(Playback::Loop).local_time(3.0), 0.0);
+}
+
+#[test]
+fn test_animation_moves_instance() {
+    use crate::model::cube;
+    use crate::vector4f::Vector4f;
+
+    let cube = cube(2.0);
+    let mut instance = Instance::new(&cube, Vector3f::zero_vector(), 1.0, Vector3f::zero_vector());
+    let z_axis = Vector3f { x: 0.0, y: 0.0, z: 1.0 };
+    let animation = Animation::new(Playback::Loop)
+        .with_position(
+            Track::new()
+                .with_keyframe(0.0, Vector3f::zero_vector(), Interpolation::Linear)
+                .with_keyframe(2.0, Vector3f { x: 0.0, y: 0.0, z: 4.0 }, Interpolation::Linear),
+        )
+        .with_orientation(
+            Track::new()
+                .with_keyframe(0.0, Quaternion::identity(), Interpolation::Linear)
+                .with_keyframe(
+                    2.0,
+                    Quaternion::from_axis_angle(z_axis, 90.0),
+                    Interpolation::Linear,
+                ),
+        )
+        .with_scale(Track::new().with_keyframe(
+            0.0,
+            Vector3f { x: 2.0, y: 1.0, z: 1.0 },
+            Interpolation::Step,
+        ));
+
+    // the same time gives the same pose, whatever came before
+    animation.apply(&mut instance, 3.0);
+    let later = instance.transform();
+    animation.apply(&mut instance, 0.5);
+    animation.apply(&mut instance, 1.0);
+    let transform = instance.transform();
+    assert_eq!(later.elements, transform.elements);
+
+    // half way: moved by 2 along z and turned by 45 degrees, with x stretched twice
+    let x: Vector3f = Vector4f::from(Vector3f { x: 1.0, y: 0.0, z: 0.0 }).transform(transform).into();
+    let half_diagonal = 2.0f64.sqrt();
+    let expected = Vector3f { x: half_diagonal, y: half_diagonal, z: 2.0 };
+    assert!(vectors::length(vectors::difference(x, expected)) < 1e-9);
+}
@@ -1,26 +1,34 @@
-use crate::projective_camera::ProjectiveCamera;
-use crate::vector4f::Vector4f;
+use crate::camera::{Camera, Projected};
 use crate::Point;
 use common::{Color, Pixel};
+use std::ops::RangeInclusive;
 
 pub struct BufferCanvas {
-    pub size: usize,
+    pub width: usize,
+    pub height: usize,
     pub buffer: Vec<u8>,
+    /// Greater is closer, 0 is infinitely far: the depths of `Camera::project` or normalized depths, see `Clipping`
     pub depth_buffer: Vec<f64>,
 }
 
 impl BufferCanvas {
-    pub fn new(size: usize) -> Self {
+    pub fn new(width: usize, height: usize) -> Self {
         Self {
-            size,
-            buffer: vec![0u8; size * size * 3],
-            depth_buffer: vec![0.0; size * size],
+            width,
+            height,
+            buffer: vec![0u8; width * height * 3],
+            depth_buffer: vec![0.0; width * height],
         }
     }
 
+    /// Width divided by height, for the `aspect_ratio` of the camera
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+
     pub fn clear(&mut self) {
         self.buffer.fill(0);
-        // self.buffer = vec![0u8; self.size * self.size * 3];
+        // self.buffer = vec![0u8; self.width * self.height * 3];
         // unsafe {
         //     libc::memset(
         //         self.buffer.as_mut_ptr() as _,
@@ -30,7 +38,7 @@ impl BufferCanvas {
         // }
 
         self.depth_buffer.fill(0.0);
-        // self.depth_buffer = vec![0.0; self.size * self.size];
+        // self.depth_buffer = vec![0.0; self.width * self.height];
         // unsafe {
         //     libc::memset(
         //         self.depth_buffer.as_mut_ptr() as _,
@@ -40,29 +48,31 @@ impl BufferCanvas {
         // };
     }
 
-    pub fn viewport_to_canvas(&self, vertex: Vector4f, camera: &ProjectiveCamera) -> Point {
-        let point = camera.project_vertex(vertex);
-        let canvas_size = self.size as f64;
+    /// Pixels are square: the height of the canvas is the height of the viewport.
+    /// The point keeps the w of the projection as its z.
+    pub fn viewport_to_canvas(&self, projected: Projected, camera: &dyn Camera) -> Point {
+        let point = projected.point;
+        let canvas_height = self.height as f64;
         Point {
-            x: (point.x * canvas_size / camera.viewport_size) as i32,
-            y: (point.y * canvas_size / camera.viewport_size) as i32,
+            x: (point.x * canvas_height / camera.viewport_size()) as i32,
+            y: (point.y * canvas_height / camera.viewport_size()) as i32,
             h: 1.0,
-            z: vertex.z,
+            z: projected.w,
         }
     }
 
     pub fn put_pixel(&mut self, pixel: Pixel) {
         log::trace!(
-            "pixel.y: {}, self.size: {}, pixel.x: {}",
+            "pixel.y: {}, self.width: {}, pixel.x: {}",
             pixel.y,
-            self.size,
+            self.width,
             pixel.x
         );
-        common::put_pixel_to_buffer(&mut self.buffer, self.size, pixel);
+        common::put_pixel_to_buffer(&mut self.buffer, self.width, pixel);
     }
 
     pub fn update_depth_buffer_if_closer(&mut self, screen_x: usize, screen_y: usize, iz: f64) -> bool {
-        let depth_index = screen_y * self.size + screen_x;
+        let depth_index = screen_y * self.width + screen_x;
 
         if iz > self.depth_buffer[depth_index] {
             self.depth_buffer[depth_index] = iz;
@@ -73,25 +83,29 @@ impl BufferCanvas {
     }
 
     pub fn screen_x(&self, x_canvas: i32) -> usize {
-        let canvas_width = self.size as i32;
-        let result = canvas_width / 2 + x_canvas;
-
-        if result == canvas_width {
-            return (canvas_width - 1) as usize;
-        } else {
-            return result as usize;
-        }
+        screen_x(self.width, x_canvas)
     }
 
     pub fn screen_y(&self, y_canvas: i32) -> usize {
-        let canvas_height = self.size as i32;
-        let result = canvas_height / 2 - y_canvas - 1;
+        screen_y(self.height, y_canvas)
+    }
 
-        if result == -1 {
-            return 0;
-        } else {
-            return result as usize;
-        }
+    /// Splits the canvas into horizontal tiles of `rows` rows (the last one may be lower),
+    /// each borrowing its own part of the buffers
+    pub fn tiles(&mut self, rows: usize) -> Vec<CanvasTile<'_>> {
+        let (width, height) = (self.width, self.height);
+        self.buffer
+            .chunks_mut(rows * width * 3)
+            .zip(self.depth_buffer.chunks_mut(rows * width))
+            .enumerate()
+            .map(|(index, (buffer, depth_buffer))| CanvasTile {
+                width,
+                height,
+                first_row: index * rows,
+                buffer,
+                depth_buffer,
+            })
+            .collect()
     }
 
     fn point_to_pixel(&self, x: i32, y: i32, color: Color) -> Pixel {
@@ -169,3 +183,149 @@ impl BufferCanvas {
         }
     }
 }
+
+/// Horizontal band of canvas rows, which can be rendered independently of the other tiles.
+/// Methods take screen coordinates of the whole canvas.
+pub struct CanvasTile<'a> {
+    pub width: usize,
+    /// Height of the whole canvas
+    pub height: usize,
+    pub first_row: usize,
+    pub buffer: &'a mut [u8],
+    pub depth_buffer: &'a mut [f64],
+}
+
+impl CanvasTile<'_> {
+    pub fn rows(&self) -> usize {
+        self.depth_buffer.len() / self.width
+    }
+
+    /// Canvas y coordinates that fall into the tile
+    pub fn canvas_y_range(&self) -> RangeInclusive<i32> {
+        let half_height = (self.height / 2) as i32;
+        let first_row = self.first_row as i32;
+        let last_row = first_row + self.rows() as i32 - 1;
+
+        // the y one past the top of the canvas is drawn into the first row, see `screen_y`
+        let top = if first_row == 0 {
+            half_height
+        } else {
+            half_height - first_row - 1
+        };
+        (half_height - last_row - 1)..=top
+    }
+
+    pub fn screen_x(&self, x_canvas: i32) -> usize {
+        screen_x(self.width, x_canvas)
+    }
+
+    pub fn screen_y(&self, y_canvas: i32) -> usize {
+        screen_y(self.height, y_canvas)
+    }
+
+    pub fn update_depth_buffer_if_closer(&mut self, screen_x: usize, screen_y: usize, iz: f64) -> bool {
+        let depth_index = (screen_y - self.first_row) * self.width + screen_x;
+
+        if iz > self.depth_buffer[depth_index] {
+            self.depth_buffer[depth_index] = iz;
+            return true;
+        }
+
+        false
+    }
+
+    /// Whether the canvas point is one past the top or the right edge of the canvas;
+    /// such points are drawn into the first row or the last column, see `screen_y` and `screen_x`
+    pub fn is_clamped(&self, x_canvas: i32, y_canvas: i32) -> bool {
+        (self.width / 2) as i32 + x_canvas == self.width as i32 || y_canvas == (self.height / 2) as i32
+    }
+
+    /// Depth test without updating the depth buffer
+    pub fn is_closer(&self, screen_x: usize, screen_y: usize, iz: f64) -> bool {
+        iz > self.depth_buffer[(screen_y - self.first_row) * self.width + screen_x]
+    }
+
+    pub fn put_pixel(&mut self, pixel: Pixel) {
+        let offset = self.offset(pixel.x, pixel.y);
+        self.buffer[offset] = pixel.color.r;
+        self.buffer[offset + 1] = pixel.color.g;
+        self.buffer[offset + 2] = pixel.color.b;
+    }
+
+    pub fn get_pixel(&self, screen_x: usize, screen_y: usize) -> Color {
+        let offset = self.offset(screen_x, screen_y);
+        Color {
+            r: self.buffer[offset],
+            g: self.buffer[offset + 1],
+            b: self.buffer[offset + 2],
+        }
+    }
+
+    /// Mixes the color of the pixel with the color already in the tile, "over" operator
+    pub fn blend_pixel(&mut self, pixel: Pixel, alpha: f64) {
+        let offset = self.offset(pixel.x, pixel.y);
+        let color = [pixel.color.r, pixel.color.g, pixel.color.b];
+        for (channel, &value) in self.buffer[offset..offset + 3].iter_mut().zip(color.iter()) {
+            *channel = (value as f64 * alpha + *channel as f64 * (1.0 - alpha)).round() as u8;
+        }
+    }
+
+    fn offset(&self, screen_x: usize, screen_y: usize) -> usize {
+        ((screen_y - self.first_row) * self.width + screen_x) * 3
+    }
+}
+
+fn screen_x(canvas_width: usize, x_canvas: i32) -> usize {
+    let canvas_width = canvas_width as i32;
+    let result = canvas_width / 2 + x_canvas;
+
+    if result == canvas_width {
+        return (canvas_width - 1) as usize;
+    } else {
+        return result as usize;
+    }
+}
+
+fn screen_y(canvas_height: usize, y_canvas: i32) -> usize {
+    let canvas_height = canvas_height as i32;
+    let result = canvas_height / 2 - y_canvas - 1;
+
+    if result == -1 {
+        return 0;
+    } else {
+        return result as usize;
+    }
+}
+
+#[test]
+fn test_tiles_cover_canvas() {
+    let mut canvas = BufferCanvas::new(200, 301);
+    let tiles = canvas.tiles(16);
+    assert_eq!(tiles.len(), 19);
+
+    // every canvas y that is drawn falls into exactly one tile, into the row it is drawn to
+    for y in -151..=150 {
+        let row = screen_y(301, y);
+        let containing: Vec<_> = tiles.iter().filter(|tile| tile.canvas_y_range().contains(&y)).collect();
+        assert_eq!(containing.len(), 1, "y = {}", y);
+        let tile = containing[0];
+        assert!(
+            tile.first_row <= row && row < tile.first_row + tile.rows(),
+            "y = {}",
+            y
+        );
+    }
+}
+
+#[test]
+fn test_blend_pixel() {
+    let mut canvas = BufferCanvas::new(4, 4);
+    let mut tiles = canvas.tiles(2);
+    let tile = &mut tiles[1];
+
+    tile.put_pixel(Pixel { x: 1, y: 3, color: Color { r: 200, g: 100, b: 0 } });
+    tile.blend_pixel(Pixel { x: 1, y: 3, color: Color { r: 0, g: 0, b: 255 } }, 0.25);
+
+    let color = tile.get_pixel(1, 3);
+    assert_eq!((color.r, color.g, color.b), (150, 75, 64));
+}
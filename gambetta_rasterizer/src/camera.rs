@@ -0,0 +1,89 @@
+//! Projections of camera space, where the camera looks along z with x to the right and y up,
+//! to the viewport and back.
+//!
+//! The renderer, clipping and unprojection only go through the `Camera` trait. Its default
+//! methods follow the projection matrix, so a camera mostly has to give the matrix, the direction
+//! it looks along at every point and the way back from the viewport.
+
+use crate::matrix44f::Matrix44f;
+use crate::vector4f::Vector4f;
+use crate::{Plane, PlaneType, Point2D, CLIP_SPACE_PLANES};
+use common::{vectors, Vector3f};
+
+/// Camera-space point projected to the viewport
+#[derive(Copy, Clone)]
+pub struct Projected {
+    pub point: Point2D,
+    /// w of the point in clip space: its z for perspective projections, 1 for parallel ones.
+    /// Varyings divided by it are interpolated perspective-correctly.
+    pub w: f64,
+    /// Value for the depth buffer, greater is closer; changes linearly in screen space
+    pub depth: f64,
+}
+
+/// Cameras are used from several threads at once, hence `Sync`
+pub trait Camera: Sync {
+    /// Transform from world space to camera space
+    fn camera_transform(&self) -> Matrix44f;
+
+    /// Rotation part of `camera_transform`, for directions
+    fn rotation_transform(&self) -> Matrix44f;
+
+    /// Height of the viewport; it is scaled to the height of the canvas
+    fn viewport_size(&self) -> f64;
+
+    /// Width divided by height of the viewport
+    fn aspect_ratio(&self) -> f64;
+
+    /// Projection from camera space to homogeneous clip space, with the normalized depth z/w
+    /// 1 on the near plane and 0 on the far one
+    fn projection_matrix(&self) -> Matrix44f;
+
+    /// Direction in camera space from the camera to the point, along which it is projected
+    fn view_direction(&self, point: Vector3f) -> Vector3f;
+
+    /// Point in camera space seen in the viewport point, with `w` and `depth` interpolated there:
+    /// the ones given by `project` when clipping in camera space, the ones of the projection
+    /// matrix when clipping in clip space
+    fn unproject(&self, point: Point2D, w: f64, depth: f64) -> Vector3f;
+
+    /// Projection used when clipping in camera space, by default the one of the projection matrix
+    fn project(&self, point: Vector3f) -> Projected {
+        let clip = Vector4f::from(point).transform(self.projection_matrix());
+        let half_height = self.viewport_size() / 2.0;
+        Projected {
+            point: Point2D {
+                x: clip.x / clip.w * half_height * self.aspect_ratio(),
+                y: clip.y / clip.w * half_height,
+            },
+            w: clip.w,
+            depth: clip.z / clip.w,
+        }
+    }
+
+    /// Planes of the view volume in camera space, with the normals pointing inside;
+    /// by default the planes -w <= x <= w, -w <= y <= w and 0 <= z <= w of the projection matrix
+    fn clipping_planes(&self) -> Vec<Plane> {
+        let projection = self.projection_matrix().elements;
+        let plane_types = [
+            PlaneType::Left,
+            PlaneType::Right,
+            PlaneType::Bottom,
+            PlaneType::Top,
+            PlaneType::Near,
+            PlaneType::Far,
+        ];
+
+        CLIP_SPACE_PLANES
+            .iter()
+            .zip(plane_types)
+            .map(|(plane, plane_type)| {
+                // coefficients of x, y, z and 1 in camera space
+                let [a, b, c, d] = projection.map(|row| (0..4).map(|i| row[i] * plane[i]).sum::<f64>());
+                let normal = Vector3f { x: a, y: b, z: c };
+                let point = vectors::scale(-d / vectors::dot_product(normal, normal), normal);
+                Plane { plane_type, normal, point }
+            })
+            .collect()
+    }
+}
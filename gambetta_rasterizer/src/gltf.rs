@@ -0,0 +1,227 @@
+//! Models and instances of glTF scenes read by `gltf_import`.
+//!
+//! Every mesh becomes a model whose primitives share its vertex list, and every node that carries
+//! a mesh an instance at the world position, orientation and scale of the node. Shear in the node
+//! transforms is lost.
+//!
+//! The PBR materials are approximated with Phong ones: the base color, or its texture, is the
+//! diffuse color; highlights take the color of metals and stay white on dielectrics, and
+//! get wider and dimmer as the roughness grows. Metals keep their diffuse color, as there are
+//! no reflections here. Normal, metallic-roughness and emissive textures, tangents and
+//! double-sidedness are ignored.
+//!
+//! Materials borrow their textures, so loading gives a `GltfScene` that owns them,
+//! and `GltfScene::models` the models that refer to it.
+
+use crate::instance::Instance;
+use crate::material::{AlphaMode, Material};
+use crate::model::{Model, Triangle, UV};
+use crate::quaternion::Quaternion;
+use crate::texture::{Texture, Wrap};
+use common::{Color, Vector3f};
+use gltf_import::{PbrMaterial, Scene};
+use std::path::Path;
+
+pub use gltf_import::GltfError;
+
+/// Loaded scene with the textures of its images
+pub struct GltfScene {
+    pub scene: Scene,
+    /// One for every image of the scene
+    pub textures: Vec<Texture>,
+}
+
+impl GltfScene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GltfError> {
+        Ok(Self::new(gltf_import::load(path)?))
+    }
+
+    pub fn new(scene: Scene) -> Self {
+        let textures = scene
+            .images
+            .iter()
+            // the default glTF sampler repeats
+            .map(|image| {
+                Texture::from_rgba(image.width, image.height, image.rgba.clone()).with_wrap(Wrap::Repeat)
+            })
+            .collect();
+        Self { scene, textures }
+    }
+
+    /// Model of every mesh, in the order of `Scene::meshes`
+    pub fn models(&self) -> Vec<Model<'_>> {
+        let mut materials: Vec<Material> =
+            self.scene.materials.iter().map(|material| self.material(material)).collect();
+        let default_material = materials.len();
+        materials.push(self.material(&PbrMaterial::default()));
+
+        self.scene
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let mut vertices = vec![];
+                let mut triangles = vec![];
+                let mut uvs = vec![];
+                let mut material_indexes = vec![];
+                for primitive in &mesh.primitives {
+                    let offset = vertices.len();
+                    vertices.extend(primitive.positions.iter().copied());
+                    for &[a, b, c] in &primitive.triangles {
+                        let indexes = [a + offset, b + offset, c + offset];
+                        triangles.push(match &primitive.normals {
+                            Some(normals) => Triangle::new_with_provided_normals(
+                                &vertices,
+                                indexes,
+                                [normals[a], normals[b], normals[c]],
+                            ),
+                            None => Triangle::new_with_calculated_normals(&vertices, indexes),
+                        });
+                        let uv = |index: usize| {
+                            primitive.uvs.as_ref().map_or(UV { u: 0.0, v: 0.0 }, |uvs| UV {
+                                u: uvs[index][0],
+                                v: uvs[index][1],
+                            })
+                        };
+                        uvs.push([uv(a), uv(b), uv(c)]);
+                        material_indexes.push(primitive.material.unwrap_or(default_material));
+                    }
+                }
+                let has_uvs = mesh.primitives.iter().any(|primitive| primitive.uvs.is_some());
+
+                Model {
+                    name: &mesh.name,
+                    vertices,
+                    triangles,
+                    materials: materials.clone(),
+                    material_indexes,
+                    uvs: if has_uvs { Some(uvs) } else { None },
+                    edges: Default::default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Instance of every node of the default scene that carries a mesh, with `models`
+    /// as returned by `models`
+    pub fn instances<'a>(&self, models: &'a [Model<'a>]) -> Vec<Instance<'a>> {
+        self.scene
+            .mesh_instances()
+            .into_iter()
+            .map(|(mesh, transform)| {
+                let (position, rotation, scale) = gltf_import::decompose(&transform);
+                let axis = |column: usize| {
+                    Vector3f::new(rotation[0][column], rotation[1][column], rotation[2][column])
+                };
+                Instance::new(&models[mesh], position, 1.0, Vector3f::zero_vector())
+                    .with_orientation(Quaternion::from_rotated_axes(axis(0), axis(1), axis(2)))
+                    .with_non_uniform_scale(scale)
+            })
+            .collect()
+    }
+
+    fn material(&self, material: &PbrMaterial) -> Material<'_> {
+        let [r, g, b, alpha] = material.base_color;
+        let base_color = gltf_import::srgb([r, g, b]);
+        // metals tint their highlights, dielectrics keep them white, rough surfaces dim them
+        let highlight = |base: u8| {
+            let value = base as f64 * material.metallic + 255.0 * (1.0 - material.metallic);
+            (value * (1.0 - material.roughness)).round() as u8
+        };
+        let specular_color = Color {
+            r: highlight(base_color.r),
+            g: highlight(base_color.g),
+            b: highlight(base_color.b),
+        };
+        let mut converted = Material::diffuse(base_color)
+            .with_specular(specular_color, material.shininess())
+            .with_emissive(gltf_import::srgb(material.emissive))
+            .with_opacity(alpha);
+        converted.diffuse_texture = material.base_color_texture.map(|index| &self.textures[index]);
+        match material.alpha_mode {
+            gltf_import::AlphaMode::Opaque => converted,
+            gltf_import::AlphaMode::Mask { cutoff } => converted.with_alpha_mode(AlphaMode::Mask { cutoff }),
+            gltf_import::AlphaMode::Blend => converted.with_alpha_mode(AlphaMode::Blend),
+        }
+    }
+}
+
+#[test]
+fn test_gltf_scene() {
+    use crate::vector4f::Vector4f;
+    use common::vectors;
+    use gltf_import::{Image, Mesh, Node, Primitive};
+
+    let triangle = |material| Primitive {
+        positions: vec![
+            Vector3f::new(0.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+        ],
+        normals: None,
+        tangents: None,
+        uvs: Some(vec![[0.0, 1.0], [0.0, 0.0], [1.0, 1.0]]),
+        triangles: vec![[0, 1, 2]],
+        material,
+    };
+    let mut translation = gltf_import::IDENTITY;
+    translation[0][3] = 5.0;
+    let mut scale = gltf_import::IDENTITY;
+    scale[1][1] = 3.0;
+    let scene = GltfScene::new(Scene {
+        meshes: vec![Mesh {
+            name: "triangles".to_string(),
+            primitives: vec![triangle(Some(0)), triangle(None)],
+        }],
+        materials: vec![PbrMaterial {
+            base_color: [0.0, 0.0, 1.0, 0.5],
+            base_color_texture: Some(0),
+            metallic: 0.0,
+            roughness: 0.5,
+            alpha_mode: gltf_import::AlphaMode::Blend,
+            ..PbrMaterial::default()
+        }],
+        images: vec![Image { width: 1, height: 1, rgba: vec![10, 20, 30, 255] }],
+        nodes: vec![
+            Node {
+                name: "parent".to_string(),
+                mesh: None,
+                children: vec![1],
+                transform: translation,
+            },
+            Node {
+                name: "child".to_string(),
+                mesh: Some(0),
+                children: vec![],
+                transform: scale,
+            },
+        ],
+        roots: vec![0],
+    });
+
+    // the primitives share the vertex list, the one without a material gets the default one
+    let models = scene.models();
+    let model = &models[0];
+    assert_eq!(model.vertices.len(), 6);
+    assert_eq!(model.triangles[1].indexes, [3, 4, 5]);
+    assert_eq!(model.material_indexes, vec![0, 1]);
+    assert_eq!(model.uvs.as_ref().unwrap()[0][0].v, 1.0);
+    // facing -z, towards the camera, as the primitive is clockwise seen from there
+    assert!(model.triangles[0].calculated_normal.z < 0.0);
+
+    let translucent = &model.materials[0];
+    assert_eq!(translucent.diffuse_color.b, 255);
+    assert_eq!(translucent.shininess, 30);
+    assert_eq!(translucent.specular_color.r, 128);
+    assert!(translucent.diffuse_texture.is_some());
+    assert_eq!(translucent.opacity, 0.5);
+    assert!(matches!(translucent.alpha_mode, AlphaMode::Blend));
+    let default = &model.materials[1];
+    assert_eq!(default.shininess, 0);
+    assert!(default.diffuse_texture.is_none());
+
+    // the child is stretched along y, then moved by its parent
+    let instances = scene.instances(&models);
+    assert_eq!(instances.len(), 1);
+    let top: Vector3f = Vector4f::from(model.vertices[1]).transform(instances[0].transform()).into();
+    assert!(vectors::length(vectors::difference(top, Vector3f::new(5.0, 3.0, 0.0))) < 1e-9);
+}
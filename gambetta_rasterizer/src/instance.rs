@@ -1,40 +1,118 @@
 use crate::matrix44f::Matrix44f;
-use crate::model::Model;
+use crate::model::{BoundingSphere, Model};
+use crate::quaternion::Quaternion;
+use crate::scene_graph::Transform;
 use crate::vector4f::Vector4f;
 use crate::Vector3f;
+use common::Color;
 
 pub struct Instance<'a> {
     pub model: &'a Model<'a>,
     position: Vector4f,
-    scale: f64,
-    rotation: Vector3f,
+    /// Along the x, y and z axes of the model
+    scale: Vector3f,
+    orientation: Quaternion,
     pub position_delta: Vector3f,
+    /// Added to the scale along every axis
     pub scale_delta: f64,
+    /// Degrees around the x, y and z axes of the model, turned every time the deltas are applied
     pub rotation_delta: Vector3f,
+    /// Of the model, in model space
+    bounding_sphere: BoundingSphere,
+    /// World transform of the scene graph node the instance belongs to, applied after its own
+    parent: Transform,
+    /// Of the edges in the wireframe, hidden-line and overlay rendering modes
+    wireframe_color: Color,
 }
 
 impl<'a> Instance<'a> {
+    /// Instance with a uniform scale and the rotation in degrees around x, then y, then z
     pub fn new(model: &'a Model, position: Vector3f, scale: f64, rotation: Vector3f) -> Self {
         Self {
             model,
             position: position.into(),
-            scale,
-            rotation,
+            scale: Vector3f { x: scale, y: scale, z: scale },
+            orientation: Quaternion::from_euler(rotation),
             position_delta: Vector3f::zero_vector(),
             scale_delta: 0.0,
             rotation_delta: Vector3f::zero_vector(),
+            bounding_sphere: model.bounding_sphere(),
+            parent: Transform::identity(),
+            wireframe_color: Color { r: 255, g: 255, b: 255 },
         }
     }
 
+    pub fn with_orientation(mut self, orientation: Quaternion) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn with_non_uniform_scale(mut self, scale: Vector3f) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_parent(mut self, parent: Transform) -> Self {
+        self.parent = parent;
+        self
+    }
+
+    pub fn with_wireframe_color(mut self, color: Color) -> Self {
+        self.wireframe_color = color;
+        self
+    }
+
+    pub fn wireframe_color(&self) -> Color {
+        self.wireframe_color
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Quaternion) {
+        self.orientation = orientation;
+    }
+
+    pub fn set_position(&mut self, position: Vector3f) {
+        self.position = position.into();
+    }
+
+    /// Along the x, y and z axes of the model
+    pub fn set_scale(&mut self, scale: Vector3f) {
+        self.scale = scale;
+    }
+
+    /// Sphere containing the whole instance, in world space
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        let scale = self.scale.x.abs().max(self.scale.y.abs()).max(self.scale.z.abs());
+        BoundingSphere {
+            center: Vector4f::from(self.bounding_sphere.center).transform(self.transform()).into(),
+            radius: self.bounding_sphere.radius * scale * self.parent.scale,
+        }
+    }
+
+    /// Scale, rotation and translation, then the transform of the parent
     pub fn transform(&self) -> Matrix44f {
-        self.rotation_transform()
-            .multiply(Matrix44f::uniform_scale(self.scale))
+        Matrix44f::scale(self.scale)
+            .multiply(self.orientation.to_matrix())
             .multiply(Matrix44f::translation(self.position))
+            .multiply(self.parent.matrix())
     }
 
     pub fn rotation_transform(&self) -> Matrix44f {
-        Matrix44f::rotation_x(self.rotation.x)
-            .multiply(Matrix44f::rotation_y(self.rotation.y).multiply(Matrix44f::rotation_z(self.rotation.z)))
+        self.orientation.to_matrix().multiply(self.parent.rotation)
+    }
+
+    /// Inverse transpose of the linear part of `transform`, which keeps normals perpendicular
+    /// to the surface under non-uniform scale; the normals it gives are not normalized
+    pub fn normal_transform(&self) -> Matrix44f {
+        let inverse_scale = Vector3f {
+            x: 1.0 / self.scale.x,
+            y: 1.0 / self.scale.y,
+            z: 1.0 / self.scale.z,
+        };
+        Matrix44f::scale(inverse_scale).multiply(self.rotation_transform())
     }
 
     pub fn apply_deltas(&mut self) {
@@ -44,9 +122,7 @@ impl<'a> Instance<'a> {
     }
 
     fn apply_rotation_deltas(&mut self) {
-        self.rotation.x += self.rotation_delta.x;
-        self.rotation.y += self.rotation_delta.y;
-        self.rotation.z += self.rotation_delta.z;
+        self.orientation = Quaternion::from_euler(self.rotation_delta).then(self.orientation).normalize();
     }
 
     fn apply_position_deltas(&mut self) {
@@ -56,7 +132,9 @@ impl<'a> Instance<'a> {
     }
 
     fn apply_scale_delta(&mut self) {
-        self.scale += self.scale_delta;
+        self.scale.x += self.scale_delta;
+        self.scale.y += self.scale_delta;
+        self.scale.z += self.scale_delta;
     }
 }
 
@@ -73,9 +151,10 @@ fn test_new() {
         name: "test_model",
         vertices,
         triangles,
-        colors: vec![common::Color { r: 0, g: 0, b: 0 }],
-        textures: None,
+        materials: vec![crate::Material::diffuse(common::Color { r: 0, g: 0, b: 0 })],
+        material_indexes: vec![0],
         uvs: None,
+        edges: Default::default(),
     };
 
     let instance = Instance::new(&model, Vector3f::zero_vector(), 1.0, Vector3f::zero_vector());
@@ -97,9 +176,10 @@ fn test_new_with_position() {
         name: "test_model",
         vertices,
         triangles,
-        colors: vec![common::Color { r: 0, g: 0, b: 0 }],
-        textures: None,
+        materials: vec![crate::Material::diffuse(common::Color { r: 0, g: 0, b: 0 })],
+        material_indexes: vec![0],
         uvs: None,
+        edges: Default::default(),
     };
 
     let instance = Instance::new(
@@ -126,9 +206,10 @@ fn test_new_with_scale() {
         name: "test_model",
         vertices,
         triangles,
-        colors: vec![common::Color { r: 0, g: 0, b: 0 }],
-        textures: None,
+        materials: vec![crate::Material::diffuse(common::Color { r: 0, g: 0, b: 0 })],
+        material_indexes: vec![0],
         uvs: None,
+        edges: Default::default(),
     };
 
     let instance = Instance::new(&model, Vector3f::zero_vector(), 2.0, Vector3f::zero_vector());
@@ -150,9 +231,10 @@ fn test_new_with_rotation() {
         name: "test_model",
         vertices,
         triangles,
-        colors: vec![common::Color { r: 0, g: 0, b: 0 }],
-        textures: None,
+        materials: vec![crate::Material::diffuse(common::Color { r: 0, g: 0, b: 0 })],
+        material_indexes: vec![0],
         uvs: None,
+        edges: Default::default(),
     };
 
     let instance = Instance::new(
@@ -165,3 +247,62 @@ fn test_new_with_rotation() {
         println!("{:.2} {:.2} {:.2}", vertex.x, vertex.y, vertex.z);
     }
 }
+
+#[test]
+fn test_normals_with_non_uniform_scale() {
+    use crate::model::cube;
+    use common::vectors;
+
+    let cube = cube(2.0);
+    let instance = Instance::new(&cube, Vector3f::zero_vector(), 1.0, Vector3f::zero_vector())
+        .with_non_uniform_scale(Vector3f { x: 1.0, y: 4.0, z: 0.5 })
+        .with_orientation(Quaternion::from_axis_angle(
+            Vector3f { x: 1.0, y: 2.0, z: 3.0 },
+            50.0,
+        ));
+    let transform = instance.transform();
+    let normal_transform = instance.normal_transform();
+
+    // a slanted plane through the points, the normal stays perpendicular to it
+    let points = [
+        Vector3f { x: 1.0, y: 0.0, z: 0.0 },
+        Vector3f { x: 0.0, y: 1.0, z: 0.0 },
+        Vector3f { x: 0.0, y: 0.0, z: 1.0 },
+    ];
+    let normal: Vector3f =
+        Vector4f::from(Vector3f { x: 1.0, y: 1.0, z: 1.0 }).transform(normal_transform).into();
+    let [a, b, c] = points.map(|point| -> Vector3f { Vector4f::from(point).transform(transform).into() });
+    assert!(vectors::dot_product(normal, vectors::difference(b, a)).abs() < 1e-9);
+    assert!(vectors::dot_product(normal, vectors::difference(c, a)).abs() < 1e-9);
+
+    // the rotation alone doesn't keep it perpendicular
+    let rotated: Vector3f = Vector4f::from(Vector3f { x: 1.0, y: 1.0, z: 1.0 })
+        .transform(instance.rotation_transform())
+        .into();
+    assert!(vectors::dot_product(rotated, vectors::difference(b, a)).abs() > 0.1);
+}
+
+#[test]
+fn test_rotation_deltas_turn_around_the_model_axes() {
+    use crate::model::cube;
+
+    let cube = cube(2.0);
+    let mut instance = Instance::new(
+        &cube,
+        Vector3f::zero_vector(),
+        1.0,
+        Vector3f { x: 0.0, y: 0.0, z: 90.0 },
+    );
+    instance.rotation_delta = Vector3f { x: 0.0, y: 0.0, z: 1.0 };
+    for _ in 0..90 {
+        instance.apply_deltas();
+    }
+
+    let expected = Quaternion::from_euler(Vector3f { x: 0.0, y: 0.0, z: 180.0 }).to_matrix();
+    let actual = instance.rotation_transform();
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((actual.elements[i][j] - expected.elements[i][j]).abs() < 1e-9);
+        }
+    }
+}
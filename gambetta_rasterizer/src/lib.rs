@@ -3,31 +3,73 @@
 //! by Gabriel Gambetta
 
 pub mod model;
+pub mod animation;
 mod buffer_canvas;
+mod camera;
+pub mod gltf;
 mod instance;
+pub mod material;
 mod matrix44f;
+mod oblique_camera;
+pub mod obj;
+mod orthographic_camera;
+pub mod ply;
 mod projective_camera;
+mod quaternion;
+mod scanline;
+pub mod scene_graph;
+pub mod shader;
+pub mod shadow;
 pub mod texture;
+mod transparency;
 mod vector4f;
+mod wireframe;
 
+pub use crate::animation::Animation;
 pub use crate::buffer_canvas::BufferCanvas;
+pub use crate::camera::{Camera, Projected};
 pub use crate::instance::Instance;
+pub use crate::material::Material;
 pub use crate::matrix44f::Matrix44f;
 pub use crate::model::Triangle;
 pub use crate::model::UV;
+pub use crate::oblique_camera::ObliqueCamera;
+pub use crate::orthographic_camera::{IsometricView, OrthographicCamera};
 pub use crate::projective_camera::ProjectiveCamera;
+pub use crate::quaternion::Quaternion;
+pub use crate::scanline::Fragment;
+pub use crate::scene_graph::SceneGraph;
+pub use crate::shadow::ShadowSettings;
 pub use crate::texture::Texture;
 pub use crate::vector4f::Vector4f;
+pub use crate::wireframe::{EdgeCache, Edges, WireframeSettings};
+
+use crate::buffer_canvas::CanvasTile;
+use crate::material::AlphaMode;
+use crate::scanline::ScreenTriangle;
+use crate::shader::{Face, FlatShader, FragmentShader, GouraudShader, PhongShader, VertexShader};
+use crate::shadow::ShadowMap;
+use crate::transparency::WeightedBlendedTile;
+
 use common::vectors;
 use common::Color;
 use common::Light;
 use common::Pixel;
 use common::Vector3f;
+use rayon::prelude::*;
+
+/// Height of the canvas tiles that are rasterized in parallel
+const TILE_ROWS: usize = 16;
 
 #[derive(Copy, Clone)]
 pub enum RenderingMode {
+    /// Edges only, seen through the surfaces
     Wireframe,
     Filled,
+    /// Edges only, hidden by the surfaces in front of them
+    HiddenLine,
+    /// Filled surfaces with their edges drawn over them
+    Overlay,
 }
 
 #[derive(Copy, Clone)]
@@ -37,16 +79,44 @@ pub enum ShadingModel {
     Phong,
 }
 
+/// How triangles with blended materials are composited over the opaque ones
+#[derive(Copy, Clone)]
+pub enum Transparency {
+    /// Drawn back-to-front, sorted by the depth of their centers every frame
+    Sorted,
+    /// Weighted blended order-independent transparency: no sorting, but approximate colors
+    /// where transparent surfaces overlap
+    WeightedBlended,
+}
+
+/// Where the triangles are clipped by the view volume and what the depth buffer stores
+#[derive(Copy, Clone)]
+pub enum Clipping {
+    /// Against the planes of the view volume in camera space; the depths of `Camera::project`
+    /// in the depth buffer, inverse depths 1/z for the projective camera
+    CameraSpace,
+    /// Against -w..w in homogeneous clip space, after the projection matrix of the camera,
+    /// and then divided by w; normalized depths in the depth buffer
+    ClipSpace,
+}
+
 pub struct RenderingSettings {
     pub rendering_mode: RenderingMode,
     pub shading_model: ShadingModel,
     pub show_normals: bool,
     pub backface_culling: bool,
+    pub transparency: Transparency,
+    pub clipping: Clipping,
+    /// Shadow maps are rendered for every point and directional light when set
+    pub shadows: Option<ShadowSettings>,
+    /// Which edges are drawn in the wireframe, hidden-line and overlay modes
+    pub wireframe: WireframeSettings,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum PlaneType {
     Near,
+    Far,
     Left,
     Right,
     Top,
@@ -59,6 +129,14 @@ pub struct Plane {
     pub point: Vector3f,
 }
 
+impl Plane {
+    /// Distance from the plane to the point, negative on the outer side, where the normal doesn't point
+    pub fn signed_distance(&self, point: Vector3f) -> f64 {
+        vectors::dot_product(self.normal, vectors::difference(point, self.point))
+            / vectors::length(self.normal)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Point2D {
     pub x: f64,
@@ -85,39 +163,189 @@ pub struct Triangle4f {
 pub fn render_scene(
     instances: &Vec<Instance>,
     lights: &Vec<Light>,
-    camera: &ProjectiveCamera,
+    camera: &dyn Camera,
     rendering_settings: &RenderingSettings,
     canvas: &mut BufferCanvas,
 ) {
-    let camera_transform = camera.camera_transform();
-    let camera_rotation_transform = camera.rotation.transpose();
-    let clipping_planes = camera.clipping_planes();
-    for instance in instances {
-        render_instance(
-            instance,
+    match rendering_settings.shading_model {
+        ShadingModel::Flat => render_scene_with_shaders(
+            instances,
+            lights,
+            camera,
+            rendering_settings,
+            &FlatShader,
+            &FlatShader,
             canvas,
+        ),
+        ShadingModel::Gouraud => render_scene_with_shaders(
+            instances,
+            lights,
             camera,
+            rendering_settings,
+            &GouraudShader,
+            &GouraudShader,
+            canvas,
+        ),
+        ShadingModel::Phong => render_scene_with_shaders(
+            instances,
             lights,
+            camera,
             rendering_settings,
-            camera_transform,
-            camera_rotation_transform,
-            &clipping_planes,
-        );
+            &PhongShader,
+            &PhongShader,
+            canvas,
+        ),
     }
 }
 
-fn render_instance(
-    instance: &Instance,
-    canvas: &mut BufferCanvas,
-    camera: &ProjectiveCamera,
+/// Renders the scene with custom shaders; `shading_model` of the settings is ignored.
+///
+/// Triangles are transformed, clipped and set up in parallel, then binned into tiles of
+/// the canvas, and every tile is rasterized by its own worker. Each tile draws its opaque
+/// triangles in the scene order and then the blended ones, so the picture doesn't depend
+/// on the number of threads.
+///
+/// The edges of the wireframe, hidden-line and overlay modes are drawn over the canvas afterwards,
+/// one instance after another.
+pub fn render_scene_with_shaders<const N: usize>(
+    instances: &Vec<Instance>,
     lights: &Vec<Light>,
+    camera: &dyn Camera,
     rendering_settings: &RenderingSettings,
-    camera_transform: Matrix44f,
-    camera_rotation_transform: Matrix44f,
-    clipping_planes: &Vec<Plane>,
+    vertex_shader: &impl VertexShader<N>,
+    fragment_shader: &impl FragmentShader<N>,
+    canvas: &mut BufferCanvas,
 ) {
-    log::debug!("rendering instance");
+    let camera_transform = camera.camera_transform();
+    let camera_rotation_transform = camera.rotation_transform();
+    let clipping_planes = camera.clipping_planes();
+    let view_volume = match rendering_settings.clipping {
+        Clipping::CameraSpace => ViewVolume::Planes(&clipping_planes),
+        Clipping::ClipSpace => ViewVolume::ClipSpace(camera.projection_matrix()),
+    };
+    let transformed_lights = transform_lights(lights, camera_transform, camera_rotation_transform);
+    let shadow_maps: Vec<Option<ShadowMap>> = match &rendering_settings.shadows {
+        Some(shadow_settings) => {
+            let casters = shadow_casters(instances, camera_transform);
+            transformed_lights
+                .par_iter()
+                .map(|light| ShadowMap::render(light, &casters, shadow_settings))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let mut triangles = Vec::<(Triangle4f, Face)>::new();
+    let mut visible_instances = Vec::<&Instance>::new();
+    for instance in instances {
+        if !is_instance_in_frustum(instance, camera_transform, &clipping_planes) {
+            continue;
+        }
+        visible_instances.push(instance);
+        triangles.append(&mut clip_instance_triangles(
+            instance,
+            &transformed_lights,
+            &shadow_maps,
+            rendering_settings,
+            camera,
+            &view_volume,
+        ));
+    }
+
+    match rendering_settings.rendering_mode {
+        RenderingMode::Filled | RenderingMode::Overlay => {
+            let screen_triangles: Vec<ScreenTriangle<N>> = triangles
+                .par_iter()
+                .map(|(_, face)| {
+                    let vertices = face.vertices.map(Vector4f::from);
+                    let varyings = [0, 1, 2].map(|index| vertex_shader.shade(face, index));
+                    match view_volume {
+                        ViewVolume::Planes(_) => ScreenTriangle::new(vertices, varyings, camera, canvas),
+                        ViewVolume::ClipSpace(projection) => ScreenTriangle::from_clip_space(
+                            vertices.map(|vertex| vertex.transform(projection)),
+                            varyings,
+                            canvas,
+                        ),
+                    }
+                })
+                .collect();
+
+            let (mut blended, opaque): (Vec<usize>, Vec<usize>) = (0..triangles.len())
+                .partition(|&index| triangles[index].1.material.alpha_mode == AlphaMode::Blend);
+            if let Transparency::Sorted = rendering_settings.transparency {
+                // back to front; the sort is stable, so triangles at the same depth keep the scene order
+                blended.sort_by(|&a, &b| triangles[b].1.center().z.total_cmp(&triangles[a].1.center().z));
+            }
+            let opaque_bins = bin_triangles(&screen_triangles, &opaque, canvas);
+            let blended_bins = bin_triangles(&screen_triangles, &blended, canvas);
+
+            canvas.tiles(TILE_ROWS).into_par_iter().zip(opaque_bins).zip(blended_bins).for_each(
+                |((mut tile, opaque), blended)| {
+                    for index in opaque {
+                        let face = &triangles[index].1;
+                        draw_opaque_triangle(
+                            &mut tile,
+                            &screen_triangles[index],
+                            face,
+                            camera,
+                            fragment_shader,
+                        );
+                    }
+
+                    let blended =
+                        blended.into_iter().map(|index| (&screen_triangles[index], &triangles[index].1));
+                    draw_blended_triangles(
+                        &mut tile,
+                        blended,
+                        camera,
+                        fragment_shader,
+                        rendering_settings.transparency,
+                    );
+                },
+            );
+
+            if rendering_settings.show_normals {
+                for (triangle, _) in &triangles {
+                    draw_normal_to_vertex(triangle.a, triangle.normals[0], camera, canvas);
+                    draw_normal_to_vertex(triangle.b, triangle.normals[1], camera, canvas);
+                    draw_normal_to_vertex(triangle.c, triangle.normals[2], camera, canvas);
+                }
+            }
+        }
+        RenderingMode::Wireframe | RenderingMode::HiddenLine => {}
+    }
 
+    let surfaces = match rendering_settings.rendering_mode {
+        RenderingMode::Filled => return,
+        RenderingMode::Wireframe => None,
+        // opaque surfaces hide the edges behind them; cutouts don't, as their holes would
+        // need the textures sampled
+        RenderingMode::HiddenLine | RenderingMode::Overlay => Some(wireframe::surface_depths(
+            triangles
+                .iter()
+                .filter(|(_, face)| face.material.alpha_mode == AlphaMode::Opaque)
+                .map(|(triangle, _)| triangle),
+            rendering_settings,
+            camera,
+            &view_volume,
+            canvas,
+        )),
+    };
+    wireframe::draw_edges(
+        &visible_instances,
+        surfaces.as_deref(),
+        rendering_settings,
+        camera,
+        &view_volume,
+        canvas,
+    );
+}
+
+fn transform_lights(
+    lights: &Vec<Light>,
+    camera_transform: Matrix44f,
+    camera_rotation_transform: Matrix44f,
+) -> Vec<Light> {
     let mut transformed_lights = Vec::<Light>::with_capacity(lights.len());
     for light in lights {
         let transformed_light = match *light {
@@ -133,86 +361,199 @@ fn render_instance(
         };
         transformed_lights.push(transformed_light);
     }
+    transformed_lights
+}
+
+/// Triangles of all the instances in camera space that cast shadows: whole, facing any way,
+/// except those with blended materials
+fn shadow_casters(instances: &Vec<Instance>, camera_transform: Matrix44f) -> Vec<[Vector3f; 3]> {
+    instances
+        .par_iter()
+        .flat_map_iter(|instance| {
+            let model = instance.model;
+            let instance_transform = instance.transform().multiply(camera_transform);
+            let vertices: Vec<Vector3f> = model
+                .vertices
+                .iter()
+                .map(|vertex| Vector4f::from(vertex).transform(instance_transform).into())
+                .collect();
+
+            model
+                .triangles
+                .iter()
+                .zip(&model.material_indexes)
+                .filter(|(_, &material)| model.materials[material].alpha_mode != AlphaMode::Blend)
+                .map(move |(triangle, _)| triangle.indexes.map(|index| vertices[index]))
+        })
+        .collect()
+}
 
-    let instance_transform = instance.transform().multiply(camera_transform);
-    let combined_rotation_transform = instance.rotation_transform().multiply(camera_rotation_transform);
+/// Whether the bounding sphere of the instance is at least partially inside the clipping planes,
+/// a cheap test for skipping whole instances before their triangles are clipped
+fn is_instance_in_frustum(
+    instance: &Instance,
+    camera_transform: Matrix44f,
+    clipping_planes: &[Plane],
+) -> bool {
+    let sphere = instance.bounding_sphere();
+    let center = Vector4f::from(sphere.center).transform(camera_transform).into();
+    clipping_planes.iter().all(|plane| plane.signed_distance(center) >= -sphere.radius)
+}
 
-    let mut transformed_vertices = Vec::<Vector4f>::with_capacity(instance.model.vertices.len());
+/// Visible triangles of the instance in camera space, clipped by the view frustum,
+/// together with the faces the shaders get for them
+fn clip_instance_triangles<'a>(
+    instance: &'a Instance,
+    transformed_lights: &'a [Light],
+    shadow_maps: &'a [Option<ShadowMap>],
+    rendering_settings: &RenderingSettings,
+    camera: &'a dyn Camera,
+    view_volume: &ViewVolume,
+) -> Vec<(Triangle4f, Face<'a>)> {
+    log::debug!("rendering instance");
 
-    for point3d in &instance.model.vertices {
-        let vertex = Vector4f::from(point3d);
+    let instance_transform = instance.transform().multiply(camera.camera_transform());
+    let combined_normal_transform = instance.normal_transform().multiply(camera.rotation_transform());
+
+    let transformed_vertices: Vec<Vector4f> = instance
+        .model
+        .vertices
+        .par_iter()
+        .map(|point3d| Vector4f::from(point3d).transform(instance_transform))
+        .collect();
+
+    instance
+        .model
+        .triangles
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, triangle)| {
+            let transformed_triangle_normal =
+                Vector4f::from(triangle.calculated_normal).transform(combined_normal_transform);
+
+            let is_face_visible = if rendering_settings.backface_culling {
+                face_visible_4f(
+                    camera.view_direction(transformed_vertices[triangle.indexes[0]].into()),
+                    transformed_triangle_normal.into(),
+                )
+            } else {
+                true
+            };
+
+            let material = &instance.model.materials[instance.model.material_indexes[i]];
+            let triangles = if is_face_visible {
+                view_volume.clip(convert_face_to_triangles(
+                    triangle,
+                    &transformed_vertices,
+                    combined_normal_transform,
+                    material.diffuse_color,
+                ))
+            } else {
+                Vec::new()
+            };
+
+            triangles.into_iter().map(move |triangle| {
+                let face = Face {
+                    vertices: [triangle.a.into(), triangle.b.into(), triangle.c.into()],
+                    normals: triangle.normals,
+                    uvs: instance.model.uvs.as_ref().map(|uvs| uvs[i]),
+                    material,
+                    lights: transformed_lights,
+                    shadow_maps,
+                    camera,
+                };
+                (triangle, face)
+            })
+        })
+        .collect()
+}
 
-        let transformed_vertex = vertex.transform(instance_transform);
-        transformed_vertices.push(transformed_vertex);
+/// Draws a triangle with an opaque or a cutout material, which hides what is behind it
+fn draw_opaque_triangle<const N: usize>(
+    tile: &mut CanvasTile,
+    triangle: &ScreenTriangle<N>,
+    face: &Face,
+    camera: &dyn Camera,
+    fragment_shader: &impl FragmentShader<N>,
+) {
+    match face.material.alpha_mode {
+        AlphaMode::Mask { cutoff } => triangle.rasterize(tile, camera, |tile, fragment| {
+            let (x, y) = (tile.screen_x(fragment.x), tile.screen_y(fragment.y));
+            // the cut out points must not get into the depth buffer
+            if tile.is_closer(x, y, fragment.depth) && fragment_shader.opacity(face, fragment) >= cutoff {
+                tile.update_depth_buffer_if_closer(x, y, fragment.depth);
+                let color = fragment_shader.shade(face, fragment);
+                tile.put_pixel(Pixel { x, y, color });
+            }
+        }),
+        _ => triangle.draw(tile, camera, |fragment| fragment_shader.shade(face, fragment)),
     }
+}
 
-    let mut i = 0;
-    for triangle in &instance.model.triangles {
-        let transformed_triangle_normal =
-            Vector4f::from(triangle.calculated_normal).transform(combined_rotation_transform);
+/// Composites the triangles with blended materials over the picture in the tile.
+/// They are depth tested against the opaque triangles but don't hide each other.
+fn draw_blended_triangles<'f, const N: usize>(
+    tile: &mut CanvasTile,
+    triangles: impl Iterator<Item = (&'f ScreenTriangle<N>, &'f Face<'f>)>,
+    camera: &dyn Camera,
+    fragment_shader: &impl FragmentShader<N>,
+    transparency: Transparency,
+) {
+    let mut accumulation = match transparency {
+        Transparency::Sorted => None,
+        Transparency::WeightedBlended => Some(WeightedBlendedTile::new(tile)),
+    };
 
-        let is_face_visible = if rendering_settings.backface_culling {
-            face_visible_4f(
-                transformed_vertices[triangle.indexes[0]].into(),
-                transformed_triangle_normal.into(),
-            )
-        } else {
-            true
-        };
+    for (triangle, face) in triangles {
+        triangle.rasterize(tile, camera, |tile, fragment| {
+            // the pixels of the clamped points are drawn anyway, they must not be blended twice
+            if tile.is_clamped(fragment.x, fragment.y) {
+                return;
+            }
+            let (x, y) = (tile.screen_x(fragment.x), tile.screen_y(fragment.y));
+            if !tile.is_closer(x, y, fragment.depth) {
+                return;
+            }
+            let pixel = Pixel { x, y, color: fragment_shader.shade(face, fragment) };
+            let alpha = fragment_shader.opacity(face, fragment);
+            match &mut accumulation {
+                Some(accumulation) => accumulation.accumulate(pixel, alpha, fragment.position().z),
+                None => tile.blend_pixel(pixel, alpha),
+            }
+        });
+    }
 
-        if is_face_visible {
-            let triangles = clip_triangles(
-                convert_face_to_triangles(
-                    triangle,
-                    &transformed_vertices,
-                    combined_rotation_transform,
-                    instance.model.colors[i],
-                ),
-                clipping_planes,
-            );
+    if let Some(accumulation) = accumulation {
+        accumulation.resolve(tile);
+    }
+}
 
-            for triangle in triangles {
-                match rendering_settings.rendering_mode {
-                    RenderingMode::Filled => {
-                        match &instance.model.textures {
-                            None => {
-                                render_filled_triangle(
-                                    triangle,
-                                    None,
-                                    None,
-                                    camera,
-                                    canvas,
-                                    &transformed_lights,
-                                    rendering_settings,
-                                );
-                            }
-                            Some(textures) => {
-                                let uvs = instance.model.uvs.as_ref().unwrap();
-
-                                render_filled_triangle(
-                                    triangle,
-                                    Some(textures[i]),
-                                    Some(uvs[i]),
-                                    camera,
-                                    canvas,
-                                    &transformed_lights,
-                                    rendering_settings,
-                                );
-                            }
-                        };
-                    }
-                    RenderingMode::Wireframe => {
-                        render_wireframe_triangle(triangle, camera, canvas);
-                    }
-                }
-            }
+/// Indexes of the given triangles overlapping every canvas tile, in the given order
+fn bin_triangles<const N: usize>(
+    triangles: &[ScreenTriangle<N>],
+    indexes: &[usize],
+    canvas: &BufferCanvas,
+) -> Vec<Vec<usize>> {
+    let tile_count = canvas.height.div_ceil(TILE_ROWS);
+    // y of the triangles beyond the canvas are clamped to its top and bottom rows
+    let half_height = (canvas.height / 2) as i32;
+    let tile_of =
+        |y: i32| canvas.screen_y(y.clamp(half_height - canvas.height as i32, half_height)) / TILE_ROWS;
+
+    let mut bins = vec![Vec::new(); tile_count];
+    for &index in indexes {
+        let triangle = &triangles[index];
+        // y grows upwards and rows grow downwards, so the top of the triangle is in the first tile
+        let (bottom, top) = triangle.canvas_y_range();
+        for bin in &mut bins[tile_of(top)..=tile_of(bottom)] {
+            bin.push(index);
         }
-        i += 1;
     }
+    bins
 }
 
-fn face_visible_4f(vertex: Vector3f, normal_direction: Vector3f) -> bool {
-    vectors::dot_product(vertex, normal_direction) < 0.0
+fn face_visible_4f(view_direction: Vector3f, normal_direction: Vector3f) -> bool {
+    vectors::dot_product(view_direction, normal_direction) < 0.0
 }
 
 fn face_normal_direction_in_right(face: &Vec<i32>, vertices: &[Vector4f]) -> Vector3f {
@@ -239,6 +580,88 @@ fn face_normal_direction_in_left(triangle: &Triangle, vertices: &[Vector4f]) ->
     vectors::cross_product(vector2, vector1)
 }
 
+/// View volume the triangles in camera space are clipped by
+enum ViewVolume<'p> {
+    /// Planes of the frustum in camera space
+    Planes(&'p Vec<Plane>),
+    /// Projection matrix of the camera, the triangles are clipped in homogeneous clip space
+    ClipSpace(Matrix44f),
+}
+
+impl ViewVolume<'_> {
+    fn clip(&self, triangles: Vec<Triangle4f>) -> Vec<Triangle4f> {
+        match self {
+            ViewVolume::Planes(clipping_planes) => clip_triangles(triangles, clipping_planes),
+            ViewVolume::ClipSpace(projection) => triangles
+                .into_iter()
+                .flat_map(|triangle| clip_triangle_in_clip_space(triangle, *projection))
+                .collect(),
+        }
+    }
+}
+
+/// Planes of the view volume in homogeneous clip space, as coefficients of x, y, z and w
+/// that give non-negative values inside it: -w <= x <= w, -w <= y <= w and 0 <= z <= w
+const CLIP_SPACE_PLANES: [[f64; 4]; 6] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [-1.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+    [0.0, -1.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0, 1.0],
+    [0.0, 0.0, 1.0, 0.0],
+];
+
+/// Clips the triangle in camera space by the view volume in homogeneous clip space.
+///
+/// The polygon left inside every plane is split into a fan of triangles. New vertices are found
+/// in clip space, and their camera-space positions are interpolated the same way, as the projection
+/// is linear; the normals of the triangle are kept, as when clipping in camera space.
+fn clip_triangle_in_clip_space(triangle: Triangle4f, projection: Matrix44f) -> Vec<Triangle4f> {
+    let lerp = |a: Vector4f, b: Vector4f, t: f64| Vector4f {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+        w: a.w + (b.w - a.w) * t,
+    };
+    // vertices of the polygon in clip space and in camera space
+    let mut polygon: Vec<(Vector4f, Vector4f)> = [triangle.a, triangle.b, triangle.c]
+        .iter()
+        .map(|&vertex| (vertex.transform(projection), vertex))
+        .collect();
+
+    for plane in CLIP_SPACE_PLANES {
+        let distance = |vertex: Vector4f| {
+            plane[0] * vertex.x + plane[1] * vertex.y + plane[2] * vertex.z + plane[3] * vertex.w
+        };
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (index, &(clip, camera)) in polygon.iter().enumerate() {
+            let (next_clip, next_camera) = polygon[(index + 1) % polygon.len()];
+            let (current_distance, next_distance) = (distance(clip), distance(next_clip));
+            if current_distance >= 0.0 {
+                clipped.push((clip, camera));
+            }
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                clipped.push((lerp(clip, next_clip, t), lerp(camera, next_camera, t)));
+            }
+        }
+        polygon = clipped;
+        if polygon.is_empty() {
+            return Vec::new();
+        }
+    }
+
+    (1..polygon.len() - 1)
+        .map(|index| Triangle4f {
+            a: polygon[0].1,
+            b: polygon[index].1,
+            c: polygon[index + 1].1,
+            color: triangle.color,
+            normals: triangle.normals,
+        })
+        .collect()
+}
+
 fn clip_triangles(triangles: Vec<Triangle4f>, clipping_planes: &Vec<Plane>) -> Vec<Triangle4f> {
     let mut clipped_triangles = triangles.clone();
 
@@ -398,13 +821,13 @@ fn is_vertex_inside(plane: &Plane, vertex: Vector4f) -> bool {
 fn convert_face_to_triangles(
     triangle: &Triangle,
     vertices: &Vec<Vector4f>,
-    combined_rotation_transform: Matrix44f,
+    combined_normal_transform: Matrix44f,
     color: Color,
 ) -> Vec<Triangle4f> {
     let transformed_normals = [
-        Vector4f::from(triangle.normals[0]).transform(combined_rotation_transform).into(),
-        Vector4f::from(triangle.normals[1]).transform(combined_rotation_transform).into(),
-        Vector4f::from(triangle.normals[2]).transform(combined_rotation_transform).into(),
+        Vector4f::from(triangle.normals[0]).transform(combined_normal_transform).into(),
+        Vector4f::from(triangle.normals[1]).transform(combined_normal_transform).into(),
+        Vector4f::from(triangle.normals[2]).transform(combined_normal_transform).into(),
     ];
 
     vec![Triangle4f {
@@ -416,43 +839,7 @@ fn convert_face_to_triangles(
     }]
 }
 
-fn render_filled_triangle(
-    triangle: Triangle4f,
-    texture: Option<&Texture>,
-    uvs: Option<[UV; 3]>,
-    camera: &ProjectiveCamera,
-    canvas: &mut BufferCanvas,
-    lights: &Vec<Light>,
-    rendering_settings: &RenderingSettings,
-) {
-    match texture {
-        None => match rendering_settings.shading_model {
-            ShadingModel::Flat => flat_shaded_triangle(triangle, camera, lights, canvas),
-            ShadingModel::Gouraud => gouraud_shaded_triangle(triangle, camera, lights, canvas),
-            ShadingModel::Phong => phong_shaded_triangle(triangle, camera, lights, canvas),
-        },
-        Some(texture) => match rendering_settings.shading_model {
-            ShadingModel::Flat => flat_shaded_triangle(triangle, camera, lights, canvas),
-            ShadingModel::Gouraud => gouraud_shaded_triangle(triangle, camera, lights, canvas),
-            ShadingModel::Phong => {
-                textured_phong_shaded_triangle(triangle, texture, uvs.unwrap(), camera, lights, canvas)
-            }
-        },
-    }
-
-    if rendering_settings.show_normals {
-        draw_normal_to_vertex(triangle.a, triangle.normals[0], camera, canvas);
-        draw_normal_to_vertex(triangle.b, triangle.normals[1], camera, canvas);
-        draw_normal_to_vertex(triangle.c, triangle.normals[2], camera, canvas);
-    }
-}
-
-fn draw_normal_to_vertex(
-    vertex: Vector4f,
-    normal: Vector3f,
-    camera: &ProjectiveCamera,
-    canvas: &mut BufferCanvas,
-) {
+fn draw_normal_to_vertex(vertex: Vector4f, normal: Vector3f, camera: &dyn Camera, canvas: &mut BufferCanvas) {
     let start = vertex_to_canvas_point(vertex, camera, canvas);
     let end = vertex_to_canvas_point(vectors::sum(vertex.into(), normal).into(), camera, canvas);
 
@@ -462,806 +849,32 @@ fn draw_normal_to_vertex(
 }
 
 fn is_point_in_canvas(point: Point, canvas: &BufferCanvas) -> bool {
-    let canvas_half_size = (canvas.size / 2) as i32;
-    let min_x = -canvas_half_size;
-    let max_x = canvas_half_size;
-    let min_y = -canvas_half_size;
-    let max_y = canvas_half_size;
+    let canvas_half_width = (canvas.width / 2) as i32;
+    let canvas_half_height = (canvas.height / 2) as i32;
+    let min_x = -canvas_half_width;
+    let max_x = canvas_half_width;
+    let min_y = -canvas_half_height;
+    let max_y = canvas_half_height;
 
     point.x >= min_x && point.x <= max_x && point.y >= min_y && point.y <= max_y
 }
 
-fn compute_illumination(
-    vertex: Vector3f,
-    normal_direction: Vector3f,
-    camera: &ProjectiveCamera,
-    lights: &Vec<Light>,
-) -> f64 {
-    let mut result = 0.0;
-    let normal = vectors::normalize(normal_direction);
-
-    for light in lights {
-        result += match *light {
-            Light::Ambient { intensity } => intensity,
-            Light::Point { intensity, position } => {
-                let direction = vectors::difference(position, vertex);
-                light_from_direction(vertex, normal, direction, intensity)
-            }
-            Light::Directional { intensity, direction } => {
-                light_from_direction(vertex, normal, direction, intensity)
-            }
-        }
-    }
-    result
-}
-
-fn light_from_direction(
-    vertex: Vector3f,
-    normal: Vector3f,
-    light_direction: Vector3f,
-    light_intensity: f64,
-) -> f64 {
-    let mut result = 0.0;
-    let shininess = 50;
-
-    // diffuse
-    let dot = vectors::dot_product(normal, light_direction);
-    if dot > 0.0 {
-        // assuming that normal is a unit vector (has length 1)
-        result += light_intensity * dot / vectors::length(light_direction);
-    }
-
-    // specular
-    // TODO add color of the light to this component
-    if shininess > 0 {
-        let view = vectors::negate(vertex);
-        let reflection_direction = vectors::reflect(light_direction, normal);
-        let reflection_dot_view = vectors::dot_product(reflection_direction, view);
-        if reflection_dot_view > 0.0 {
-            result += light_intensity
-                * (reflection_dot_view / (vectors::length(reflection_direction) * vectors::length(view)))
-                    .powi(shininess)
-        }
-    }
-
-    result
-}
-
-fn render_wireframe_triangle(triangle: Triangle4f, camera: &ProjectiveCamera, canvas: &mut BufferCanvas) {
-    let a = vertex_to_canvas_point(triangle.a, camera, canvas);
-    let b = vertex_to_canvas_point(triangle.b, camera, canvas);
-    let c = vertex_to_canvas_point(triangle.c, camera, canvas);
-
-    canvas.draw_line(a, b, Color { r: 255, g: 255, b: 255 });
-    canvas.draw_line(b, c, Color { r: 255, g: 255, b: 255 });
-    canvas.draw_line(c, a, Color { r: 255, g: 255, b: 255 });
-}
-
-fn flat_shaded_triangle(
-    triangle: Triangle4f,
-    camera: &ProjectiveCamera,
-    lights: &Vec<Light>,
-    canvas: &mut BufferCanvas,
-) {
-    let mut p0 = vertex_to_canvas_point(triangle.a, camera, canvas);
-    let mut p1 = vertex_to_canvas_point(triangle.b, camera, canvas);
-    let mut p2 = vertex_to_canvas_point(triangle.c, camera, canvas);
-
-    let center = Vector3f {
-        x: (triangle.a.x + triangle.b.x + triangle.c.x) / 3.0,
-        y: (triangle.a.y + triangle.b.y + triangle.c.y) / 3.0,
-        z: (triangle.a.z + triangle.b.z + triangle.c.z) / 3.0,
-    };
-    let intensity = compute_illumination(center, triangle.normals[0], camera, lights);
-
-    // sort points from bottom to top
-    if p1.y < p0.y {
-        let swap = p0;
-        p0 = p1;
-        p1 = swap;
-    }
-    if p2.y < p0.y {
-        let swap = p0;
-        p0 = p2;
-        p2 = swap;
-    }
-    if p2.y < p1.y {
-        let swap = p1;
-        p1 = p2;
-        p2 = swap;
-    }
-
-    // x coordinates of the edges
-    let mut x01 = interpolate_int(p0.y, p0.x, p1.y, p1.x);
-    let mut h01 = interpolate_float(p0.y, p0.h, p1.y, p1.h);
-    let mut iz01 = interpolate_float(p0.y, 1.0 / p0.z, p1.y, 1.0 / p1.z);
-
-    let mut x12 = interpolate_int(p1.y, p1.x, p2.y, p2.x);
-    let mut h12 = interpolate_float(p1.y, p1.h, p2.y, p2.h);
-    let mut iz12 = interpolate_float(p1.y, 1.0 / p1.z, p2.y, 1.0 / p2.z);
-
-    let mut x02 = interpolate_int(p0.y, p0.x, p2.y, p2.x);
-    let mut h02 = interpolate_float(p0.y, p0.h, p2.y, p2.h);
-    let mut iz02 = interpolate_float(p0.y, 1.0 / p0.z, p2.y, 1.0 / p2.z);
-
-    x01.pop();
-    let mut x012 = Vec::<i32>::new();
-    x012.append(&mut x01);
-    x012.append(&mut x12);
-
-    h01.pop();
-    let mut h012 = Vec::<f64>::new();
-    h012.append(&mut h01);
-    h012.append(&mut h12);
-
-    iz01.pop();
-    let mut iz012 = Vec::<f64>::new();
-    iz012.append(&mut iz01);
-    iz012.append(&mut iz12);
-
-    let mut x_left;
-    let mut x_right;
-    let mut h_left;
-    let mut h_right;
-    let mut iz_left;
-    let mut iz_right;
-
-    let m = x02.len() / 2;
-    if x02[m] < x012[m] {
-        x_left = x02;
-        x_right = x012;
-
-        h_left = h02;
-        h_right = h012;
-
-        iz_left = iz02;
-        iz_right = iz012;
-    } else {
-        x_left = x012;
-        x_right = x02;
-
-        h_left = h012;
-        h_right = h02;
-
-        iz_left = iz012;
-        iz_right = iz02;
-    };
-
-    for y in p0.y..(p2.y + 1) {
-        let screen_y = canvas.screen_y(y);
-        let y_index = (y - p0.y) as usize;
-        let x_l = x_left[y_index];
-        let x_r = x_right[y_index];
-        let h_segment = interpolate_float(x_l, h_left[y_index], x_r, h_right[y_index]);
-        let iz_segment = interpolate_float(x_l, iz_left[y_index], x_r, iz_right[y_index]);
-        for x in x_l..(x_r + 1) {
-            let x_index = (x - x_l) as usize;
-            let iz = iz_segment[x_index];
-            let screen_x = canvas.screen_x(x);
-
-            if canvas.update_depth_buffer_if_closer(screen_x, screen_y, iz) {
-                let shaded_color = common::multiply_color(intensity, triangle.color);
-                canvas.put_pixel(Pixel { x: screen_x, y: screen_y, color: shaded_color });
-            }
-        }
-    }
-}
-
-fn gouraud_shaded_triangle(
-    triangle: Triangle4f,
-    camera: &ProjectiveCamera,
-    lights: &Vec<Light>,
-    canvas: &mut BufferCanvas,
-) {
-    let mut v0 = triangle.a.into();
-    let mut v1 = triangle.b.into();
-    let mut v2 = triangle.c.into();
-
-    let mut normal0 = triangle.normals[0];
-    let mut normal1 = triangle.normals[1];
-    let mut normal2 = triangle.normals[2];
-
-    let mut p0 = vertex_to_canvas_point(triangle.a, camera, canvas);
-    let mut p1 = vertex_to_canvas_point(triangle.b, camera, canvas);
-    let mut p2 = vertex_to_canvas_point(triangle.c, camera, canvas);
-
-    // sort points from bottom to top
-    if p1.y < p0.y {
-        let swap = p0;
-        p0 = p1;
-        p1 = swap;
-
-        let swap = v0;
-        v0 = v1;
-        v1 = swap;
-
-        let swap = normal0;
-        normal0 = normal1;
-        normal1 = swap;
-    }
-    if p2.y < p0.y {
-        let swap = p0;
-        p0 = p2;
-        p2 = swap;
-
-        let swap = v0;
-        v0 = v2;
-        v2 = swap;
-
-        let swap = normal0;
-        normal0 = normal2;
-        normal2 = swap;
-    }
-    if p2.y < p1.y {
-        let swap = p1;
-        p1 = p2;
-        p2 = swap;
-
-        let swap = v1;
-        v1 = v2;
-        v2 = swap;
-
-        let swap = normal1;
-        normal1 = normal2;
-        normal2 = swap;
-    }
-
-    let i0 = compute_illumination(v0, normal0, camera, lights);
-    let i1 = compute_illumination(v1, normal1, camera, lights);
-    let i2 = compute_illumination(v2, normal2, camera, lights);
-
-    //interpolating attributes along edges
-    let mut x01 = interpolate_int(p0.y, p0.x, p1.y, p1.x);
-    let mut h01 = interpolate_float(p0.y, p0.h, p1.y, p1.h);
-    let mut iz01 = interpolate_float(p0.y, 1.0 / p0.z, p1.y, 1.0 / p1.z);
-    let mut i01 = interpolate_float(p0.y, i0, p1.y, i1);
-
-    let mut x12 = interpolate_int(p1.y, p1.x, p2.y, p2.x);
-    let mut h12 = interpolate_float(p1.y, p1.h, p2.y, p2.h);
-    let mut iz12 = interpolate_float(p1.y, 1.0 / p1.z, p2.y, 1.0 / p2.z);
-    let mut i12 = interpolate_float(p1.y, i1, p2.y, i2);
-
-    let mut x02 = interpolate_int(p0.y, p0.x, p2.y, p2.x);
-    let mut h02 = interpolate_float(p0.y, p0.h, p2.y, p2.h);
-    let mut iz02 = interpolate_float(p0.y, 1.0 / p0.z, p2.y, 1.0 / p2.z);
-    let mut i02 = interpolate_float(p0.y, i0, p2.y, i2);
-
-    // combining 3 edges to left and right boundaries
-    x01.pop();
-    let mut x012 = Vec::<i32>::new();
-    x012.append(&mut x01);
-    x012.append(&mut x12);
-
-    h01.pop();
-    let mut h012 = Vec::<f64>::new();
-    h012.append(&mut h01);
-    h012.append(&mut h12);
-
-    iz01.pop();
-    let mut iz012 = Vec::<f64>::new();
-    iz012.append(&mut iz01);
-    iz012.append(&mut iz12);
-
-    i01.pop();
-    let mut i012 = Vec::<f64>::new();
-    i012.append(&mut i01);
-    i012.append(&mut i12);
-
-    let mut x_left;
-    let mut x_right;
-    let mut h_left;
-    let mut h_right;
-    let mut iz_left;
-    let mut iz_right;
-    let mut i_left;
-    let mut i_right;
-
-    let m = x02.len() / 2;
-    if x02[m] < x012[m] {
-        x_left = x02;
-        x_right = x012;
-
-        h_left = h02;
-        h_right = h012;
-
-        iz_left = iz02;
-        iz_right = iz012;
-
-        i_left = i02;
-        i_right = i012;
-    } else {
-        x_left = x012;
-        x_right = x02;
-
-        h_left = h012;
-        h_right = h02;
-
-        iz_left = iz012;
-        iz_right = iz02;
-
-        i_left = i012;
-        i_right = i02;
-    };
-
-    for y in p0.y..(p2.y + 1) {
-        let screen_y = canvas.screen_y(y);
-        let y_index = (y - p0.y) as usize;
-        let x_l = x_left[y_index];
-        let x_r = x_right[y_index];
-        let h_segment = interpolate_float(x_l, h_left[y_index], x_r, h_right[y_index]);
-        let iz_segment = interpolate_float(x_l, iz_left[y_index], x_r, iz_right[y_index]);
-        let i_segment = interpolate_float(x_l, i_left[y_index], x_r, i_right[y_index]);
-        for x in x_l..(x_r + 1) {
-            let screen_x = canvas.screen_x(x);
-            let x_index = (x - x_l) as usize;
-            let iz = iz_segment[x_index];
-
-            if canvas.update_depth_buffer_if_closer(screen_x, screen_y, iz) {
-                let shaded_color = common::multiply_color(i_segment[x_index], triangle.color);
-                canvas.put_pixel(Pixel { x: screen_x, y: screen_y, color: shaded_color });
-            }
-        }
-    }
-}
-
-fn phong_shaded_triangle(
-    triangle: Triangle4f,
-    camera: &ProjectiveCamera,
-    lights: &Vec<Light>,
-    canvas: &mut BufferCanvas,
-) {
-    let mut v0: Vector3f = triangle.a.into();
-    let mut v1: Vector3f = triangle.b.into();
-    let mut v2: Vector3f = triangle.c.into();
-
-    let mut normal0 = triangle.normals[0];
-    let mut normal1 = triangle.normals[1];
-    let mut normal2 = triangle.normals[2];
-
-    let mut p0 = vertex_to_canvas_point(triangle.a, camera, canvas);
-    let mut p1 = vertex_to_canvas_point(triangle.b, camera, canvas);
-    let mut p2 = vertex_to_canvas_point(triangle.c, camera, canvas);
-
-    // sort points from bottom to top
-    if p1.y < p0.y {
-        let swap = p0;
-        p0 = p1;
-        p1 = swap;
-
-        let swap = v0;
-        v0 = v1;
-        v1 = swap;
-
-        let swap = normal0;
-        normal0 = normal1;
-        normal1 = swap;
-    }
-    if p2.y < p0.y {
-        let swap = p0;
-        p0 = p2;
-        p2 = swap;
-
-        let swap = v0;
-        v0 = v2;
-        v2 = swap;
-
-        let swap = normal0;
-        normal0 = normal2;
-        normal2 = swap;
-    }
-    if p2.y < p1.y {
-        let swap = p1;
-        p1 = p2;
-        p2 = swap;
-
-        let swap = v1;
-        v1 = v2;
-        v2 = swap;
-
-        let swap = normal1;
-        normal1 = normal2;
-        normal2 = swap;
-    }
-
-    //interpolating attributes along edges
-    let mut x01 = interpolate_int(p0.y, p0.x, p1.y, p1.x);
-    let mut h01 = interpolate_float(p0.y, p0.h, p1.y, p1.h);
-    let mut iz01 = interpolate_float(p0.y, 1.0 / p0.z, p1.y, 1.0 / p1.z);
-    let mut normal_x_01 = interpolate_float(p0.y, normal0.x, p1.y, normal1.x);
-    let mut normal_y_01 = interpolate_float(p0.y, normal0.y, p1.y, normal1.y);
-    let mut normal_z_01 = interpolate_float(p0.y, normal0.z, p1.y, normal1.z);
-
-    let mut x12 = interpolate_int(p1.y, p1.x, p2.y, p2.x);
-    let mut h12 = interpolate_float(p1.y, p1.h, p2.y, p2.h);
-    let mut iz12 = interpolate_float(p1.y, 1.0 / p1.z, p2.y, 1.0 / p2.z);
-    let mut normal_x_12 = interpolate_float(p1.y, normal1.x, p2.y, normal2.x);
-    let mut normal_y_12 = interpolate_float(p1.y, normal1.y, p2.y, normal2.y);
-    let mut normal_z_12 = interpolate_float(p1.y, normal1.z, p2.y, normal2.z);
-
-    let mut x02 = interpolate_int(p0.y, p0.x, p2.y, p2.x);
-    let mut h02 = interpolate_float(p0.y, p0.h, p2.y, p2.h);
-    let mut iz02 = interpolate_float(p0.y, 1.0 / p0.z, p2.y, 1.0 / p2.z);
-    let mut normal_x_02 = interpolate_float(p0.y, normal0.x, p2.y, normal2.x);
-    let mut normal_y_02 = interpolate_float(p0.y, normal0.y, p2.y, normal2.y);
-    let mut normal_z_02 = interpolate_float(p0.y, normal0.z, p2.y, normal2.z);
-
-    // combining 3 edges to left and right boundaries
-    x01.pop();
-    let mut x012 = Vec::<i32>::new();
-    x012.append(&mut x01);
-    x012.append(&mut x12);
-
-    h01.pop();
-    let mut h012 = Vec::<f64>::new();
-    h012.append(&mut h01);
-    h012.append(&mut h12);
-
-    iz01.pop();
-    let mut iz012 = Vec::<f64>::new();
-    iz012.append(&mut iz01);
-    iz012.append(&mut iz12);
-
-    normal_x_01.pop();
-    let mut normal_x_012 = Vec::<f64>::with_capacity(normal_x_01.len() + normal_x_12.len());
-    normal_x_012.append(&mut normal_x_01);
-    normal_x_012.append(&mut normal_x_12);
-
-    normal_y_01.pop();
-    let mut normal_y_012 = Vec::<f64>::with_capacity(normal_y_01.len() + normal_y_12.len());
-    normal_y_012.append(&mut normal_y_01);
-    normal_y_012.append(&mut normal_y_12);
-
-    normal_z_01.pop();
-    let mut normal_z_012 = Vec::<f64>::with_capacity(normal_z_01.len() + normal_z_12.len());
-    normal_z_012.append(&mut normal_z_01);
-    normal_z_012.append(&mut normal_z_12);
-
-    let x_left;
-    let x_right;
-    let h_left;
-    let h_right;
-    let iz_left;
-    let iz_right;
-    let normal_x_left;
-    let normal_x_right;
-    let normal_y_left;
-    let normal_y_right;
-    let normal_z_left;
-    let normal_z_right;
-
-    let m = x02.len() / 2;
-    if x02[m] < x012[m] {
-        x_left = x02;
-        x_right = x012;
-
-        h_left = h02;
-        h_right = h012;
-
-        iz_left = iz02;
-        iz_right = iz012;
-
-        normal_x_left = normal_x_02;
-        normal_x_right = normal_x_012;
-        normal_y_left = normal_y_02;
-        normal_y_right = normal_y_012;
-        normal_z_left = normal_z_02;
-        normal_z_right = normal_z_012;
-    } else {
-        x_left = x012;
-        x_right = x02;
-
-        h_left = h012;
-        h_right = h02;
-
-        iz_left = iz012;
-        iz_right = iz02;
-
-        normal_x_left = normal_x_012;
-        normal_x_right = normal_x_02;
-        normal_y_left = normal_y_012;
-        normal_y_right = normal_y_02;
-        normal_z_left = normal_z_012;
-        normal_z_right = normal_z_02;
-    };
-
-    for y in p0.y..(p2.y + 1) {
-        let screen_y = canvas.screen_y(y);
-        let y_index = (y - p0.y) as usize;
-        let x_l = x_left[y_index];
-        let x_r = x_right[y_index];
-        let h_segment = interpolate_float(x_l, h_left[y_index], x_r, h_right[y_index]);
-        let iz_segment = interpolate_float(x_l, iz_left[y_index], x_r, iz_right[y_index]);
-        let normal_x_segment = interpolate_float(x_l, normal_x_left[y_index], x_r, normal_x_right[y_index]);
-        let normal_y_segment = interpolate_float(x_l, normal_y_left[y_index], x_r, normal_y_right[y_index]);
-        let normal_z_segment = interpolate_float(x_l, normal_z_left[y_index], x_r, normal_z_right[y_index]);
-        for x in x_l..(x_r + 1) {
-            let screen_x = canvas.screen_x(x);
-            let x_index = (x - x_l) as usize;
-            let iz = iz_segment[x_index];
-
-            if canvas.update_depth_buffer_if_closer(screen_x, screen_y, iz) {
-                let vertex = unproject_vertex(x, y, iz, canvas, camera);
-                let normal = Vector3f {
-                    x: normal_x_segment[x_index],
-                    y: normal_y_segment[x_index],
-                    z: normal_z_segment[x_index],
-                };
-                let intensity = compute_illumination(vertex, normal, camera, lights);
-
-                let shaded_color = common::multiply_color(intensity, triangle.color);
-                canvas.put_pixel(Pixel { x: screen_x, y: screen_y, color: shaded_color });
-            }
-        }
-    }
-}
-
-fn textured_phong_shaded_triangle(
-    triangle: Triangle4f,
-    texture: &Texture,
-    uvs: [UV; 3],
-    camera: &ProjectiveCamera,
-    lights: &Vec<Light>,
-    canvas: &mut BufferCanvas,
-) {
-    let mut v0: Vector3f = triangle.a.into();
-    let mut v1: Vector3f = triangle.b.into();
-    let mut v2: Vector3f = triangle.c.into();
-
-    let mut normal0 = triangle.normals[0];
-    let mut normal1 = triangle.normals[1];
-    let mut normal2 = triangle.normals[2];
-
-    let mut p0 = vertex_to_canvas_point(triangle.a, camera, canvas);
-    let mut p1 = vertex_to_canvas_point(triangle.b, camera, canvas);
-    let mut p2 = vertex_to_canvas_point(triangle.c, camera, canvas);
-
-    let mut uv0 = uvs[0];
-    let mut uv1 = uvs[1];
-    let mut uv2 = uvs[2];
-
-    // sort points from bottom to top
-    if p1.y < p0.y {
-        let swap = p0;
-        p0 = p1;
-        p1 = swap;
-
-        let swap = v0;
-        v0 = v1;
-        v1 = swap;
-
-        let swap = normal0;
-        normal0 = normal1;
-        normal1 = swap;
-
-        let swap = uv0;
-        uv0 = uv1;
-        uv1 = swap;
-    }
-    if p2.y < p0.y {
-        let swap = p0;
-        p0 = p2;
-        p2 = swap;
-
-        let swap = v0;
-        v0 = v2;
-        v2 = swap;
-
-        let swap = normal0;
-        normal0 = normal2;
-        normal2 = swap;
-
-        let swap = uv0;
-        uv0 = uv2;
-        uv2 = swap;
-    }
-    if p2.y < p1.y {
-        let swap = p1;
-        p1 = p2;
-        p2 = swap;
-
-        let swap = v1;
-        v1 = v2;
-        v2 = swap;
-
-        let swap = normal1;
-        normal1 = normal2;
-        normal2 = swap;
-
-        let swap = uv1;
-        uv1 = uv2;
-        uv2 = swap;
-    }
-
-    //interpolating attributes along edges
-    let mut x01 = interpolate_int(p0.y, p0.x, p1.y, p1.x);
-    let mut h01 = interpolate_float(p0.y, p0.h, p1.y, p1.h);
-    let mut iz01 = interpolate_float(p0.y, 1.0 / p0.z, p1.y, 1.0 / p1.z);
-    let mut uz01 = interpolate_float(p0.y, uv0.u / p0.z, p1.y, uv1.u / p1.z);
-    let mut vz01 = interpolate_float(p0.y, uv0.v / p0.z, p1.y, uv1.v / p1.z);
-    let mut normal_x_01 = interpolate_float(p0.y, normal0.x, p1.y, normal1.x);
-    let mut normal_y_01 = interpolate_float(p0.y, normal0.y, p1.y, normal1.y);
-    let mut normal_z_01 = interpolate_float(p0.y, normal0.z, p1.y, normal1.z);
-
-    let mut x12 = interpolate_int(p1.y, p1.x, p2.y, p2.x);
-    let mut h12 = interpolate_float(p1.y, p1.h, p2.y, p2.h);
-    let mut iz12 = interpolate_float(p1.y, 1.0 / p1.z, p2.y, 1.0 / p2.z);
-    let mut uz12 = interpolate_float(p1.y, uv1.u / p1.z, p2.y, uv2.u / p2.z);
-    let mut vz12 = interpolate_float(p1.y, uv1.v / p1.z, p2.y, uv2.v / p2.z);
-    let mut normal_x_12 = interpolate_float(p1.y, normal1.x, p2.y, normal2.x);
-    let mut normal_y_12 = interpolate_float(p1.y, normal1.y, p2.y, normal2.y);
-    let mut normal_z_12 = interpolate_float(p1.y, normal1.z, p2.y, normal2.z);
-
-    let mut x02 = interpolate_int(p0.y, p0.x, p2.y, p2.x);
-    let mut h02 = interpolate_float(p0.y, p0.h, p2.y, p2.h);
-    let mut iz02 = interpolate_float(p0.y, 1.0 / p0.z, p2.y, 1.0 / p2.z);
-    let mut uz02 = interpolate_float(p0.y, uv0.u / p0.z, p2.y, uv2.u / p2.z);
-    let mut vz02 = interpolate_float(p0.y, uv0.v / p0.z, p2.y, uv2.v / p2.z);
-    let mut normal_x_02 = interpolate_float(p0.y, normal0.x, p2.y, normal2.x);
-    let mut normal_y_02 = interpolate_float(p0.y, normal0.y, p2.y, normal2.y);
-    let mut normal_z_02 = interpolate_float(p0.y, normal0.z, p2.y, normal2.z);
-
-    // combining 3 edges to left and right boundaries
-    x01.pop();
-    let mut x012 = Vec::<i32>::new();
-    x012.append(&mut x01);
-    x012.append(&mut x12);
-
-    h01.pop();
-    let mut h012 = Vec::<f64>::new();
-    h012.append(&mut h01);
-    h012.append(&mut h12);
-
-    iz01.pop();
-    let mut iz012 = Vec::<f64>::new();
-    iz012.append(&mut iz01);
-    iz012.append(&mut iz12);
-
-    uz01.pop();
-    let mut uz012 = Vec::<f64>::new();
-    uz012.append(&mut uz01);
-    uz012.append(&mut uz12);
-
-    vz01.pop();
-    let mut vz012 = Vec::<f64>::new();
-    vz012.append(&mut vz01);
-    vz012.append(&mut vz12);
-
-    normal_x_01.pop();
-    let mut normal_x_012 = Vec::<f64>::with_capacity(normal_x_01.len() + normal_x_12.len());
-    normal_x_012.append(&mut normal_x_01);
-    normal_x_012.append(&mut normal_x_12);
-
-    normal_y_01.pop();
-    let mut normal_y_012 = Vec::<f64>::with_capacity(normal_y_01.len() + normal_y_12.len());
-    normal_y_012.append(&mut normal_y_01);
-    normal_y_012.append(&mut normal_y_12);
-
-    normal_z_01.pop();
-    let mut normal_z_012 = Vec::<f64>::with_capacity(normal_z_01.len() + normal_z_12.len());
-    normal_z_012.append(&mut normal_z_01);
-    normal_z_012.append(&mut normal_z_12);
-
-    let x_left;
-    let x_right;
-    let h_left;
-    let h_right;
-    let iz_left;
-    let iz_right;
-    let uz_left;
-    let uz_right;
-    let vz_left;
-    let vz_right;
-    let normal_x_left;
-    let normal_x_right;
-    let normal_y_left;
-    let normal_y_right;
-    let normal_z_left;
-    let normal_z_right;
-
-    let m = x02.len() / 2;
-    if x02[m] < x012[m] {
-        x_left = x02;
-        x_right = x012;
-
-        h_left = h02;
-        h_right = h012;
-
-        iz_left = iz02;
-        iz_right = iz012;
-
-        uz_left = uz02;
-        uz_right = uz012;
-
-        vz_left = vz02;
-        vz_right = vz012;
-
-        normal_x_left = normal_x_02;
-        normal_x_right = normal_x_012;
-        normal_y_left = normal_y_02;
-        normal_y_right = normal_y_012;
-        normal_z_left = normal_z_02;
-        normal_z_right = normal_z_012;
-    } else {
-        x_left = x012;
-        x_right = x02;
-
-        h_left = h012;
-        h_right = h02;
-
-        iz_left = iz012;
-        iz_right = iz02;
-
-        uz_left = uz012;
-        uz_right = uz02;
-
-        vz_left = vz012;
-        vz_right = vz02;
-
-        normal_x_left = normal_x_012;
-        normal_x_right = normal_x_02;
-        normal_y_left = normal_y_012;
-        normal_y_right = normal_y_02;
-        normal_z_left = normal_z_012;
-        normal_z_right = normal_z_02;
-    };
-
-    for y in p0.y..(p2.y + 1) {
-        let screen_y = canvas.screen_y(y);
-        let y_index = (y - p0.y) as usize;
-        let x_l = x_left[y_index];
-        let x_r = x_right[y_index];
-        let h_segment = interpolate_float(x_l, h_left[y_index], x_r, h_right[y_index]);
-        let iz_segment = interpolate_float(x_l, iz_left[y_index], x_r, iz_right[y_index]);
-        let uz_segment = interpolate_float(x_l, uz_left[y_index], x_r, uz_right[y_index]);
-        let vz_segment = interpolate_float(x_l, vz_left[y_index], x_r, vz_right[y_index]);
-        let normal_x_segment = interpolate_float(x_l, normal_x_left[y_index], x_r, normal_x_right[y_index]);
-        let normal_y_segment = interpolate_float(x_l, normal_y_left[y_index], x_r, normal_y_right[y_index]);
-        let normal_z_segment = interpolate_float(x_l, normal_z_left[y_index], x_r, normal_z_right[y_index]);
-        for x in x_l..(x_r + 1) {
-            let screen_x = canvas.screen_x(x);
-            let x_index = (x - x_l) as usize;
-            let iz = iz_segment[x_index];
-
-            if canvas.update_depth_buffer_if_closer(screen_x, screen_y, iz) {
-                let vertex = unproject_vertex(x, y, iz, canvas, camera);
-                let normal = Vector3f {
-                    x: normal_x_segment[x_index],
-                    y: normal_y_segment[x_index],
-                    z: normal_z_segment[x_index],
-                };
-                let intensity = compute_illumination(vertex, normal, camera, lights);
-
-                let u = uz_segment[x_index] / iz;
-                let v = vz_segment[x_index] / iz;
-                let color = texture.get_texel(u, v);
-
-                let shaded_color = common::multiply_color(intensity, color);
-                canvas.put_pixel(Pixel { x: screen_x, y: screen_y, color: shaded_color });
-            }
-        }
-    }
-}
-
 fn unproject_vertex(
     canvas_x: i32,
     canvas_y: i32,
     iz: f64,
-    canvas: &BufferCanvas,
-    camera: &ProjectiveCamera,
+    depth: f64,
+    canvas_height: usize,
+    camera: &dyn Camera,
 ) -> Vector3f {
-    let z = 1.0 / iz;
-
-    let viewport_x = (canvas_x as f64) * camera.viewport_size / (canvas.size as f64);
-    let viewport_y = (canvas_y as f64) * camera.viewport_size / (canvas.size as f64);
+    let viewport_x = (canvas_x as f64) * camera.viewport_size() / (canvas_height as f64);
+    let viewport_y = (canvas_y as f64) * camera.viewport_size() / (canvas_height as f64);
 
-    let unprojected_x = viewport_x * z / camera.projection_plane_z;
-    let unprojected_y = viewport_y * z / camera.projection_plane_z;
-
-    Vector3f { x: unprojected_x, y: unprojected_y, z }
+    camera.unproject(Point2D { x: viewport_x, y: viewport_y }, 1.0 / iz, depth)
 }
 
-fn vertex_to_canvas_point(vertex: Vector4f, camera: &ProjectiveCamera, canvas: &BufferCanvas) -> Point {
-    let result = canvas.viewport_to_canvas(vertex, camera);
+fn vertex_to_canvas_point(vertex: Vector4f, camera: &dyn Camera, canvas: &BufferCanvas) -> Point {
+    let result = canvas.viewport_to_canvas(camera.project(vertex.into()), camera);
     log::trace!(
         "vertex [{:.2} {:.2} {:.2}] converted to canvas point [{} {}]",
         vertex.x,
@@ -1274,6 +887,7 @@ fn vertex_to_canvas_point(vertex: Vector4f, camera: &ProjectiveCamera, canvas: &
     result
 }
 
+#[cfg(test)]
 fn interpolate_int(i0: i32, d0: i32, i1: i32, d1: i32) -> Vec<i32> {
     if i0 == i1 {
         return vec![d0];
@@ -1290,6 +904,7 @@ fn interpolate_int(i0: i32, d0: i32, i1: i32, d1: i32) -> Vec<i32> {
     results
 }
 
+#[cfg(test)]
 fn interpolate_float(i0: i32, d0: f64, i1: i32, d1: f64) -> Vec<f64> {
     if i0 == i1 {
         return vec![d0];
@@ -1321,3 +936,381 @@ fn test_interpolate_float() {
         println!("result float: {:.2}", result);
     }
 }
+
+#[test]
+fn test_render_scene_does_not_depend_on_thread_count() {
+    let sphere = model::sphere(24);
+    let cubes = model::random_cubes_scene(20, 2.0);
+    let instances = vec![
+        Instance::new(
+            &cubes,
+            Vector3f { x: 0.0, y: -2.0, z: 12.0 },
+            1.0,
+            Vector3f { x: 0.0, y: 30.0, z: 0.0 },
+        ),
+        Instance::new(
+            &sphere,
+            Vector3f { x: 0.5, y: 0.0, z: 5.0 },
+            1.5,
+            Vector3f::zero_vector(),
+        ),
+    ];
+    let lights = vec![
+        Light::Ambient { intensity: 0.2 },
+        Light::Point {
+            intensity: 0.6,
+            position: Vector3f { x: 2.0, y: 1.0, z: 0.0 },
+        },
+    ];
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let settings = RenderingSettings {
+        rendering_mode: RenderingMode::Filled,
+        shading_model: ShadingModel::Phong,
+        show_normals: false,
+        backface_culling: true,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: None,
+        wireframe: WireframeSettings::default(),
+    };
+
+    let render_with_threads = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let mut canvas = BufferCanvas::new(203, 203);
+        pool.install(|| render_scene(&instances, &lights, &camera, &settings, &mut canvas));
+        canvas
+    };
+
+    let single = render_with_threads(1);
+    let multiple = render_with_threads(4);
+    assert!(single.buffer.iter().any(|&channel| channel > 0));
+    assert!(single.buffer == multiple.buffer);
+    assert!(single.depth_buffer == multiple.depth_buffer);
+}
+
+#[test]
+fn test_blended_triangles_do_not_hide_opaque_ones() {
+    use crate::material::AlphaMode;
+
+    let cube = model::cube(2.0);
+    let glass = model::square(
+        4.0,
+        Material::diffuse(Color { r: 0, g: 0, b: 255 })
+            .with_opacity(0.5)
+            .with_alpha_mode(AlphaMode::Blend),
+    );
+    // the glass is drawn first in the scene order, but is closer to the camera
+    let instances = vec![
+        Instance::new(
+            &glass,
+            Vector3f { x: 0.0, y: 0.0, z: 3.0 },
+            1.0,
+            Vector3f::zero_vector(),
+        ),
+        Instance::new(
+            &cube,
+            Vector3f { x: 0.0, y: 0.0, z: 6.0 },
+            1.0,
+            Vector3f::zero_vector(),
+        ),
+    ];
+    let lights = vec![Light::Ambient { intensity: 1.0 }];
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+
+    for transparency in [Transparency::Sorted, Transparency::WeightedBlended] {
+        let settings = RenderingSettings {
+            rendering_mode: RenderingMode::Filled,
+            shading_model: ShadingModel::Flat,
+            show_normals: false,
+            backface_culling: true,
+            transparency,
+            clipping: Clipping::CameraSpace,
+            shadows: None,
+            wireframe: WireframeSettings::default(),
+        };
+        let mut canvas = BufferCanvas::new(100, 100);
+        render_scene(&instances, &lights, &camera, &settings, &mut canvas);
+
+        // the gray cube is seen through the blue glass and stays in the depth buffer
+        let center = 50 * canvas.width + 50;
+        assert_eq!(&canvas.buffer[center * 3..center * 3 + 3], &[60, 68, 204]);
+        assert_eq!(canvas.depth_buffer[center], 1.0 / 5.0);
+        // the glass alone is over the black background, also in the top row
+        let beside = 80 * canvas.width + 10;
+        assert_eq!(&canvas.buffer[beside * 3..beside * 3 + 3], &[0, 0, 128]);
+        assert_eq!(&canvas.buffer[30 * 3..30 * 3 + 3], &[0, 0, 128]);
+    }
+}
+
+#[test]
+fn test_shadows_without_casters() {
+    use crate::material::AlphaMode;
+
+    // blended triangles don't cast shadows, so the directional light has nothing in its map
+    let glass = model::square(
+        4.0,
+        Material::diffuse(Color { r: 0, g: 0, b: 255 })
+            .with_opacity(0.5)
+            .with_alpha_mode(AlphaMode::Blend),
+    );
+    let instances = vec![Instance::new(
+        &glass,
+        Vector3f { x: 0.0, y: 0.0, z: 3.0 },
+        1.0,
+        Vector3f::zero_vector(),
+    )];
+    let lights = vec![Light::Directional {
+        intensity: 1.0,
+        direction: Vector3f { x: 0.0, y: 0.0, z: -1.0 },
+    }];
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let settings = RenderingSettings {
+        rendering_mode: RenderingMode::Filled,
+        shading_model: ShadingModel::Flat,
+        show_normals: false,
+        backface_culling: true,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: Some(ShadowSettings::default()),
+        wireframe: WireframeSettings::default(),
+    };
+    let mut canvas = BufferCanvas::new(100, 100);
+    render_scene(&instances, &lights, &camera, &settings, &mut canvas);
+
+    let center = 50 * canvas.width + 50;
+    assert!(canvas.buffer[center * 3 + 2] > 0);
+}
+
+#[test]
+fn test_wide_canvas_and_far_plane() {
+    let cube = model::cube(1.0);
+    let instances = vec![
+        // beyond the sides of a square viewport, but inside of the wide one
+        Instance::new(
+            &cube,
+            Vector3f { x: 2.5, y: 0.0, z: 5.0 },
+            1.0,
+            Vector3f::zero_vector(),
+        ),
+        // crosses the far plane, its front part is drawn
+        Instance::new(
+            &cube,
+            Vector3f { x: -40.0, y: 0.0, z: 100.0 },
+            20.0,
+            Vector3f::zero_vector(),
+        ),
+        // entirely beyond the far plane
+        Instance::new(
+            &cube,
+            Vector3f { x: 0.0, y: 0.0, z: 150.0 },
+            30.0,
+            Vector3f::zero_vector(),
+        ),
+    ];
+    let lights = vec![Light::Ambient { intensity: 1.0 }];
+    let mut canvas = BufferCanvas::new(200, 100);
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: canvas.aspect_ratio(),
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let settings = RenderingSettings {
+        rendering_mode: RenderingMode::Filled,
+        shading_model: ShadingModel::Flat,
+        show_normals: false,
+        backface_culling: true,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: None,
+        wireframe: WireframeSettings::default(),
+    };
+    render_scene(&instances, &lights, &camera, &settings, &mut canvas);
+
+    let pixel = |x: usize, y: usize| {
+        let offset = (y * canvas.width + x) * 3;
+        &canvas.buffer[offset..offset + 3]
+    };
+    assert_ne!(pixel(150, 50), &[0, 0, 0]);
+    assert_eq!(canvas.depth_buffer[50 * canvas.width + 150], 1.0 / 4.5);
+    assert_ne!(pixel(56, 50), &[0, 0, 0]);
+    assert_eq!(pixel(100, 50), &[0, 0, 0]);
+
+    let clipping_planes = camera.clipping_planes();
+    let in_frustum = |instance| is_instance_in_frustum(instance, camera.camera_transform(), &clipping_planes);
+    assert!(in_frustum(&instances[0]));
+    assert!(in_frustum(&instances[1]));
+    assert!(!in_frustum(&instances[2]));
+}
+
+#[test]
+fn test_clip_space_matches_camera_space() {
+    let cube = model::cube(2.0);
+    let sphere = model::sphere(16);
+    let floor = model::square(40.0, Material::diffuse(Color { r: 0, g: 200, b: 0 }));
+    let instances = vec![
+        Instance::new(
+            &cube,
+            Vector3f { x: -1.5, y: 0.0, z: 7.0 },
+            1.0,
+            Vector3f { x: 20.0, y: 30.0, z: 0.0 },
+        ),
+        Instance::new(
+            &sphere,
+            Vector3f { x: 1.5, y: 0.5, z: 6.0 },
+            1.2,
+            Vector3f::zero_vector(),
+        ),
+        // crosses the near plane and the sides of the frustum
+        Instance::new(
+            &floor,
+            Vector3f { x: 0.0, y: -1.0, z: 10.0 },
+            1.0,
+            Vector3f { x: 90.0, y: 0.0, z: 0.0 },
+        ),
+    ];
+    let lights = vec![
+        Light::Ambient { intensity: 0.2 },
+        Light::Point {
+            intensity: 0.6,
+            position: Vector3f { x: 2.0, y: 1.0, z: 0.0 },
+        },
+    ];
+    let mut canvas = BufferCanvas::new(160, 120);
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 0.5,
+        far_distance: 100.0,
+        aspect_ratio: canvas.aspect_ratio(),
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let render = |clipping: Clipping, canvas: &mut BufferCanvas| {
+        let settings = RenderingSettings {
+            rendering_mode: RenderingMode::Filled,
+            shading_model: ShadingModel::Phong,
+            show_normals: false,
+            backface_culling: false,
+            transparency: Transparency::Sorted,
+            clipping,
+            shadows: None,
+            wireframe: WireframeSettings::default(),
+        };
+        canvas.clear();
+        render_scene(&instances, &lights, &camera, &settings, canvas);
+        (canvas.buffer.clone(), canvas.depth_buffer.clone())
+    };
+
+    let (camera_space, inverse_depths) = render(Clipping::CameraSpace, &mut canvas);
+    let (clip_space, depths) = render(Clipping::ClipSpace, &mut canvas);
+
+    // the same picture, but for rounding on the silhouettes; the borders of the canvas differ more,
+    // as clipping in clip space puts the clipped vertices exactly on them
+    let (width, height) = (canvas.width, canvas.height);
+    let noticeable = (1..height - 1)
+        .flat_map(|y| (1..width - 1).map(move |x| (y * width + x) * 3))
+        .filter(|&offset| {
+            let (a, b) = (&camera_space[offset..offset + 3], &clip_space[offset..offset + 3]);
+            a.iter().zip(b).any(|(&a, &b)| a.abs_diff(b) > 8)
+        })
+        .count();
+    assert!(noticeable < width * height / 200, "{} pixels differ", noticeable);
+
+    // normalized depths are 1 on the near plane and 0 on the far one
+    assert!(depths.iter().all(|&depth| (0.0..=1.0).contains(&depth)));
+    let sphere_center = 49 * width + 110;
+    let normalized = 0.5 / 99.5 * (100.0 * inverse_depths[sphere_center] - 1.0);
+    assert!((depths[sphere_center] - normalized).abs() < 1e-9);
+}
+
+#[test]
+fn test_parallel_cameras() {
+    let cube = model::cube(2.0);
+    // the same size at different distances
+    let instances = vec![
+        Instance::new(
+            &cube,
+            Vector3f { x: -3.0, y: 0.0, z: 5.0 },
+            1.0,
+            Vector3f::zero_vector(),
+        ),
+        Instance::new(
+            &cube,
+            Vector3f { x: 3.0, y: 0.0, z: 20.0 },
+            1.0,
+            Vector3f::zero_vector(),
+        ),
+    ];
+    let lights = vec![Light::Ambient { intensity: 1.0 }];
+    let mut canvas = BufferCanvas::new(200, 100);
+    let orthographic = OrthographicCamera {
+        viewport_size: 5.0,
+        near_distance: 0.0,
+        far_distance: 50.0,
+        aspect_ratio: canvas.aspect_ratio(),
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let render = |camera: &dyn Camera, clipping: Clipping, canvas: &mut BufferCanvas| {
+        let settings = RenderingSettings {
+            rendering_mode: RenderingMode::Filled,
+            shading_model: ShadingModel::Phong,
+            show_normals: false,
+            backface_culling: true,
+            transparency: Transparency::Sorted,
+            clipping,
+            shadows: None,
+            wireframe: WireframeSettings::default(),
+        };
+        canvas.clear();
+        render_scene(&instances, &lights, camera, &settings, canvas);
+        (canvas.buffer.clone(), canvas.depth_buffer.clone())
+    };
+    let covered = |buffer: &[u8], y: usize, columns: std::ops::Range<usize>| {
+        columns.filter(|&x| buffer[(y * 200 + x) * 3..(y * 200 + x) * 3 + 3] != [0, 0, 0]).count()
+    };
+
+    let (camera_space, camera_space_depths) = render(&orthographic, Clipping::CameraSpace, &mut canvas);
+    let (clip_space, clip_space_depths) = render(&orthographic, Clipping::ClipSpace, &mut canvas);
+    assert_eq!(covered(&camera_space, 50, 0..100), 41);
+    assert_eq!(covered(&camera_space, 50, 100..200), 41);
+    assert!(camera_space == clip_space);
+    // normalized depths of the front faces in both modes
+    assert!((camera_space_depths[50 * 200 + 40] - 0.92).abs() < 1e-9);
+    assert!((clip_space_depths[50 * 200 + 160] - 0.62).abs() < 1e-9);
+
+    // the receding top and right faces are seen as well, drawn up and to the right
+    let cabinet = ObliqueCamera::cabinet(orthographic, 5.0);
+    let (oblique, _) = render(&cabinet, Clipping::CameraSpace, &mut canvas);
+    assert!(covered(&oblique, 50, 0..100) > 41);
+    assert_eq!(covered(&camera_space, 26, 0..100), 0);
+    assert!(covered(&oblique, 26, 0..100) > 0);
+}
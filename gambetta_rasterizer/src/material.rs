@@ -0,0 +1,158 @@
+use crate::model::UV;
+use crate::shader::Illumination;
+use crate::texture::{Texture, UvDerivatives};
+use common::Color;
+
+/// How the opacity of a material is used
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    /// Opacity is ignored
+    Opaque,
+    /// Cutout: points with opacity below `cutoff` are discarded, the rest are opaque
+    Mask { cutoff: f64 },
+    /// Blended over whatever is behind, without hiding it in the depth buffer
+    Blend,
+}
+
+/// Surface properties shared by the triangles of a model
+#[derive(Copy, Clone)]
+pub struct Material<'a> {
+    pub diffuse_color: Color,
+    pub specular_color: Color,
+    /// Exponent of the specular highlight; 0 turns the highlights off
+    pub shininess: i32,
+    /// Light emitted by the surface itself, added regardless of the lights
+    pub emissive_color: Color,
+    /// Replaces `diffuse_color` where UVs are available
+    pub diffuse_texture: Option<&'a Texture>,
+    /// Replaces `specular_color` where UVs are available
+    pub specular_texture: Option<&'a Texture>,
+    /// From 0 (invisible) to 1, multiplied by the alpha of the diffuse texture
+    pub opacity: f64,
+    pub alpha_mode: AlphaMode,
+}
+
+impl<'a> Material<'a> {
+    /// Plastic-like surface with highlights of its own color
+    pub fn diffuse(color: Color) -> Self {
+        Self {
+            diffuse_color: color,
+            specular_color: color,
+            shininess: 50,
+            emissive_color: Color { r: 0, g: 0, b: 0 },
+            diffuse_texture: None,
+            specular_texture: None,
+            opacity: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+
+    /// Surface colored by the texture, including its highlights;
+    /// without UVs it takes the average color of the texture
+    pub fn textured(texture: &'a Texture) -> Self {
+        Self {
+            diffuse_texture: Some(texture),
+            specular_texture: Some(texture),
+            ..Self::diffuse(texture.average_color())
+        }
+    }
+
+    pub fn with_specular(self, specular_color: Color, shininess: i32) -> Self {
+        Self { specular_color, shininess, ..self }
+    }
+
+    pub fn with_emissive(self, emissive_color: Color) -> Self {
+        Self { emissive_color, ..self }
+    }
+
+    pub fn with_specular_texture(self, specular_texture: Option<&'a Texture>) -> Self {
+        Self { specular_texture, ..self }
+    }
+
+    pub fn with_opacity(self, opacity: f64) -> Self {
+        Self { opacity, ..self }
+    }
+
+    pub fn with_alpha_mode(self, alpha_mode: AlphaMode) -> Self {
+        Self { alpha_mode, ..self }
+    }
+
+    /// Opacity of a point of the surface; the texture is sampled only when `uv` is given
+    pub fn alpha(&self, uv: Option<UV>, derivatives: UvDerivatives) -> f64 {
+        match (self.diffuse_texture, uv) {
+            (Some(texture), Some(uv)) => self.opacity * texture.sample_alpha(uv, derivatives),
+            _ => self.opacity,
+        }
+    }
+
+    /// Color of a point of the surface; textures are sampled only when `uv` is given,
+    /// with the mip level selected by its screen-space derivatives
+    pub fn shade(&self, illumination: Illumination, uv: Option<UV>, derivatives: UvDerivatives) -> Color {
+        let sample = |texture: Option<&Texture>, color: Color| match (texture, uv) {
+            (Some(texture), Some(uv)) => texture.sample(uv, derivatives),
+            _ => color,
+        };
+        let diffuse = sample(self.diffuse_texture, self.diffuse_color);
+        let specular = sample(self.specular_texture, self.specular_color);
+        let emissive = self.emissive_color;
+
+        let channel = |diffuse: u8, specular: u8, emissive: u8| {
+            let value = illumination.diffuse * diffuse as f64
+                + illumination.specular * specular as f64
+                + emissive as f64;
+            value.clamp(0.0, 255.0) as u8
+        };
+
+        Color {
+            r: channel(diffuse.r, specular.r, emissive.r),
+            g: channel(diffuse.g, specular.g, emissive.g),
+            b: channel(diffuse.b, specular.b, emissive.b),
+        }
+    }
+}
+
+#[test]
+fn test_shade() {
+    let material = Material::diffuse(Color { r: 100, g: 50, b: 0 })
+        .with_specular(Color { r: 0, g: 0, b: 200 }, 10)
+        .with_emissive(Color { r: 10, g: 0, b: 0 });
+
+    let color = material.shade(
+        Illumination { diffuse: 0.5, specular: 0.25 },
+        None,
+        UvDerivatives::default(),
+    );
+    assert_eq!((color.r, color.g, color.b), (60, 25, 50));
+
+    // channels saturate instead of wrapping around
+    let color = material.shade(
+        Illumination { diffuse: 4.0, specular: 4.0 },
+        None,
+        UvDerivatives::default(),
+    );
+    assert_eq!((color.r, color.g, color.b), (255, 200, 255));
+}
+
+#[test]
+fn test_alpha() {
+    let leaves = Texture::from_rgba(1, 1, vec![0, 255, 0, 128]);
+    let material = Material::textured(&leaves).with_opacity(0.5);
+    let uv = UV { u: 0.5, v: 0.5 };
+
+    assert_eq!(material.alpha(None, UvDerivatives::default()), 0.5);
+    assert!((material.alpha(Some(uv), UvDerivatives::default()) - 0.25).abs() < 0.01);
+}
+
+#[test]
+fn test_textured_without_uvs() {
+    let bricks = Texture::from_rgb(2, 1, vec![200, 100, 0, 100, 50, 0]);
+    let material = Material::textured(&bricks).with_specular(Color { r: 0, g: 0, b: 0 }, 0);
+
+    // the texture can't be sampled, so its average color is used instead of white
+    let color = material.shade(
+        Illumination { diffuse: 1.0, specular: 0.0 },
+        None,
+        UvDerivatives::default(),
+    );
+    assert_eq!((color.r, color.g, color.b), (150, 75, 0));
+}
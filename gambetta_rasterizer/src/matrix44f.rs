@@ -1,4 +1,5 @@
 use crate::vector4f::Vector4f;
+use common::Vector3f;
 
 #[derive(Copy, Clone)]
 pub struct Matrix44f {
@@ -28,6 +29,18 @@ impl Matrix44f {
         }
     }
 
+    /// Scale along the x, y and z axes
+    pub fn scale(scale: Vector3f) -> Matrix44f {
+        Matrix44f {
+            elements: [
+                [scale.x, 0.0, 0.0, 0.0],
+                [0.0, scale.y, 0.0, 0.0],
+                [0.0, 0.0, scale.z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
     pub fn rotation_x(degrees: f64) -> Matrix44f {
         let radians = degrees.to_radians();
         let cos = radians.cos();
@@ -73,6 +86,65 @@ impl Matrix44f {
         }
     }
 
+    /// Perspective projection to homogeneous clip space, for row vectors like the other transforms.
+    ///
+    /// The viewport at `projection_plane_z` goes to x and y from -w to w, and w is the z of the point.
+    /// Depth z/w is normalized in reverse: 1 on the near plane and 0 on the far one, so that greater
+    /// is closer, as with the inverse depth 1/z.
+    pub fn perspective(
+        viewport_width: f64,
+        viewport_height: f64,
+        projection_plane_z: f64,
+        near_distance: f64,
+        far_distance: f64,
+    ) -> Matrix44f {
+        let depth_range = far_distance - near_distance;
+
+        Matrix44f {
+            elements: [
+                [2.0 * projection_plane_z / viewport_width, 0.0, 0.0, 0.0],
+                [0.0, 2.0 * projection_plane_z / viewport_height, 0.0, 0.0],
+                [0.0, 0.0, -near_distance / depth_range, 1.0],
+                [0.0, 0.0, near_distance * far_distance / depth_range, 0.0],
+            ],
+        }
+    }
+
+    /// Orthographic projection to homogeneous clip space, for row vectors like the other transforms.
+    ///
+    /// The viewport goes to x and y from -1 to 1 and w stays 1. Depth z is normalized in reverse
+    /// like in `perspective`, but changes linearly with the distance.
+    pub fn orthographic(
+        viewport_width: f64,
+        viewport_height: f64,
+        near_distance: f64,
+        far_distance: f64,
+    ) -> Matrix44f {
+        let depth_range = far_distance - near_distance;
+
+        Matrix44f {
+            elements: [
+                [2.0 / viewport_width, 0.0, 0.0, 0.0],
+                [0.0, 2.0 / viewport_height, 0.0, 0.0],
+                [0.0, 0.0, -1.0 / depth_range, 0.0],
+                [0.0, 0.0, far_distance / depth_range, 1.0],
+            ],
+        }
+    }
+
+    /// Shifts x and y in proportion to the distance from the plane z = `plane_z`,
+    /// by `x_per_z` and `y_per_z` for every unit of it; the plane itself stays in place
+    pub fn oblique_shear(x_per_z: f64, y_per_z: f64, plane_z: f64) -> Matrix44f {
+        Matrix44f {
+            elements: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [x_per_z, y_per_z, 1.0, 0.0],
+                [-plane_z * x_per_z, -plane_z * y_per_z, 0.0, 1.0],
+            ],
+        }
+    }
+
     pub fn multiply(&self, other: Matrix44f) -> Matrix44f {
         let mut elements = [[0.0; 4]; 4];
 
@@ -119,3 +191,42 @@ fn test_transpose() {
         );
     }
 }
+
+#[test]
+fn test_perspective() {
+    let matrix = Matrix44f::perspective(2.0, 1.0, 1.0, 0.5, 10.0);
+    let clip = |x: f64, y: f64, z: f64| {
+        let vertex = Vector4f { x, y, z, w: 1.0 }.transform(matrix);
+        (
+            vertex.x / vertex.w,
+            vertex.y / vertex.w,
+            vertex.z / vertex.w,
+            vertex.w,
+        )
+    };
+
+    // the corner of the viewport
+    assert_eq!(clip(1.0, 0.5, 1.0), (1.0, 1.0, 0.5 * 9.0 / 9.5, 1.0));
+    // the near and the far plane
+    assert_eq!(clip(0.0, 0.0, 0.5).2, 1.0);
+    assert_eq!(clip(0.0, 0.0, 10.0).2, 0.0);
+    assert_eq!(clip(-4.0, 2.0, 4.0), (-1.0, 1.0, 0.5 * 6.0 / (4.0 * 9.5), 4.0));
+}
+
+#[test]
+fn test_orthographic_and_oblique_shear() {
+    let matrix = Matrix44f::orthographic(4.0, 2.0, 1.0, 11.0);
+    let clip = |matrix: Matrix44f, x: f64, y: f64, z: f64| {
+        let vertex = Vector4f { x, y, z, w: 1.0 }.transform(matrix);
+        (vertex.x, vertex.y, vertex.z, vertex.w)
+    };
+
+    // sizes don't depend on the distance, depth is linear in it
+    assert_eq!(clip(matrix, 2.0, -1.0, 1.0), (1.0, -1.0, 1.0, 1.0));
+    assert_eq!(clip(matrix, 2.0, -1.0, 11.0), (1.0, -1.0, 0.0, 1.0));
+    assert_eq!(clip(matrix, 1.0, 0.5, 6.0), (0.5, 0.5, 0.5, 1.0));
+
+    let shear = Matrix44f::oblique_shear(0.5, 0.25, 2.0);
+    assert_eq!(clip(shear, 1.0, 1.0, 2.0), (1.0, 1.0, 2.0, 1.0));
+    assert_eq!(clip(shear, 1.0, 1.0, 6.0), (3.0, 2.0, 6.0, 1.0));
+}
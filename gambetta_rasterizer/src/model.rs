@@ -1,14 +1,21 @@
+use crate::material::Material;
 use crate::texture::Texture;
+use crate::wireframe::EdgeCache;
 use common::vectors;
 use common::{Color, Vector3f};
+use mesh_export::TriangleMesh;
 
 pub struct Model<'a> {
     pub name: &'a str,
     pub vertices: Vec<Vector3f>,
     pub triangles: Vec<Triangle>,
-    pub colors: Vec<Color>,
-    pub textures: Option<Vec<&'a Texture>>,
+    pub materials: Vec<Material<'a>>,
+    /// Index of the material of every triangle
+    pub material_indexes: Vec<usize>,
     pub uvs: Option<Vec<[UV; 3]>>,
+    /// Edges for the wireframe rendering modes, found the first time they are drawn;
+    /// reset it to the default after changing the vertices or the triangles
+    pub edges: EdgeCache,
 }
 
 use std::fmt;
@@ -17,8 +24,8 @@ impl<'a> fmt::Display for Model<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let vertex_count = self.vertices.len();
         let triangle_count = self.triangles.len();
-        let color_count = self.colors.len();
-        let texture_count = self.textures.as_ref().map_or(0, |ts| ts.len());
+        let material_count = self.materials.len();
+        let material_index_count = self.material_indexes.len();
         let uv_count = self.uvs.as_ref().map_or(0, |uvs| uvs.len());
 
         write!(
@@ -26,18 +33,16 @@ impl<'a> fmt::Display for Model<'a> {
             "Model '{}' {{\n\
              \tVertices: {}\n\
              \tTriangles: {}\n\
-             \tColors: {}\n\
-             \tTextures: {}\n\
+             \tMaterials: {}\n\
              \tUVs: {}\n\
              \tConnected: {}",
             self.name,
             vertex_count,
             triangle_count,
-            color_count,
-            texture_count,
+            material_count,
             uv_count,
-            if triangle_count == color_count
-                && (texture_count == 0 || texture_count == triangle_count)
+            if triangle_count == material_index_count
+                && self.material_indexes.iter().all(|&index| index < material_count)
                 && (uv_count == 0 || uv_count == triangle_count)
             {
                 "✅ (consistent)"
@@ -62,6 +67,71 @@ impl<'a> fmt::Display for Model<'a> {
     }
 }
 
+impl Model<'_> {
+    /// Sphere around the center of the bounding box of the vertices that contains all of them
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        if self.vertices.is_empty() {
+            return BoundingSphere { center: Vector3f::zero_vector(), radius: 0.0 };
+        }
+
+        let mut min = Vector3f {
+            x: f64::INFINITY,
+            y: f64::INFINITY,
+            z: f64::INFINITY,
+        };
+        let mut max = Vector3f {
+            x: f64::NEG_INFINITY,
+            y: f64::NEG_INFINITY,
+            z: f64::NEG_INFINITY,
+        };
+        for vertex in &self.vertices {
+            min = Vector3f {
+                x: min.x.min(vertex.x),
+                y: min.y.min(vertex.y),
+                z: min.z.min(vertex.z),
+            };
+            max = Vector3f {
+                x: max.x.max(vertex.x),
+                y: max.y.max(vertex.y),
+                z: max.z.max(vertex.z),
+            };
+        }
+
+        let center = vectors::scale(0.5, vectors::sum(min, max));
+        let radius = self
+            .vertices
+            .iter()
+            .map(|&vertex| vectors::length(vectors::difference(vertex, center)))
+            .fold(0.0, f64::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// Copy of the geometry for the exporters of `mesh_export`, with the normals of the corners
+    /// and the texture coordinates; materials are not exported
+    pub fn to_triangle_mesh(&self) -> TriangleMesh {
+        TriangleMesh {
+            name: self.name.to_string(),
+            positions: self.vertices.clone(),
+            triangles: self
+                .triangles
+                .iter()
+                .enumerate()
+                .map(|(index, triangle)| mesh_export::Triangle {
+                    positions: triangle.indexes,
+                    normals: Some(triangle.normals),
+                    uvs: self.uvs.as_ref().map(|uvs| uvs[index].map(|uv| [uv.u, uv.v])),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct BoundingSphere {
+    pub center: Vector3f,
+    pub radius: f64,
+}
+
 
 #[derive(Copy, Clone)]
 pub struct Triangle {
@@ -124,7 +194,7 @@ fn test_new_with_calculated_normal() {
     );
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct UV {
     pub u: f64,
     pub v: f64,
@@ -175,28 +245,17 @@ pub fn cube<'a>(size: f64) -> Model<'a> {
     //     Color { r: rng.gen(), g: rng.gen(), b: rng.gen() },
     // ];
 
-    let colors = vec![
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-    ];
+    let materials = vec![Material::diffuse(Color { r: 119, g: 136, b: 153 })];
+    let material_indexes = vec![0; triangles.len()];
 
     Model {
         name: "cube",
         vertices,
         triangles,
-        colors,
-        textures: None,
+        materials,
+        material_indexes,
         uvs: None,
+        edges: Default::default(),
     }
 }
 
@@ -229,25 +288,8 @@ pub fn textured_cube<'a>(size: f64, texture: &'a Texture) -> Model<'a> {
         Triangle::new_with_calculated_normals(&vertices, [2, 7, 3]),
     ];
 
-    let colors = vec![
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-        Color { r: 119, g: 136, b: 153 },
-    ];
-
-    let textures = vec![
-        texture, texture, texture, texture, texture, texture, texture, texture, texture, texture, texture,
-        texture,
-    ];
+    let materials = vec![Material::textured(texture)];
+    let material_indexes = vec![0; triangles.len()];
 
     let uvs = vec![
         [UV { u: 0.0, v: 0.0 }, UV { u: 1.0, v: 0.0 }, UV { u: 1.0, v: 1.0 }],
@@ -268,9 +310,42 @@ pub fn textured_cube<'a>(size: f64, texture: &'a Texture) -> Model<'a> {
         name: "cube",
         vertices,
         triangles,
-        colors,
-        textures: Some(textures),
+        materials,
+        material_indexes,
+        uvs: Some(uvs),
+        edges: Default::default(),
+    }
+}
+
+/// Square in the XY plane facing -z, textured with the whole texture of the material if it has one
+pub fn square<'a>(size: f64, material: Material<'a>) -> Model<'a> {
+    let half_size = size / 2.0;
+
+    let vertices = vec![
+        Vector3f { x: -half_size, y: half_size, z: 0.0 },
+        Vector3f { x: half_size, y: half_size, z: 0.0 },
+        Vector3f { x: half_size, y: -half_size, z: 0.0 },
+        Vector3f { x: -half_size, y: -half_size, z: 0.0 },
+    ];
+
+    let triangles = vec![
+        Triangle::new_with_calculated_normals(&vertices, [0, 1, 2]),
+        Triangle::new_with_calculated_normals(&vertices, [0, 2, 3]),
+    ];
+
+    let uvs = vec![
+        [UV { u: 0.0, v: 0.0 }, UV { u: 1.0, v: 0.0 }, UV { u: 1.0, v: 1.0 }],
+        [UV { u: 0.0, v: 0.0 }, UV { u: 1.0, v: 1.0 }, UV { u: 0.0, v: 1.0 }],
+    ];
+
+    Model {
+        name: "square",
+        vertices,
+        triangles,
+        materials: vec![material],
+        material_indexes: vec![0, 0],
         uvs: Some(uvs),
+        edges: Default::default(),
     }
 }
 
@@ -359,16 +434,17 @@ pub fn sphere<'a>(divs: i32) -> Model<'a> {
         ));
     }
 
-    let color = Color { r: 119, g: 136, b: 153 };
-    let colors = vec![color; triangles.len()];
+    let materials = vec![Material::diffuse(Color { r: 119, g: 136, b: 153 })];
+    let material_indexes = vec![0; triangles.len()];
 
     Model {
         name: "sphere",
         vertices,
         triangles,
-        colors,
-        textures: None,
+        materials,
+        material_indexes,
         uvs: None,
+        edges: Default::default(),
     }
 }
 
@@ -414,7 +490,8 @@ pub fn random_cubes_scene<'a>(count: usize, max_size: f64) -> Model<'a> {
     let mut rng = rand::thread_rng();
     let mut all_vertices = Vec::new();
     let mut all_triangles = Vec::new();
-    let mut all_colors = Vec::new();
+    let mut all_materials = Vec::new();
+    let mut all_material_indexes = Vec::new();
 
     let base_cube = cube(1.0);
     let area_size = 20.0;
@@ -482,8 +559,9 @@ pub fn random_cubes_scene<'a>(count: usize, max_size: f64) -> Model<'a> {
                     g: rng.gen_range(50..=255),
                     b: rng.gen_range(50..=255),
                 };
+                all_materials.push(Material::diffuse(color));
                 for _ in 0..base_cube.triangles.len() {
-                    all_colors.push(color);
+                    all_material_indexes.push(all_materials.len() - 1);
                 }
 
                 placed = true;
@@ -508,9 +586,10 @@ pub fn random_cubes_scene<'a>(count: usize, max_size: f64) -> Model<'a> {
         name: "random_cubes_scene",
         vertices: all_vertices,
         triangles: all_triangles,
-        colors: all_colors,
-        textures: None,
+        materials: all_materials,
+        material_indexes: all_material_indexes,
         uvs: None,
+        edges: Default::default(),
     }
 }
 
@@ -0,0 +1,674 @@
+//! Importer of Wavefront OBJ models with their MTL materials.
+//!
+//! Reads positions, texture coordinates and normals, polygons of any size split into triangle
+//! fans, `usemtl` and `mtllib`. Faces without normals are smoothed with the normals of the faces
+//! around their vertices, unless smoothing is turned off with `s off`. Materials take `Kd`, `Ks`,
+//! `Ns`, `Ke`, `d` or `Tr`, `map_Kd` and `map_Ks`; textures are loaded once, relative to the MTL
+//! file. Groups, objects and the rest are skipped. Missing MTL files and textures are only
+//! warned about: the faces keep the default material, and the materials stay untextured.
+//!
+//! Materials borrow their textures, so loading gives an `ObjModel` that owns them,
+//! and `ObjModel::model` the `Model` that refers to it.
+
+use crate::material::{AlphaMode, Material};
+use crate::model::{Model, Triangle, UV};
+use crate::texture::{self, Texture, Wrap};
+use common::{vectors, Color, Vector3f};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Color of faces before any `usemtl`
+const DEFAULT_COLOR: Color = Color { r: 200, g: 200, b: 200 };
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Line of the OBJ or MTL file, counted from 1, that can't be parsed
+    InvalidLine {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// A face refers to a position, texture coordinate or normal that doesn't exist
+    InvalidIndex {
+        path: PathBuf,
+        line: usize,
+        index: i64,
+    },
+    Texture {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "error reading {}: {}", path.display(), error),
+            ObjError::InvalidLine { path, line, reason } => {
+                write!(f, "invalid line {} of {}: {}", line, path.display(), reason)
+            }
+            ObjError::InvalidIndex { path, line, index } => {
+                write!(
+                    f,
+                    "line {} of {} refers to a missing element {}",
+                    line,
+                    path.display(),
+                    index
+                )
+            }
+            ObjError::Texture { path, error } => {
+                write!(f, "error loading texture {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Texture { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Material as the MTL file describes it, with its textures as indexes into `ObjModel::textures`
+#[derive(Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse_color: Color,
+    pub specular_color: Color,
+    pub shininess: i32,
+    pub emissive_color: Color,
+    pub opacity: f64,
+    pub diffuse_texture: Option<usize>,
+    pub specular_texture: Option<usize>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse_color: Color { r: 255, g: 255, b: 255 },
+            specular_color: Color { r: 0, g: 0, b: 0 },
+            shininess: 0,
+            emissive_color: Color { r: 0, g: 0, b: 0 },
+            opacity: 1.0,
+            diffuse_texture: None,
+            specular_texture: None,
+        }
+    }
+}
+
+/// Loaded model with the textures its materials use
+pub struct ObjModel {
+    pub name: String,
+    pub vertices: Vec<Vector3f>,
+    pub triangles: Vec<Triangle>,
+    pub uvs: Option<Vec<[UV; 3]>>,
+    /// Index into `materials` of every triangle
+    pub material_indexes: Vec<usize>,
+    pub materials: Vec<ObjMaterial>,
+    pub textures: Vec<Texture>,
+}
+
+impl ObjModel {
+    pub fn model(&self) -> Model<'_> {
+        let texture = |index: Option<usize>| index.map(|index| &self.textures[index]);
+        let materials = self
+            .materials
+            .iter()
+            .map(|material| {
+                let mut converted = Material::diffuse(material.diffuse_color)
+                    .with_specular(material.specular_color, material.shininess)
+                    .with_emissive(material.emissive_color)
+                    .with_specular_texture(texture(material.specular_texture));
+                converted.diffuse_texture = texture(material.diffuse_texture);
+                if material.opacity < 1.0 {
+                    converted = converted.with_opacity(material.opacity).with_alpha_mode(AlphaMode::Blend);
+                }
+                converted
+            })
+            .collect();
+
+        Model {
+            name: &self.name,
+            vertices: self.vertices.clone(),
+            triangles: self.triangles.clone(),
+            materials,
+            material_indexes: self.material_indexes.clone(),
+            uvs: self.uvs.clone(),
+            edges: Default::default(),
+        }
+    }
+}
+
+/// Corner of a face: indexes of the position, texture coordinate and normal
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Face {
+    corners: [Corner; 3],
+    material: usize,
+    smooth: bool,
+}
+
+/// Loads the model with the materials of its MTL files, named after the file
+pub fn load(path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let mut positions = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut faces = vec![];
+    let mut materials = vec![ObjMaterial {
+        diffuse_color: DEFAULT_COLOR,
+        ..ObjMaterial::new("default")
+    }];
+    let mut textures = vec![];
+    let mut texture_indexes = HashMap::new();
+    let mut material = 0;
+    let mut smooth = true;
+
+    for_each_line(path, |number, words| {
+        let invalid = |reason: &str| ObjError::InvalidLine {
+            path: path.to_path_buf(),
+            line: number,
+            reason: reason.to_string(),
+        };
+        match words {
+            ["v", coordinates @ ..] => {
+                positions.push(vector(coordinates).ok_or_else(|| invalid("invalid position"))?)
+            }
+            ["vn", coordinates @ ..] => {
+                normals.push(vector(coordinates).ok_or_else(|| invalid("invalid normal"))?)
+            }
+            ["vt", u, rest @ ..] => {
+                let u = u.parse().map_err(|_| invalid("invalid texture coordinate"))?;
+                let v = rest
+                    .first()
+                    .map_or(Ok(0.0), |v| v.parse())
+                    .map_err(|_| invalid("invalid texture coordinate"))?;
+                // v goes up in OBJ files, down the rows of textures here
+                uvs.push(UV { u, v: 1.0 - v });
+            }
+            ["f", corners @ ..] => {
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let corners = corners
+                    .iter()
+                    .map(|corner| parse_corner(corner, counts, path, number))
+                    .collect::<Result<Vec<Corner>, ObjError>>()?;
+                for i in 1..corners.len().saturating_sub(1) {
+                    faces.push(Face {
+                        corners: [corners[0], corners[i], corners[i + 1]],
+                        material,
+                        smooth,
+                    });
+                }
+            }
+            ["usemtl", name, ..] => {
+                // the MTL materials come after the built-in default; the last one of a name wins
+                material = match materials[1..].iter().rposition(|material| material.name == *name) {
+                    Some(index) => index + 1,
+                    None => {
+                        log::warn!("{}: material {} is not defined", path.display(), name);
+                        0
+                    }
+                }
+            }
+            ["mtllib", files @ ..] => {
+                for file in files {
+                    let mtl_path = relative_path(path, file);
+                    if !mtl_path.is_file() {
+                        log::warn!(
+                            "{}: material library {} not found",
+                            path.display(),
+                            mtl_path.display()
+                        );
+                        continue;
+                    }
+                    load_materials(&mtl_path, &mut materials, &mut textures, &mut texture_indexes)?;
+                }
+            }
+            ["s", "off"] | ["s", "0"] => smooth = false,
+            ["s", _] => smooth = true,
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    Ok(build_model(
+        name, positions, &uvs, &normals, faces, materials, textures,
+    ))
+}
+
+fn build_model(
+    name: String,
+    vertices: Vec<Vector3f>,
+    uvs: &[UV],
+    normals: &[Vector3f],
+    faces: Vec<Face>,
+    materials: Vec<ObjMaterial>,
+    textures: Vec<Texture>,
+) -> ObjModel {
+    // smooth normals of the vertices, from the faces around them that have no normals
+    let mut vertex_normals = vec![Vector3f::zero_vector(); vertices.len()];
+    for face in faces.iter().filter(|face| face.smooth) {
+        let indexes = face.corners.map(|(position, _, _)| position);
+        let normal = Triangle::new_with_calculated_normals(&vertices, indexes).calculated_normal;
+        if normal.x.is_finite() && normal.y.is_finite() && normal.z.is_finite() {
+            for index in indexes {
+                vertex_normals[index] = vectors::sum(vertex_normals[index], normal);
+            }
+        }
+    }
+
+    let has_uvs = faces.iter().any(|face| face.corners.iter().any(|&(_, uv, _)| uv.is_some()));
+    let mut triangles = Vec::with_capacity(faces.len());
+    let mut triangle_uvs = Vec::with_capacity(if has_uvs { faces.len() } else { 0 });
+    let mut material_indexes = Vec::with_capacity(faces.len());
+    for face in &faces {
+        let indexes = face.corners.map(|(position, _, _)| position);
+        let corner_normals = face.corners.map(|(position, _, normal)| match normal {
+            Some(normal) => Some(normals[normal]),
+            None if face.smooth => {
+                Some(vertex_normals[position]).filter(|normal| vectors::length(*normal) > 1e-12)
+            }
+            None => None,
+        });
+        triangles.push(match corner_normals {
+            [Some(a), Some(b), Some(c)] => Triangle::new_with_provided_normals(&vertices, indexes, [a, b, c]),
+            _ => Triangle::new_with_calculated_normals(&vertices, indexes),
+        });
+        if has_uvs {
+            triangle_uvs.push(face.corners.map(|(_, uv, _)| uv.map_or(UV { u: 0.0, v: 0.0 }, |uv| uvs[uv])));
+        }
+        material_indexes.push(face.material);
+    }
+
+    ObjModel {
+        name,
+        vertices,
+        triangles,
+        uvs: if has_uvs { Some(triangle_uvs) } else { None },
+        material_indexes,
+        materials,
+        textures,
+    }
+}
+
+fn load_materials(
+    path: &Path,
+    materials: &mut Vec<ObjMaterial>,
+    textures: &mut Vec<Texture>,
+    texture_indexes: &mut HashMap<PathBuf, usize>,
+) -> Result<(), ObjError> {
+    let mut current: Option<usize> = None;
+    for_each_line(path, |number, words| {
+        let invalid = |reason: &str| ObjError::InvalidLine {
+            path: path.to_path_buf(),
+            line: number,
+            reason: reason.to_string(),
+        };
+        if let ["newmtl", name, ..] = words {
+            materials.push(ObjMaterial::new(name));
+            current = Some(materials.len() - 1);
+            return Ok(());
+        }
+        let Some(material) = current.map(|index| &mut materials[index]) else {
+            return match words {
+                [] => Ok(()),
+                _ => Err(invalid("material property before newmtl")),
+            };
+        };
+
+        match words {
+            ["Kd", rgb @ ..] => {
+                material.diffuse_color = color(rgb).ok_or_else(|| invalid("invalid color"))?
+            }
+            ["Ks", rgb @ ..] => {
+                material.specular_color = color(rgb).ok_or_else(|| invalid("invalid color"))?
+            }
+            ["Ke", rgb @ ..] => {
+                material.emissive_color = color(rgb).ok_or_else(|| invalid("invalid color"))?
+            }
+            ["Ns", exponent] => {
+                let exponent: f64 = exponent.parse().map_err(|_| invalid("invalid exponent"))?;
+                material.shininess = exponent.round() as i32;
+            }
+            ["d", opacity, ..] => {
+                material.opacity = opacity.parse().map_err(|_| invalid("invalid opacity"))?
+            }
+            ["Tr", transparency, ..] => {
+                let transparency: f64 = transparency.parse().map_err(|_| invalid("invalid transparency"))?;
+                material.opacity = 1.0 - transparency;
+            }
+            ["map_Kd", .., file] | ["map_Ks", .., file] => {
+                // options like -s or -o come before the file name
+                let texture_path = relative_path(path, file);
+                let index = match texture_indexes.get(&texture_path) {
+                    Some(&index) => index,
+                    None if !texture_path.is_file() => {
+                        log::warn!("{}: texture {} not found", path.display(), texture_path.display());
+                        return Ok(());
+                    }
+                    None => {
+                        let texture = texture::try_load_from_file(&texture_path)
+                            .map_err(|error| ObjError::Texture { path: texture_path.clone(), error })?;
+                        // MTL texture maps tile unless told otherwise
+                        textures.push(texture.with_wrap(Wrap::Repeat));
+                        texture_indexes.insert(texture_path, textures.len() - 1);
+                        textures.len() - 1
+                    }
+                };
+                if words[0] == "map_Kd" {
+                    material.diffuse_texture = Some(index);
+                } else {
+                    material.specular_texture = Some(index);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })
+}
+
+/// Calls the function with the number, from 1, and the words of every line of the file,
+/// without comments
+fn for_each_line(
+    path: &Path,
+    mut function: impl FnMut(usize, &[&str]) -> Result<(), ObjError>,
+) -> Result<(), ObjError> {
+    let io_error = |error| ObjError::Io { path: path.to_path_buf(), error };
+    let reader = BufReader::new(File::open(path).map_err(io_error)?);
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        let line = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        function(index + 1, &words)?;
+    }
+    Ok(())
+}
+
+/// Path of a file referred to from another one, relative to its directory
+fn relative_path(from: &Path, file: &str) -> PathBuf {
+    // files exported on Windows may use backslashes
+    let file = file.replace('\\', "/");
+    from.parent().unwrap_or(Path::new("")).join(file)
+}
+
+fn vector(coordinates: &[&str]) -> Option<Vector3f> {
+    match coordinates {
+        [x, y, z, ..] => Some(Vector3f {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+            z: z.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Color from 0 to 1 in every channel
+fn color(channels: &[&str]) -> Option<Color> {
+    let channel = |value: &str| -> Option<u8> {
+        Some((value.parse::<f64>().ok()? * 255.0).round().clamp(0.0, 255.0) as u8)
+    };
+    match channels {
+        [r, g, b, ..] => Some(Color { r: channel(r)?, g: channel(g)?, b: channel(b)? }),
+        // a single value is gray
+        [gray] => channel(gray).map(|gray| Color { r: gray, g: gray, b: gray }),
+        _ => None,
+    }
+}
+
+/// Corner of a face in the form `v`, `v/vt`, `v//vn` or `v/vt/vn`; indexes count from 1,
+/// negative ones back from the last element read so far
+fn parse_corner(
+    corner: &str,
+    counts: (usize, usize, usize),
+    path: &Path,
+    line: usize,
+) -> Result<Corner, ObjError> {
+    let mut parts = corner.split('/');
+    let mut index = |count: usize, required: bool| -> Result<Option<usize>, ObjError> {
+        let part = parts.next().unwrap_or_default();
+        if part.is_empty() && !required {
+            return Ok(None);
+        }
+        let index: i64 = part.parse().map_err(|_| ObjError::InvalidLine {
+            path: path.to_path_buf(),
+            line,
+            reason: format!("invalid face corner '{}'", corner),
+        })?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(ObjError::InvalidIndex { path: path.to_path_buf(), line, index });
+        }
+        Ok(Some(resolved as usize))
+    };
+
+    let position = index(counts.0, true)?.unwrap_or_default();
+    let uv = index(counts.1, false)?;
+    let normal = index(counts.2, false)?;
+    Ok((position, uv, normal))
+}
+
+#[cfg(test)]
+fn write_files(directory: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    for (name, contents) in files {
+        std::fs::write(directory.join(name), contents).unwrap();
+    }
+    directory
+}
+
+#[test]
+fn test_textured_quad() {
+    let directory = write_files(
+        "gambetta_rasterizer_obj_textured_quad",
+        &[
+            (
+                "quad.obj",
+                "# a textured quad and a plain triangle\n\
+                 mtllib quad.mtl\n\
+                 v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                 vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                 vn 0 0 -1\n\
+                 usemtl checker\n\
+                 f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+                 usemtl glass\n\
+                 f -4 -2 -1\n",
+            ),
+            (
+                "quad.mtl",
+                "newmtl checker\nKd 1 1 1\nKs 0.5 0.5 0.5\nNs 40\nmap_Kd -s 1 1 1 textures\\checker.png\n\
+                 newmtl glass\nKd 0 0.5 1\nd 0.25\nmap_Ks textures/checker.png\n",
+            ),
+        ],
+    );
+    std::fs::create_dir_all(directory.join("textures")).unwrap();
+    image::save_buffer(
+        directory.join("textures/checker.png"),
+        &[0, 0, 0, 255, 255, 255],
+        2,
+        1,
+        image::RGB(8),
+    )
+    .unwrap();
+
+    let obj = load(directory.join("quad.obj")).unwrap();
+    assert_eq!(obj.name, "quad");
+    assert_eq!(obj.vertices.len(), 4);
+    let indexes: Vec<[usize; 3]> = obj.triangles.iter().map(|triangle| triangle.indexes).collect();
+    assert_eq!(indexes, vec![[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+    assert_eq!(obj.triangles[0].normals[1].z, -1.0);
+
+    // v is flipped, the plain triangle has no UVs
+    let uvs = obj.uvs.as_ref().unwrap();
+    assert_eq!((uvs[0][2].u, uvs[0][2].v), (1.0, 0.0));
+    assert_eq!((uvs[2][0].u, uvs[2][0].v), (0.0, 0.0));
+
+    // both materials share the texture, loaded once
+    assert_eq!(obj.textures.len(), 1);
+    assert_eq!(obj.material_indexes, vec![1, 1, 2]);
+    let model = obj.model();
+    let checker = &model.materials[1];
+    assert!(checker.diffuse_texture.is_some());
+    assert_eq!((checker.specular_color.r, checker.shininess), (128, 40));
+    let glass = &model.materials[2];
+    assert!(glass.diffuse_texture.is_none() && glass.specular_texture.is_some());
+    assert_eq!(
+        (glass.diffuse_color.b, glass.opacity, glass.alpha_mode),
+        (255, 0.25, AlphaMode::Blend)
+    );
+}
+
+#[test]
+fn test_smooth_normals() {
+    let pyramid = "v 0 1 0\nv -1 0 -1\nv 1 0 -1\nv 1 0 1\nv -1 0 1\n\
+                   f 1 2 3\nf 1 3 4\nf 1 4 5\nf 1 5 2\n";
+    let directory = write_files(
+        "gambetta_rasterizer_obj_smooth_normals",
+        &[("smooth.obj", pyramid), ("flat.obj", &format!("s off\n{}", pyramid))],
+    );
+
+    // the apex of the smooth pyramid points straight up or down, the flat one keeps the faces
+    let smooth = load(directory.join("smooth.obj")).unwrap();
+    let apex = smooth.triangles[0].normals[0];
+    assert!(apex.x.abs() < 1e-9 && apex.z.abs() < 1e-9 && (apex.y.abs() - 1.0).abs() < 1e-9);
+    assert!(smooth.uvs.is_none());
+    assert_eq!(smooth.material_indexes, vec![0; 4]);
+
+    let flat = load(directory.join("flat.obj")).unwrap();
+    let face = flat.triangles[0];
+    assert_eq!(face.normals[0].x, face.calculated_normal.x);
+    assert!(face.normals[0].z.abs() > 0.1);
+}
+
+#[test]
+fn test_material_names() {
+    let directory = write_files(
+        "gambetta_rasterizer_obj_material_names",
+        &[
+            (
+                "names.obj",
+                "mtllib names.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                 usemtl default\nf 1 2 3\nusemtl red\nf 1 2 3\nusemtl missing\nf 1 2 3\n",
+            ),
+            (
+                "names.mtl",
+                "newmtl default\nKd 0 0 1\nnewmtl red\nKd 0.5 0 0\nnewmtl red\nKd 1 0 0\n",
+            ),
+        ],
+    );
+
+    // a material of the MTL file named like the built-in one isn't shadowed by it, and the last
+    // definition of a name is used; undefined names get the built-in default
+    let obj = load(directory.join("names.obj")).unwrap();
+    assert_eq!(obj.material_indexes, vec![1, 3, 0]);
+    let model = obj.model();
+    assert_eq!(model.materials[1].diffuse_color.b, 255);
+    assert_eq!(model.materials[3].diffuse_color.r, 255);
+}
+
+#[test]
+fn test_missing_material_library() {
+    let directory = write_files(
+        "gambetta_rasterizer_obj_missing_mtllib",
+        &[(
+            "missing.obj",
+            "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n",
+        )],
+    );
+
+    // the faces fall back to the built-in default material
+    let obj = load(directory.join("missing.obj")).unwrap();
+    assert_eq!(obj.materials.len(), 1);
+    assert_eq!(obj.material_indexes, vec![0]);
+    assert_eq!(obj.materials[0].diffuse_color.r, DEFAULT_COLOR.r);
+}
+
+#[test]
+fn test_errors() {
+    let directory = write_files(
+        "gambetta_rasterizer_obj_errors",
+        &[
+            ("index.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"),
+            ("corner.obj", "v 0 0 0\nf 1 a 2\n"),
+            ("texture.obj", "mtllib texture.mtl\n"),
+            ("texture.mtl", "newmtl missing\nmap_Kd missing.png\n"),
+            ("broken.mtl", "newmtl broken\nmap_Kd broken.png\n"),
+            ("broken.obj", "mtllib broken.mtl\n"),
+            ("broken.png", "not a png"),
+        ],
+    );
+
+    assert!(matches!(
+        load(directory.join("index.obj")),
+        Err(ObjError::InvalidIndex { line: 4, index: 4, .. })
+    ));
+    assert!(matches!(
+        load(directory.join("corner.obj")),
+        Err(ObjError::InvalidLine { line: 2, .. })
+    ));
+    // a missing texture leaves the material untextured, but one that can't be read is an error
+    let texture = load(directory.join("texture.obj")).unwrap();
+    assert_eq!(texture.materials[1].diffuse_texture, None);
+    assert!(texture.textures.is_empty());
+    assert!(matches!(
+        load(directory.join("broken.obj")),
+        Err(ObjError::Texture { .. })
+    ));
+    assert!(matches!(
+        load(directory.join("missing.obj")),
+        Err(ObjError::Io { .. })
+    ));
+}
+
+#[test]
+fn test_export_round_trip() {
+    use crate::model::{sphere, textured_cube};
+
+    let texture = Texture::from_rgba(1, 1, vec![255; 4]);
+    let directory = write_files("gambetta_rasterizer_obj_export", &[]);
+    for (name, model) in [("cube.obj", textured_cube(2.0, &texture)), ("sphere.obj", sphere(6))] {
+        let path = directory.join(name);
+        mesh_export::save(&model.to_triangle_mesh(), &path).unwrap();
+        let loaded = load(&path).unwrap();
+
+        assert!(loaded
+            .vertices
+            .iter()
+            .map(Vector3f::to_vec)
+            .eq(model.vertices.iter().map(Vector3f::to_vec)));
+        assert_eq!(loaded.triangles.len(), model.triangles.len());
+        let close = |a: Vector3f, b: Vector3f| vectors::length(vectors::difference(a, b)) < 1e-12;
+        for (loaded, triangle) in loaded.triangles.iter().zip(&model.triangles) {
+            assert_eq!(loaded.indexes, triangle.indexes);
+            assert!((0..3).all(|corner| close(loaded.normals[corner], triangle.normals[corner])));
+        }
+        match (&loaded.uvs, &model.uvs) {
+            (Some(loaded), Some(uvs)) => {
+                let corners =
+                    |uvs: &Vec<[UV; 3]>| uvs.iter().flatten().map(|uv| (uv.u, uv.v)).collect::<Vec<_>>();
+                for (a, b) in corners(loaded).into_iter().zip(corners(uvs)) {
+                    assert!((a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12);
+                }
+            }
+            (None, None) => {}
+            _ => panic!("texture coordinates of {} are lost or invented", name),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::camera::Camera;
+use crate::matrix44f::Matrix44f;
+use crate::orthographic_camera::OrthographicCamera;
+use crate::Point2D;
+use common::Vector3f;
+
+/// Parallel projection along a direction slanted from z: faces parallel to the viewport keep
+/// their shape and size, and the depth recedes on the viewport at an angle
+#[derive(Copy, Clone)]
+pub struct ObliqueCamera {
+    /// Viewport, view volume and placement of the camera
+    pub orthographic: OrthographicCamera,
+    /// Distance to the plane that is drawn in place; points beyond it are shifted along `angle`,
+    /// closer ones the opposite way
+    pub projection_plane_z: f64,
+    /// Direction of the receding depth on the viewport, in degrees counterclockwise from x
+    pub angle: f64,
+    /// Length on the viewport of a unit of depth
+    pub depth_scale: f64,
+}
+
+impl ObliqueCamera {
+    /// Depth drawn at 45 degrees at full length
+    pub fn cavalier(orthographic: OrthographicCamera, projection_plane_z: f64) -> Self {
+        Self {
+            orthographic,
+            projection_plane_z,
+            angle: 45.0,
+            depth_scale: 1.0,
+        }
+    }
+
+    /// Depth drawn at 45 degrees at half length, which looks closer to the real proportions
+    pub fn cabinet(orthographic: OrthographicCamera, projection_plane_z: f64) -> Self {
+        Self {
+            orthographic,
+            projection_plane_z,
+            angle: 45.0,
+            depth_scale: 0.5,
+        }
+    }
+
+    /// Shift of x and y on the viewport for every unit of depth
+    fn shear(&self) -> (f64, f64) {
+        let radians = self.angle.to_radians();
+        (self.depth_scale * radians.cos(), self.depth_scale * radians.sin())
+    }
+}
+
+impl Camera for ObliqueCamera {
+    fn camera_transform(&self) -> Matrix44f {
+        self.orthographic.camera_transform()
+    }
+
+    fn rotation_transform(&self) -> Matrix44f {
+        self.orthographic.rotation_transform()
+    }
+
+    fn viewport_size(&self) -> f64 {
+        self.orthographic.viewport_size
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        self.orthographic.aspect_ratio
+    }
+
+    /// Shear of the points, see `Matrix44f::oblique_shear`, and then the orthographic projection
+    fn projection_matrix(&self) -> Matrix44f {
+        let (x_per_z, y_per_z) = self.shear();
+        Matrix44f::oblique_shear(x_per_z, y_per_z, self.projection_plane_z)
+            .multiply(self.orthographic.projection_matrix())
+    }
+
+    fn view_direction(&self, _point: Vector3f) -> Vector3f {
+        let (x_per_z, y_per_z) = self.shear();
+        Vector3f { x: -x_per_z, y: -y_per_z, z: 1.0 }
+    }
+
+    /// Only the normalized `depth` is needed, as `w` is always 1
+    fn unproject(&self, point: Point2D, w: f64, depth: f64) -> Vector3f {
+        let unsheared = self.orthographic.unproject(point, w, depth);
+        let (x_per_z, y_per_z) = self.shear();
+        let shift = unsheared.z - self.projection_plane_z;
+        Vector3f {
+            x: unsheared.x - shift * x_per_z,
+            y: unsheared.y - shift * y_per_z,
+            z: unsheared.z,
+        }
+    }
+}
+
+#[test]
+fn test_cabinet_projection() {
+    use crate::vector4f::Vector4f;
+    use common::vectors;
+
+    let orthographic = OrthographicCamera {
+        viewport_size: 4.0,
+        near_distance: 1.0,
+        far_distance: 11.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let camera = ObliqueCamera::cabinet(orthographic, 5.0);
+
+    // the projection plane stays in place, a unit of depth beyond it goes half a unit up and right
+    let in_plane = camera.project(Vector3f { x: 1.0, y: -1.0, z: 5.0 });
+    assert!((in_plane.point.x - 1.0).abs() < 1e-9 && (in_plane.point.y + 1.0).abs() < 1e-9);
+    let beyond = camera.project(Vector3f { x: 1.0, y: -1.0, z: 6.0 });
+    let half_diagonal = 0.5 / 2.0f64.sqrt();
+    assert!((beyond.point.x - 1.0 - half_diagonal).abs() < 1e-9);
+    assert!((beyond.point.y + 1.0 - half_diagonal).abs() < 1e-9);
+    assert!((beyond.depth - 0.5).abs() < 1e-9);
+
+    let unprojected = camera.unproject(beyond.point, beyond.w, beyond.depth);
+    assert!(
+        vectors::length(vectors::difference(
+            unprojected,
+            Vector3f { x: 1.0, y: -1.0, z: 6.0 }
+        )) < 1e-9
+    );
+
+    // points along the view direction are projected to the same point
+    let along = vectors::sum(
+        Vector3f { x: 1.0, y: -1.0, z: 6.0 },
+        camera.view_direction(Vector3f::zero_vector()),
+    );
+    let along = camera.project(along);
+    assert!((along.point.x - beyond.point.x).abs() < 1e-9 && (along.point.y - beyond.point.y).abs() < 1e-9);
+}
@@ -0,0 +1,170 @@
+use crate::camera::Camera;
+use crate::matrix44f::Matrix44f;
+use crate::vector4f::Vector4f;
+use crate::Point2D;
+use common::{vectors, Vector3f};
+
+/// Parallel projection along z: things keep their size at any distance
+#[derive(Copy, Clone)]
+pub struct OrthographicCamera {
+    /// Height of the viewport in world units; its width is `viewport_size * aspect_ratio`
+    pub viewport_size: f64,
+    /// Nothing closer to the camera than this is drawn; may be 0 or negative
+    pub near_distance: f64,
+    /// Nothing farther from the camera than this is drawn
+    pub far_distance: f64,
+    /// Width divided by height of the viewport, should be the one of the canvas
+    pub aspect_ratio: f64,
+    pub position: Vector4f,
+    pub rotation: Matrix44f,
+}
+
+/// Side from which an isometric camera looks down at its target, along a diagonal
+/// of the horizontal plane; the front is at negative z, the left at negative x
+#[derive(Copy, Clone, Debug)]
+pub enum IsometricView {
+    FrontLeft,
+    FrontRight,
+    BackLeft,
+    BackRight,
+}
+
+impl OrthographicCamera {
+    /// Camera looking down at the target from the given side at the angle where the x, y and z
+    /// axes are equally foreshortened; everything within `distance` from the target is drawn
+    pub fn isometric(
+        view: IsometricView,
+        target: Vector3f,
+        distance: f64,
+        viewport_size: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        let yaw = match view {
+            IsometricView::FrontLeft => 45.0,
+            IsometricView::FrontRight => -45.0,
+            IsometricView::BackLeft => 135.0,
+            IsometricView::BackRight => -135.0,
+        };
+        let pitch = (1.0 / 2.0f64.sqrt()).atan().to_degrees();
+        let rotation = Matrix44f::rotation_x(pitch).multiply(Matrix44f::rotation_y(yaw));
+        let forward = Vector4f { x: 0.0, y: 0.0, z: 1.0, w: 0.0 }.transform(rotation);
+
+        Self {
+            viewport_size,
+            near_distance: 0.0,
+            far_distance: 2.0 * distance,
+            aspect_ratio,
+            position: vectors::difference(target, vectors::scale(distance, forward.into())).into(),
+            rotation,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn camera_transform(&self) -> Matrix44f {
+        Matrix44f::translation(self.position.negate()).multiply(self.rotation.transpose())
+    }
+
+    fn rotation_transform(&self) -> Matrix44f {
+        self.rotation.transpose()
+    }
+
+    fn viewport_size(&self) -> f64 {
+        self.viewport_size
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    /// See `Matrix44f::orthographic`
+    fn projection_matrix(&self) -> Matrix44f {
+        Matrix44f::orthographic(
+            self.viewport_size * self.aspect_ratio,
+            self.viewport_size,
+            self.near_distance,
+            self.far_distance,
+        )
+    }
+
+    fn view_direction(&self, _point: Vector3f) -> Vector3f {
+        Vector3f { x: 0.0, y: 0.0, z: 1.0 }
+    }
+
+    /// Only the normalized `depth` is needed, as `w` is always 1
+    fn unproject(&self, point: Point2D, _w: f64, depth: f64) -> Vector3f {
+        Vector3f {
+            x: point.x,
+            y: point.y,
+            z: self.far_distance - depth * (self.far_distance - self.near_distance),
+        }
+    }
+}
+
+#[test]
+fn test_isometric_views_look_at_the_target() {
+    use crate::camera::Projected;
+
+    let target = Vector3f { x: 1.0, y: 2.0, z: 3.0 };
+    for view in [
+        IsometricView::FrontLeft,
+        IsometricView::FrontRight,
+        IsometricView::BackLeft,
+        IsometricView::BackRight,
+    ] {
+        let camera = OrthographicCamera::isometric(view, target, 10.0, 4.0, 1.0);
+        let offset = vectors::difference(target, camera.position.into());
+        // the camera is on a diagonal of a cube with the target in the corner, above it
+        assert!((vectors::length(offset) - 10.0).abs() < 1e-9);
+        assert!((offset.x.abs() - offset.z.abs()).abs() < 1e-9);
+        assert!((offset.y + offset.x.abs()).abs() < 1e-9);
+
+        let to_camera_space = |point: Vector3f| -> Vector3f {
+            Vector4f::from(point).transform(camera.camera_transform()).into()
+        };
+        let Projected { point, w, depth } = camera.project(to_camera_space(target));
+        assert!(point.x.abs() < 1e-9 && point.y.abs() < 1e-9);
+        assert_eq!(w, 1.0);
+        assert!((depth - 0.5).abs() < 1e-9);
+
+        // the unit axes are equally long on the viewport
+        let lengths = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)].map(|(x, y, z)| {
+            let end = vectors::sum(target, Vector3f { x, y, z });
+            let end = camera.project(to_camera_space(end)).point;
+            (end.x.powi(2) + end.y.powi(2)).sqrt()
+        });
+        assert!((lengths[0] - lengths[1]).abs() < 1e-9 && (lengths[1] - lengths[2]).abs() < 1e-9);
+    }
+
+    let front_left = OrthographicCamera::isometric(IsometricView::FrontLeft, target, 10.0, 4.0, 1.0);
+    assert!(front_left.position.x < target.x && front_left.position.z < target.z);
+}
+
+#[test]
+fn test_orthographic_projection_round_trip() {
+    let camera = OrthographicCamera {
+        viewport_size: 4.0,
+        near_distance: -1.0,
+        far_distance: 9.0,
+        aspect_ratio: 2.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let point = Vector3f { x: 3.0, y: -1.5, z: 4.0 };
+    let projected = camera.project(point);
+    assert!((projected.point.x - 3.0).abs() < 1e-9 && (projected.point.y + 1.5).abs() < 1e-9);
+    assert!((projected.depth - 0.5).abs() < 1e-9);
+
+    let unprojected = camera.unproject(projected.point, projected.w, projected.depth);
+    assert!(vectors::length(vectors::difference(unprojected, point)) < 1e-9);
+
+    // the planes of the view volume are the sides of the box
+    let planes = camera.clipping_planes();
+    let inside = |point: Vector3f| planes.iter().all(|plane| plane.signed_distance(point) >= -1e-9);
+    assert!(inside(point));
+    assert!(inside(Vector3f { x: 4.0, y: 2.0, z: -1.0 }));
+    assert!(!inside(Vector3f { x: 4.1, y: 0.0, z: 0.0 }));
+    assert!(!inside(Vector3f { x: 0.0, y: -2.1, z: 0.0 }));
+    assert!(!inside(Vector3f { x: 0.0, y: 0.0, z: -1.1 }));
+    assert!(!inside(Vector3f { x: 0.0, y: 0.0, z: 9.1 }));
+}
@@ -0,0 +1,801 @@
+//! Loader of models in the PLY format, also known as the Stanford triangle format.
+//!
+//! Reads the ASCII and both binary encodings with any elements and properties the header
+//! declares. The `vertex` element gives the positions, and when present the normals
+//! (`nx`, `ny`, `nz`), colors (`red`, `green`, `blue`) and texture coordinates (`u`, `v`,
+//! `s`, `t` or `texture_u`, `texture_v`). The `face` element gives polygons as a list of vertex
+//! indexes (`vertex_indices` or `vertex_index`), split into triangle fans, and may have its own
+//! color. Other elements and properties are read and skipped.
+//!
+//! Models have no vertex colors, so every triangle gets a diffuse material of the average color
+//! of its vertices; triangles of the same color share one.
+
+use crate::material::Material;
+use crate::model::{Model, Triangle, UV};
+use common::{Color, Vector3f};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Color of models without colors, the one of the PLY2 models
+const DEFAULT_COLOR: Color = Color { r: 119, g: 136, b: 153 };
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    /// The file doesn't start with `ply`
+    NotPly,
+    /// Line of the header, counted from 1, that can't be parsed
+    InvalidHeader {
+        line: usize,
+        reason: String,
+    },
+    /// The data ends before all the elements the header declares
+    UnexpectedEnd,
+    /// A value in ASCII data that isn't a number
+    InvalidValue {
+        element: String,
+        property: String,
+        value: String,
+    },
+    /// A property the model can't do without, like the coordinates of the vertices
+    MissingProperty {
+        element: String,
+        property: String,
+    },
+    /// A face refers to a vertex that doesn't exist
+    InvalidIndex {
+        face: usize,
+        index: f64,
+    },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(error) => write!(f, "error reading PLY: {}", error),
+            PlyError::NotPly => write!(f, "not a PLY file"),
+            PlyError::InvalidHeader { line, reason } => {
+                write!(f, "invalid PLY header at line {}: {}", line, reason)
+            }
+            PlyError::UnexpectedEnd => write!(f, "PLY data ends before all the declared elements"),
+            PlyError::InvalidValue { element, property, value } => {
+                write!(
+                    f,
+                    "invalid value '{}' of property {} of element {}",
+                    value, property, element
+                )
+            }
+            PlyError::MissingProperty { element, property } => {
+                write!(f, "element {} has no property {}", element, property)
+            }
+            PlyError::InvalidIndex { face, index } => {
+                write!(f, "face {} refers to a missing vertex {}", face, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => PlyError::UnexpectedEnd,
+            _ => PlyError::Io(error),
+        }
+    }
+}
+
+/// Loads the model from the file, named after its path
+pub fn load_model(path: &str) -> Result<Model<'_>, PlyError> {
+    let file = File::open(path)?;
+    read_model(BufReader::new(file), path)
+}
+
+/// Reads the model from PLY data
+pub fn read_model<'a>(mut reader: impl BufRead, name: &'a str) -> Result<Model<'a>, PlyError> {
+    let header = read_header(&mut reader)?;
+    let mut values: Box<dyn ValueReader> = match header.format {
+        Format::Ascii => Box::new(AsciiValues { reader, token: vec![] }),
+        Format::BinaryLittleEndian => Box::new(BinaryValues { reader, big_endian: false }),
+        Format::BinaryBigEndian => Box::new(BinaryValues { reader, big_endian: true }),
+    };
+
+    let mut data = MeshData::default();
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, values.as_mut(), &mut data)?,
+            "face" => read_faces(element, values.as_mut(), &mut data)?,
+            _ => {
+                let mut list = vec![];
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        read_property(element, property, values.as_mut(), &mut list)?;
+                    }
+                }
+            }
+        }
+    }
+
+    build_model(data, name)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, ScalarType::Float32 | ScalarType::Float64)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PropertyType {
+    Scalar(ScalarType),
+    /// Count of the items, then the items
+    List(ScalarType, ScalarType),
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property_index(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<Header, PlyError> {
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut next_line = |line: &mut String| -> Result<usize, PlyError> {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            return Err(PlyError::UnexpectedEnd);
+        }
+        line_number += 1;
+        Ok(line_number)
+    };
+
+    next_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(PlyError::NotPly);
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    loop {
+        let number = next_line(&mut line)?;
+        let invalid = |reason: &str| PlyError::InvalidHeader { line: number, reason: reason.to_string() };
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["format", encoding, _version] => {
+                format = Some(match *encoding {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid("unknown format")),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid("invalid element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property before any element"))?;
+                let count_type = ScalarType::parse(count_type).ok_or_else(|| invalid("unknown type"))?;
+                let item_type = ScalarType::parse(item_type).ok_or_else(|| invalid("unknown type"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::List(count_type, item_type),
+                });
+            }
+            ["property", scalar_type, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property before any element"))?;
+                let scalar_type = ScalarType::parse(scalar_type).ok_or_else(|| invalid("unknown type"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::Scalar(scalar_type),
+                });
+            }
+            _ => return Err(invalid("unknown keyword")),
+        }
+    }
+
+    let format =
+        format.ok_or(PlyError::InvalidHeader { line: line_number, reason: "no format".to_string() })?;
+    Ok(Header { format, elements })
+}
+
+/// Source of the values of the properties, in the order they are stored
+trait ValueReader {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, ValueError>;
+}
+
+enum ValueError {
+    Ply(PlyError),
+    /// The ASCII token that isn't a number
+    NotANumber(String),
+}
+
+impl From<io::Error> for ValueError {
+    fn from(error: io::Error) -> Self {
+        ValueError::Ply(error.into())
+    }
+}
+
+/// Values separated by whitespace, read one at a time
+struct AsciiValues<R> {
+    reader: R,
+    /// The last token read
+    token: Vec<u8>,
+}
+
+impl<R: BufRead> AsciiValues<R> {
+    /// Reads the next token into `token`, which stays empty at the end of the data
+    fn next_token(&mut self) -> io::Result<()> {
+        self.token.clear();
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(());
+            }
+            let skipped = if self.token.is_empty() {
+                buffer.iter().take_while(|byte| byte.is_ascii_whitespace()).count()
+            } else {
+                0
+            };
+            let length = buffer[skipped..].iter().take_while(|byte| !byte.is_ascii_whitespace()).count();
+            self.token.extend_from_slice(&buffer[skipped..skipped + length]);
+            // the token may go on in the next buffer unless whitespace follows it
+            let ended = skipped + length < buffer.len();
+            self.reader.consume(skipped + length);
+            if ended && !self.token.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> ValueReader for AsciiValues<R> {
+    fn read(&mut self, _scalar_type: ScalarType) -> Result<f64, ValueError> {
+        self.next_token()?;
+        if self.token.is_empty() {
+            return Err(ValueError::Ply(PlyError::UnexpectedEnd));
+        }
+        let not_a_number = || ValueError::NotANumber(String::from_utf8_lossy(&self.token).into_owned());
+        let token = std::str::from_utf8(&self.token).map_err(|_| not_a_number())?;
+        token.parse().map_err(|_| not_a_number())
+    }
+}
+
+struct BinaryValues<R> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> BinaryValues<R> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> ValueReader for BinaryValues<R> {
+    /// The bytes are turned little-endian first
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, ValueError> {
+        Ok(match scalar_type {
+            ScalarType::Int8 => i8::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::UInt8 => u8::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::Int16 => i16::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::Int32 => i32::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::Float32 => f32::from_le_bytes(self.bytes()?) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(self.bytes()?),
+        })
+    }
+}
+
+/// Reads the property of one element; the items of lists go to `list`, which the scalars leave
+/// alone. Returns the value of a scalar, or the count of a list
+fn read_property(
+    element: &Element,
+    property: &Property,
+    values: &mut dyn ValueReader,
+    list: &mut Vec<f64>,
+) -> Result<f64, PlyError> {
+    let mut read = |scalar_type| {
+        values.read(scalar_type).map_err(|error| match error {
+            ValueError::Ply(error) => error,
+            ValueError::NotANumber(value) => PlyError::InvalidValue {
+                element: element.name.clone(),
+                property: property.name.clone(),
+                value,
+            },
+        })
+    };
+
+    match property.property_type {
+        PropertyType::Scalar(scalar_type) => read(scalar_type),
+        PropertyType::List(count_type, item_type) => {
+            let count = read(count_type)?;
+            list.clear();
+            for _ in 0..count.max(0.0) as usize {
+                list.push(read(item_type)?);
+            }
+            Ok(count)
+        }
+    }
+}
+
+#[derive(Default)]
+struct MeshData {
+    vertices: Vec<Vector3f>,
+    normals: Option<Vec<Vector3f>>,
+    colors: Option<Vec<Color>>,
+    uvs: Option<Vec<UV>>,
+    faces: Vec<Vec<f64>>,
+    face_colors: Option<Vec<Color>>,
+}
+
+/// Indexes of the red, green and blue properties if the element has all of them
+fn color_properties(element: &Element) -> Option<[usize; 3]> {
+    Some([
+        element.property_index(&["red", "r", "diffuse_red"])?,
+        element.property_index(&["green", "g", "diffuse_green"])?,
+        element.property_index(&["blue", "b", "diffuse_blue"])?,
+    ])
+}
+
+/// Colors stored as floats are from 0 to 1, the integer ones from 0 to 255
+fn color(element: &Element, indexes: [usize; 3], record: &[f64]) -> Color {
+    let [r, g, b] = indexes.map(|index| {
+        let is_float =
+            matches!(element.properties[index].property_type, PropertyType::Scalar(t) if t.is_float());
+        let value = if is_float {
+            record[index] * 255.0
+        } else {
+            record[index]
+        };
+        value.round().clamp(0.0, 255.0) as u8
+    });
+    Color { r, g, b }
+}
+
+fn read_vertices(
+    element: &Element,
+    values: &mut dyn ValueReader,
+    data: &mut MeshData,
+) -> Result<(), PlyError> {
+    let required = |name: &str| {
+        element.property_index(&[name]).ok_or_else(|| PlyError::MissingProperty {
+            element: element.name.clone(),
+            property: name.to_string(),
+        })
+    };
+    let position = [required("x")?, required("y")?, required("z")?];
+    let normal = (|| {
+        Some([
+            element.property_index(&["nx"])?,
+            element.property_index(&["ny"])?,
+            element.property_index(&["nz"])?,
+        ])
+    })();
+    let colors = color_properties(element);
+    let uv = (|| {
+        Some([
+            element.property_index(&["u", "s", "texture_u", "texture_s"])?,
+            element.property_index(&["v", "t", "texture_v", "texture_t"])?,
+        ])
+    })();
+
+    // nothing is reserved for the count of the header, which may be anything
+    let mut normals = normal.map(|_| vec![]);
+    let mut vertex_colors = colors.map(|_| vec![]);
+    let mut uvs = uv.map(|_| vec![]);
+
+    let mut record = vec![0.0; element.properties.len()];
+    let mut list = vec![];
+    for _ in 0..element.count {
+        for (index, property) in element.properties.iter().enumerate() {
+            record[index] = read_property(element, property, values, &mut list)?;
+        }
+        let vector = |[x, y, z]: [usize; 3]| Vector3f { x: record[x], y: record[y], z: record[z] };
+
+        data.vertices.push(vector(position));
+        if let (Some(normals), Some(normal)) = (normals.as_mut(), normal) {
+            normals.push(vector(normal));
+        }
+        if let (Some(vertex_colors), Some(colors)) = (vertex_colors.as_mut(), colors) {
+            vertex_colors.push(color(element, colors, &record));
+        }
+        if let (Some(uvs), Some([u, v])) = (uvs.as_mut(), uv) {
+            // v goes up in PLY files, down the rows of textures here
+            uvs.push(UV { u: record[u], v: 1.0 - record[v] });
+        }
+    }
+
+    data.normals = normals;
+    data.colors = vertex_colors;
+    data.uvs = uvs;
+    Ok(())
+}
+
+fn read_faces(element: &Element, values: &mut dyn ValueReader, data: &mut MeshData) -> Result<(), PlyError> {
+    let indices = element
+        .property_index(&["vertex_indices", "vertex_index"])
+        .filter(|&index| matches!(element.properties[index].property_type, PropertyType::List(..)))
+        .ok_or_else(|| PlyError::MissingProperty {
+            element: element.name.clone(),
+            property: "vertex_indices".to_string(),
+        })?;
+    let colors = color_properties(element);
+
+    let mut face_colors = colors.map(|_| vec![]);
+
+    let mut record = vec![0.0; element.properties.len()];
+    let mut list = vec![];
+    let mut face = vec![];
+    for _ in 0..element.count {
+        for (index, property) in element.properties.iter().enumerate() {
+            record[index] = read_property(element, property, values, &mut list)?;
+            if index == indices {
+                face = list.clone();
+            }
+        }
+        data.faces.push(std::mem::take(&mut face));
+        if let (Some(face_colors), Some(colors)) = (face_colors.as_mut(), colors) {
+            face_colors.push(color(element, colors, &record));
+        }
+    }
+
+    data.face_colors = face_colors;
+    Ok(())
+}
+
+fn build_model(data: MeshData, name: &str) -> Result<Model<'_>, PlyError> {
+    let vertex_count = data.vertices.len();
+    let mut triangles = vec![];
+    let mut uvs = data.uvs.as_ref().map(|_| vec![]);
+    let mut materials = vec![];
+    let mut material_indexes = vec![];
+    let mut material_of_color = HashMap::new();
+
+    for (face_index, face) in data.faces.iter().enumerate() {
+        let indexes = face
+            .iter()
+            .map(|&index| {
+                if index >= 0.0 && index.fract() == 0.0 && (index as usize) < vertex_count {
+                    Ok(index as usize)
+                } else {
+                    Err(PlyError::InvalidIndex { face: face_index, index })
+                }
+            })
+            .collect::<Result<Vec<usize>, PlyError>>()?;
+
+        // a fan of triangles around the first vertex; faces of less than 3 vertices are skipped
+        for i in 1..indexes.len().saturating_sub(1) {
+            let triangle = [indexes[0], indexes[i], indexes[i + 1]];
+            triangles.push(match &data.normals {
+                Some(normals) => Triangle::new_with_provided_normals(
+                    &data.vertices,
+                    triangle,
+                    triangle.map(|index| normals[index]),
+                ),
+                None => Triangle::new_with_calculated_normals(&data.vertices, triangle),
+            });
+            if let (Some(uvs), Some(vertex_uvs)) = (uvs.as_mut(), &data.uvs) {
+                uvs.push(triangle.map(|index| vertex_uvs[index]));
+            }
+
+            let color = match (&data.face_colors, &data.colors) {
+                (Some(face_colors), _) => face_colors[face_index],
+                (None, Some(colors)) => average_color(triangle.map(|index| colors[index])),
+                (None, None) => DEFAULT_COLOR,
+            };
+            let material_index = *material_of_color.entry((color.r, color.g, color.b)).or_insert_with(|| {
+                materials.push(Material::diffuse(color));
+                materials.len() - 1
+            });
+            material_indexes.push(material_index);
+        }
+    }
+
+    if materials.is_empty() {
+        materials.push(Material::diffuse(DEFAULT_COLOR));
+    }
+
+    Ok(Model {
+        name,
+        vertices: data.vertices,
+        triangles,
+        materials,
+        material_indexes,
+        uvs,
+        edges: Default::default(),
+    })
+}
+
+fn average_color(colors: [Color; 3]) -> Color {
+    let average = |channel: fn(&Color) -> u8| {
+        let sum: u32 = colors.iter().map(|color| channel(color) as u32).sum();
+        ((sum + 1) / 3) as u8
+    };
+    Color {
+        r: average(|color| color.r),
+        g: average(|color| color.g),
+        b: average(|color| color.b),
+    }
+}
+
+#[cfg(test)]
+const QUAD_HEADER: &str = "ply
+format {format} 1.0
+comment a unit square of two triangles and a triangle, with normals and colors
+element vertex 5
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+property float u
+property float v
+element face 2
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+#[cfg(test)]
+fn check_quad_model(model: &Model) {
+    assert_eq!(model.vertices.len(), 5);
+    assert_eq!(
+        (model.vertices[2].x, model.vertices[2].y, model.vertices[2].z),
+        (1.0, 1.0, 0.0)
+    );
+    // the quad is split into two triangles
+    let indexes: Vec<[usize; 3]> = model.triangles.iter().map(|triangle| triangle.indexes).collect();
+    assert_eq!(indexes, vec![[0, 1, 2], [0, 2, 3], [1, 2, 4]]);
+    assert_eq!(model.triangles[0].normals[0].z, -1.0);
+
+    // red and green vertices make two colors, shared by the triangles of the quad
+    assert_eq!(model.material_indexes, vec![0, 0, 1]);
+    assert_eq!(model.materials.len(), 2);
+    assert_eq!(model.materials[0].diffuse_color.r, 255);
+    assert_eq!(model.materials[1].diffuse_color.r, 170);
+
+    let uvs = model.uvs.as_ref().unwrap();
+    assert_eq!((uvs[0][2].u, uvs[0][2].v), (1.0, 0.0));
+}
+
+#[test]
+fn test_ascii() {
+    let ply = QUAD_HEADER.replace("{format}", "ascii")
+        + "0 0 0 0 0 -1 255 0 0 0 0\n\
+           1 0 0 0 0 -1 255 0 0 1 0\n\
+           1 1 0 0 0 -1 255 0 0 1 1\n\
+           0 1 0 0 0 -1 255 0 0 0 1\n\
+           2 1 0 0 0 -1 0 255 0 0 1\n\
+           4 0 1 2 3\n\
+           3 1 2 4\n\
+           0 1\n";
+    let model = read_model(ply.as_bytes(), "quad").unwrap();
+    check_quad_model(&model);
+
+    // tokens split between the buffers of the reader
+    let model = read_model(io::BufReader::with_capacity(3, ply.as_bytes()), "quad").unwrap();
+    check_quad_model(&model);
+}
+
+#[test]
+fn test_binary() {
+    let vertices: [([f32; 6], [u8; 3], [f32; 2]); 5] = [
+        ([0.0, 0.0, 0.0, 0.0, 0.0, -1.0], [255, 0, 0], [0.0, 0.0]),
+        ([1.0, 0.0, 0.0, 0.0, 0.0, -1.0], [255, 0, 0], [1.0, 0.0]),
+        ([1.0, 1.0, 0.0, 0.0, 0.0, -1.0], [255, 0, 0], [1.0, 1.0]),
+        ([0.0, 1.0, 0.0, 0.0, 0.0, -1.0], [255, 0, 0], [0.0, 1.0]),
+        ([2.0, 1.0, 0.0, 0.0, 0.0, -1.0], [0, 255, 0], [0.0, 1.0]),
+    ];
+    let faces: [&[i32]; 2] = [&[0, 1, 2, 3], &[1, 2, 4]];
+
+    for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+        let mut ply = QUAD_HEADER.replace("{format}", format).into_bytes();
+        let float = |ply: &mut Vec<u8>, value: f32| {
+            ply.extend(if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            })
+        };
+        let int = |ply: &mut Vec<u8>, value: i32| {
+            ply.extend(if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            })
+        };
+        for (position_and_normal, color, uv) in vertices {
+            position_and_normal.iter().for_each(|&value| float(&mut ply, value));
+            ply.extend(color);
+            uv.iter().for_each(|&value| float(&mut ply, value));
+        }
+        for face in faces {
+            ply.push(face.len() as u8);
+            face.iter().for_each(|&index| int(&mut ply, index));
+        }
+        int(&mut ply, 0);
+        int(&mut ply, 1);
+
+        let model = read_model(ply.as_slice(), "quad").unwrap();
+        check_quad_model(&model);
+
+        // data cut short
+        let truncated = &ply[..ply.len() - 3];
+        assert!(matches!(
+            read_model(truncated, "quad"),
+            Err(PlyError::UnexpectedEnd)
+        ));
+    }
+}
+
+#[test]
+fn test_errors() {
+    let error = |ply: &str| read_model(ply.as_bytes(), "broken").err().unwrap();
+
+    assert!(matches!(error("obj\n"), PlyError::NotPly));
+    assert!(matches!(
+        error("ply\nformat ascii 1.0\nelement vertex 1\nproperty float128 x\nend_header\n"),
+        PlyError::InvalidHeader { line: 4, .. }
+    ));
+    assert!(matches!(
+        error(
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n0 0\n"
+        ),
+        PlyError::MissingProperty { .. }
+    ));
+
+    let triangle =
+        "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                    0 0 0 1 0 0 0 1 0\n";
+    assert!(matches!(
+        error(&(triangle.to_string() + "3 0 1 3\n")),
+        PlyError::InvalidIndex { face: 0, .. }
+    ));
+    match error(&(triangle.to_string() + "3 0 one 2\n")) {
+        PlyError::InvalidValue { element, property, value } => {
+            assert_eq!(
+                (element.as_str(), property.as_str(), value.as_str()),
+                ("face", "vertex_indices", "one")
+            );
+        }
+        other => panic!("unexpected error {}", other),
+    }
+    assert!(matches!(
+        error(&(triangle.to_string() + "3 0 1\n")),
+        PlyError::UnexpectedEnd
+    ));
+
+    // counts far beyond the data are not trusted for allocations
+    for format in ["ascii", "binary_little_endian"] {
+        let huge = format!(
+            "ply\nformat {} 1.0\nelement vertex {}\nproperty float x\nproperty float y\n\
+             property float z\nproperty float nx\nproperty float ny\nproperty float nz\nend_header\n",
+            format,
+            usize::MAX
+        );
+        assert!(matches!(error(&huge), PlyError::UnexpectedEnd));
+    }
+
+    // without colors all the triangles share the default material
+    let model = read_model((triangle.to_string() + "3 0 1 2\n").as_bytes(), "triangle").unwrap();
+    assert_eq!(model.triangles.len(), 1);
+    assert_eq!(model.materials.len(), 1);
+    assert!(model.uvs.is_none());
+}
+
+#[test]
+fn test_export_round_trip() {
+    use crate::model::{sphere, textured_cube};
+    use crate::texture::Texture;
+    use common::vectors;
+    use mesh_export::ply::{write, Format};
+
+    let texture = Texture::from_rgba(1, 1, vec![255; 4]);
+    for model in [textured_cube(2.0, &texture), sphere(6)] {
+        for format in [Format::Ascii, Format::BinaryLittleEndian, Format::BinaryBigEndian] {
+            let mut bytes = vec![];
+            write(&model.to_triangle_mesh(), &mut bytes, format).unwrap();
+            let loaded = read_model(bytes.as_slice(), "loaded").unwrap();
+
+            // corners with different normals or texture coordinates get vertices of their own,
+            // so the triangles are compared by the corners, not by the indexes
+            assert_eq!(loaded.triangles.len(), model.triangles.len());
+            let close = |a: Vector3f, b: Vector3f| vectors::length(vectors::difference(a, b)) < 1e-12;
+            for (loaded_triangle, triangle) in loaded.triangles.iter().zip(&model.triangles) {
+                for corner in 0..3 {
+                    let position = loaded.vertices[loaded_triangle.indexes[corner]];
+                    assert_eq!(
+                        position.to_vec(),
+                        model.vertices[triangle.indexes[corner]].to_vec()
+                    );
+                    assert!(close(loaded_triangle.normals[corner], triangle.normals[corner]));
+                }
+            }
+            if let (Some(loaded_uvs), Some(uvs)) = (&loaded.uvs, &model.uvs) {
+                let corners =
+                    |uvs: &Vec<[UV; 3]>| uvs.iter().flatten().map(|uv| (uv.u, uv.v)).collect::<Vec<_>>();
+                assert_eq!(corners(loaded_uvs), corners(uvs));
+            }
+            assert_eq!(loaded.uvs.is_some(), model.uvs.is_some());
+        }
+    }
+}
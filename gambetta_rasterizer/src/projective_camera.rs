@@ -1,3 +1,4 @@
+use crate::camera::{Camera, Projected};
 use crate::matrix44f::Matrix44f;
 use crate::vector4f::Vector4f;
 use crate::{Plane, PlaneType, Point2D, Vector3f};
@@ -5,34 +6,77 @@ use common::vectors;
 
 #[derive(Copy, Clone)]
 pub struct ProjectiveCamera {
+    /// Height of the viewport; its width is `viewport_size * aspect_ratio`
     pub viewport_size: f64,
+    /// Distance from the camera to the viewport, the points are projected onto it
     pub projection_plane_z: f64,
+    /// Nothing closer to the camera than this is drawn
+    pub near_distance: f64,
+    /// Nothing farther from the camera than this is drawn
+    pub far_distance: f64,
+    /// Width divided by height of the viewport, should be the one of the canvas
+    pub aspect_ratio: f64,
     pub position: Vector4f,
     pub rotation: Matrix44f,
 }
 
-impl ProjectiveCamera {
-    pub fn project(&self, point: Vector3f) -> Point2D {
-        Point2D {
-            x: point.x * self.projection_plane_z / point.z,
-            y: point.y * self.projection_plane_z / point.z,
-        }
+impl Camera for ProjectiveCamera {
+    fn camera_transform(&self) -> Matrix44f {
+        Matrix44f::translation(self.position.negate()).multiply(self.rotation.transpose())
+    }
+
+    fn rotation_transform(&self) -> Matrix44f {
+        self.rotation.transpose()
+    }
+
+    fn viewport_size(&self) -> f64 {
+        self.viewport_size
     }
 
-    pub fn project_vertex(&self, vertex: Vector4f) -> Point2D {
-        Point2D {
-            x: vertex.x * self.projection_plane_z / vertex.z,
-            y: vertex.y * self.projection_plane_z / vertex.z,
+    fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    /// See `Matrix44f::perspective`
+    fn projection_matrix(&self) -> Matrix44f {
+        Matrix44f::perspective(
+            self.viewport_size * self.aspect_ratio,
+            self.viewport_size,
+            self.projection_plane_z,
+            self.near_distance,
+            self.far_distance,
+        )
+    }
+
+    fn view_direction(&self, point: Vector3f) -> Vector3f {
+        point
+    }
+
+    /// The depth for the depth buffer is the inverse depth 1/z
+    fn project(&self, point: Vector3f) -> Projected {
+        Projected {
+            point: Point2D {
+                x: point.x * self.projection_plane_z / point.z,
+                y: point.y * self.projection_plane_z / point.z,
+            },
+            w: point.z,
+            depth: 1.0 / point.z,
         }
     }
 
-    pub fn camera_transform(&self) -> Matrix44f {
-        Matrix44f::translation(self.position.negate()).multiply(self.rotation.transpose())
+    /// Only `w` is needed, as it is the z of the point
+    fn unproject(&self, point: Point2D, w: f64, _depth: f64) -> Vector3f {
+        Vector3f {
+            x: point.x * w / self.projection_plane_z,
+            y: point.y * w / self.projection_plane_z,
+            z: w,
+        }
     }
 
     // we are in left handed coordinate-system
-    pub fn clipping_planes(&self) -> Vec<Plane> {
-        let half_viewport_size = self.viewport_size / 2.0;
+    fn clipping_planes(&self) -> Vec<Plane> {
+        let half_width = self.viewport_size * self.aspect_ratio / 2.0;
+        let half_height = self.viewport_size / 2.0;
 
         vec![
             Plane {
@@ -45,12 +89,25 @@ impl ProjectiveCamera {
                 point: Vector3f {
                     x: 0.0,
                     y: 0.0,
-                    z: self.projection_plane_z,
+                    z: self.near_distance,
+                },
+            },
+            Plane {
+                plane_type: PlaneType::Far,
+                normal: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                point: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: self.far_distance,
                 },
             },
             Plane {
                 plane_type: PlaneType::Left,
-                normal: self.left_plane_normal(half_viewport_size),
+                normal: self.left_plane_normal(half_width, half_height),
                 point: Vector3f {
                     x: 0.0,
                     y: 0.0,
@@ -59,7 +116,7 @@ impl ProjectiveCamera {
             },
             Plane {
                 plane_type: PlaneType::Right,
-                normal: self.right_plane_normal(half_viewport_size),
+                normal: self.right_plane_normal(half_width, half_height),
                 point: Vector3f {
                     x: 0.0,
                     y: 0.0,
@@ -68,7 +125,7 @@ impl ProjectiveCamera {
             },
             Plane {
                 plane_type: PlaneType::Top,
-                normal: self.top_plane_normal(half_viewport_size),
+                normal: self.top_plane_normal(half_width, half_height),
                 point: Vector3f {
                     x: 0.0,
                     y: 0.0,
@@ -77,7 +134,7 @@ impl ProjectiveCamera {
             },
             Plane {
                 plane_type: PlaneType::Bottom,
-                normal: self.bottom_plane_normal(half_viewport_size),
+                normal: self.bottom_plane_normal(half_width, half_height),
                 point: Vector3f {
                     x: 0.0,
                     y: 0.0,
@@ -86,61 +143,63 @@ impl ProjectiveCamera {
             },
         ]
     }
+}
 
-    fn right_plane_normal(&self, half_viewport_size: f64) -> Vector3f {
+impl ProjectiveCamera {
+    fn right_plane_normal(&self, half_width: f64, half_height: f64) -> Vector3f {
         let v1 = Vector3f {
-            x: half_viewport_size,
-            y: -half_viewport_size,
+            x: half_width,
+            y: -half_height,
             z: self.projection_plane_z,
         };
         let v2 = Vector3f {
-            x: half_viewport_size,
-            y: half_viewport_size,
+            x: half_width,
+            y: half_height,
             z: self.projection_plane_z,
         };
 
         vectors::cross_product(v1, v2)
     }
 
-    fn top_plane_normal(&self, half_viewport_size: f64) -> Vector3f {
+    fn top_plane_normal(&self, half_width: f64, half_height: f64) -> Vector3f {
         let v1 = Vector3f {
-            x: half_viewport_size,
-            y: half_viewport_size,
+            x: half_width,
+            y: half_height,
             z: self.projection_plane_z,
         };
         let v2 = Vector3f {
-            x: -half_viewport_size,
-            y: half_viewport_size,
+            x: -half_width,
+            y: half_height,
             z: self.projection_plane_z,
         };
 
         vectors::cross_product(v1, v2)
     }
 
-    fn left_plane_normal(&self, half_viewport_size: f64) -> Vector3f {
+    fn left_plane_normal(&self, half_width: f64, half_height: f64) -> Vector3f {
         let v1 = Vector3f {
-            x: -half_viewport_size,
-            y: half_viewport_size,
+            x: -half_width,
+            y: half_height,
             z: self.projection_plane_z,
         };
         let v2 = Vector3f {
-            x: -half_viewport_size,
-            y: -half_viewport_size,
+            x: -half_width,
+            y: -half_height,
             z: self.projection_plane_z,
         };
 
         vectors::cross_product(v1, v2)
     }
 
-    fn bottom_plane_normal(&self, half_viewport_size: f64) -> Vector3f {
+    fn bottom_plane_normal(&self, half_width: f64, half_height: f64) -> Vector3f {
         let v1 = Vector3f {
-            x: -half_viewport_size,
-            y: -half_viewport_size,
+            x: -half_width,
+            y: -half_height,
             z: self.projection_plane_z,
         };
         let v2 = Vector3f {
-            x: half_viewport_size,
-            y: -half_viewport_size,
+            x: half_width,
+            y: -half_height,
             z: self.projection_plane_z,
         };
 
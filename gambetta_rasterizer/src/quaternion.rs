@@ -0,0 +1,260 @@
+use crate::matrix44f::Matrix44f;
+use common::{vectors, Vector3f};
+
+/// Unit quaternion for orientations: no gimbal lock, and smooth interpolation with `slerp`.
+///
+/// Rotations are right-handed around their axes, like `Matrix44f::rotation_*`, and are composed
+/// with `then` in the order they are applied, like the matrices with `multiply`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Self { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+
+    /// Rotation by the angle in degrees around the axis, which doesn't have to be normalized
+    pub fn from_axis_angle(axis: Vector3f, degrees: f64) -> Self {
+        let half = degrees.to_radians() / 2.0;
+        let axis = vectors::scale(half.sin(), vectors::normalize(axis));
+        Self { w: half.cos(), x: axis.x, y: axis.y, z: axis.z }
+    }
+
+    /// Rotation by the angles in degrees around x, then y, then z,
+    /// the same as the rotation of `Instance::new`
+    pub fn from_euler(degrees: Vector3f) -> Self {
+        let around_x = Self::from_axis_angle(Vector3f { x: 1.0, y: 0.0, z: 0.0 }, degrees.x);
+        let around_y = Self::from_axis_angle(Vector3f { x: 0.0, y: 1.0, z: 0.0 }, degrees.y);
+        let around_z = Self::from_axis_angle(Vector3f { x: 0.0, y: 0.0, z: 1.0 }, degrees.z);
+        around_x.then(around_y).then(around_z)
+    }
+
+    /// Orientation that turns z to the direction and y to the up vector, as far as it can
+    /// while staying perpendicular to the direction; like the rotation of a camera looking there
+    pub fn look_at(direction: Vector3f, up: Vector3f) -> Self {
+        let z = vectors::normalize(direction);
+        let x = vectors::normalize(vectors::cross_product(up, z));
+        let y = vectors::cross_product(z, x);
+        Self::from_rotated_axes(x, y, z)
+    }
+
+    /// This rotation followed by the other one
+    pub fn then(&self, other: Quaternion) -> Self {
+        // the Hamilton product other * self
+        let (a, b) = (other, self);
+        Self {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    /// Scaled back to the unit length, which rounding errors drift away from
+    pub fn normalize(&self) -> Self {
+        let length = self.dot(*self).sqrt();
+        Self {
+            w: self.w / length,
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+        }
+    }
+
+    pub fn rotate(&self, vector: Vector3f) -> Vector3f {
+        let axis = Vector3f { x: self.x, y: self.y, z: self.z };
+        let t = vectors::scale(2.0, vectors::cross_product(axis, vector));
+        vectors::sum(
+            vectors::sum(vector, vectors::scale(self.w, t)),
+            vectors::cross_product(axis, t),
+        )
+    }
+
+    /// Spherical linear interpolation, along the shorter way from this orientation at 0
+    /// to the other one at 1, at a constant angular speed
+    pub fn slerp(&self, other: Quaternion, t: f64) -> Self {
+        let mut dot = self.dot(other);
+        let mut other = other;
+        // q and -q are the same orientation
+        if dot < 0.0 {
+            dot = -dot;
+            other = Self { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+        }
+
+        let (from, to) = if dot > 0.9995 {
+            // almost the same orientation, the linear interpolation is as good and stays stable
+            (1.0 - t, t)
+        } else {
+            let angle = dot.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Self {
+            w: from * self.w + to * other.w,
+            x: from * self.x + to * other.x,
+            y: from * self.y + to * other.y,
+            z: from * self.z + to * other.z,
+        }
+        .normalize()
+    }
+
+    /// Rotation matrix for row vectors, like the other transforms
+    pub fn to_matrix(&self) -> Matrix44f {
+        let Self { w, x, y, z } = *self;
+        Matrix44f {
+            elements: [
+                [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y), 0.0],
+                [2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x), 0.0],
+                [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    fn dot(&self, other: Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Rotation that turns the x, y and z axes to the given orthonormal vectors
+    pub(crate) fn from_rotated_axes(x: Vector3f, y: Vector3f, z: Vector3f) -> Self {
+        // elements of the rotation matrix for column vectors, its columns are the rotated axes
+        let (m00, m01, m02) = (x.x, y.x, z.x);
+        let (m10, m11, m12) = (x.y, y.y, z.y);
+        let (m20, m21, m22) = (x.z, y.z, z.z);
+        let trace = m00 + m11 + m22;
+
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self {
+                w: s / 4.0,
+                x: (m21 - m12) / s,
+                y: (m02 - m20) / s,
+                z: (m10 - m01) / s,
+            }
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Self {
+                w: (m21 - m12) / s,
+                x: s / 4.0,
+                y: (m01 + m10) / s,
+                z: (m02 + m20) / s,
+            }
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Self {
+                w: (m02 - m20) / s,
+                x: (m01 + m10) / s,
+                y: s / 4.0,
+                z: (m12 + m21) / s,
+            }
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Self {
+                w: (m10 - m01) / s,
+                x: (m02 + m20) / s,
+                y: (m12 + m21) / s,
+                z: s / 4.0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_matrices_close(actual: Matrix44f, expected: Matrix44f) {
+    for i in 0..4 {
+        for j in 0..4 {
+            let (a, e) = (actual.elements[i][j], expected.elements[i][j]);
+            assert!(
+                (a - e).abs() < 1e-9,
+                "element [{}][{}] is {} instead of {}",
+                i,
+                j,
+                a,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_vectors_close(actual: Vector3f, expected: Vector3f) {
+    assert!(vectors::length(vectors::difference(actual, expected)) < 1e-9);
+}
+
+#[test]
+fn test_euler_angles_match_matrices() {
+    let degrees = Vector3f { x: 20.0, y: 30.0, z: -70.0 };
+    let matrix = Matrix44f::rotation_x(degrees.x)
+        .multiply(Matrix44f::rotation_y(degrees.y).multiply(Matrix44f::rotation_z(degrees.z)));
+    assert_matrices_close(Quaternion::from_euler(degrees).to_matrix(), matrix);
+
+    // rotating a vector is the same as multiplying it by the matrix
+    let quaternion = Quaternion::from_euler(degrees);
+    let vector = Vector3f { x: 1.0, y: -2.0, z: 0.5 };
+    let transformed = crate::Vector4f::from(vector).transform(matrix);
+    assert_vectors_close(quaternion.rotate(vector), transformed.into());
+}
+
+#[test]
+fn test_axis_angle() {
+    let quarter = Quaternion::from_axis_angle(Vector3f { x: 0.0, y: 0.0, z: 2.0 }, 90.0);
+    let x = Vector3f { x: 1.0, y: 0.0, z: 0.0 };
+    assert_vectors_close(quarter.rotate(x), Vector3f { x: 0.0, y: 1.0, z: 0.0 });
+    assert_vectors_close(
+        quarter.then(quarter).rotate(x),
+        Vector3f { x: -1.0, y: 0.0, z: 0.0 },
+    );
+    assert_vectors_close(
+        quarter.conjugate().rotate(x),
+        Vector3f { x: 0.0, y: -1.0, z: 0.0 },
+    );
+}
+
+#[test]
+fn test_slerp() {
+    let axis = Vector3f { x: 1.0, y: 1.0, z: 0.0 };
+    let from = Quaternion::from_axis_angle(axis, 10.0);
+    let to = Quaternion::from_axis_angle(axis, 130.0);
+    assert_matrices_close(from.slerp(to, 0.0).to_matrix(), from.to_matrix());
+    assert_matrices_close(from.slerp(to, 1.0).to_matrix(), to.to_matrix());
+    assert_matrices_close(
+        from.slerp(to, 0.25).to_matrix(),
+        Quaternion::from_axis_angle(axis, 40.0).to_matrix(),
+    );
+
+    // the shorter way from 350 to 10 degrees goes through 0, not 180
+    let before = Quaternion::from_axis_angle(axis, 350.0);
+    let after = Quaternion::from_axis_angle(axis, 10.0);
+    assert_matrices_close(
+        before.slerp(after, 0.5).to_matrix(),
+        Quaternion::identity().to_matrix(),
+    );
+}
+
+#[test]
+fn test_look_at() {
+    let up = Vector3f { x: 0.0, y: 1.0, z: 0.0 };
+    for direction in [
+        Vector3f { x: 1.0, y: 0.0, z: 0.0 },
+        Vector3f { x: -1.0, y: -1.0, z: -1.0 },
+        Vector3f { x: 0.0, y: 0.2, z: -1.0 },
+        Vector3f { x: 0.1, y: -3.0, z: 0.2 },
+    ] {
+        let orientation = Quaternion::look_at(direction, up);
+        let forward = orientation.rotate(Vector3f { x: 0.0, y: 0.0, z: 1.0 });
+        assert_vectors_close(forward, vectors::normalize(direction));
+        // no roll: x stays horizontal and y points up
+        let right = orientation.rotate(Vector3f { x: 1.0, y: 0.0, z: 0.0 });
+        assert!(right.y.abs() < 1e-9);
+        assert!(orientation.rotate(up).y > 0.0);
+    }
+}
@@ -0,0 +1,513 @@
+//! Triangle setup and scanline rasterization shared by all shading models.
+//!
+//! A triangle carries `N` varyings per vertex (intensity, normal, UV, ...), which are
+//! interpolated across it together with the inverse depth and the depth for the depth test
+//! and handed to a shading closure
+//! for every visible canvas point. The setup is done once per triangle, after that
+//! the triangle can be drawn into any number of canvas tiles.
+
+use crate::buffer_canvas::{BufferCanvas, CanvasTile};
+use crate::camera::Camera;
+use crate::vector4f::Vector4f;
+use crate::{unproject_vertex, Point};
+use common::{Color, Pixel, Vector3f};
+use std::ops::RangeInclusive;
+
+/// Canvas point covered by a triangle, with the varyings interpolated at it
+pub struct Fragment<'a, const N: usize> {
+    pub x: i32,
+    pub y: i32,
+    /// Inverse 1/w of the w in clip space, interpolated linearly in screen space:
+    /// the inverse depth 1/z for perspective projections, 1 for parallel ones
+    pub iz: f64,
+    /// Value for the depth buffer, greater is closer: the one of `Camera::project` when clipping
+    /// in camera space, the normalized depth when clipping in clip space
+    pub depth: f64,
+    pub varyings: [f64; N],
+    camera: &'a dyn Camera,
+    canvas_height: usize,
+    iz_derivatives: (f64, f64),
+    derivatives: &'a [(f64, f64); N],
+}
+
+impl<const N: usize> Fragment<'_, N> {
+    /// Point of the triangle in camera space that is seen in this canvas point
+    pub fn position(&self) -> Vector3f {
+        unproject_vertex(
+            self.x,
+            self.y,
+            self.iz,
+            self.depth,
+            self.canvas_height,
+            self.camera,
+        )
+    }
+
+    /// How much the varying changes from this canvas point to the next one in x and in y;
+    /// the same in every point of the triangle
+    pub fn derivatives(&self, index: usize) -> (f64, f64) {
+        self.derivatives[index]
+    }
+
+    /// Derivatives of the perspective-correct value `varyings[index] / iz`
+    /// of a varying that was divided by w
+    pub fn perspective_derivatives(&self, index: usize) -> (f64, f64) {
+        let value = self.varyings[index] / self.iz;
+        let (dx, dy) = self.derivatives[index];
+        let (iz_dx, iz_dy) = self.iz_derivatives;
+        ((dx - value * iz_dx) / self.iz, (dy - value * iz_dy) / self.iz)
+    }
+}
+
+/// Triangle projected to the canvas, with its points sorted from bottom to top
+pub struct ScreenTriangle<const N: usize> {
+    points: [Point; 3],
+    /// Inverse depth and depth for the depth test of every point
+    depths: [[f64; 2]; 3],
+    varyings: [[f64; N]; 3],
+    iz_derivatives: (f64, f64),
+    derivatives: [(f64, f64); N],
+}
+
+impl<const N: usize> ScreenTriangle<N> {
+    pub fn new(
+        vertices: [Vector4f; 3],
+        varyings: [[f64; N]; 3],
+        camera: &dyn Camera,
+        canvas: &BufferCanvas,
+    ) -> Self {
+        let projected = vertices.map(|vertex| camera.project(vertex.into()));
+        let points = projected.map(|projected| canvas.viewport_to_canvas(projected, camera));
+        Self::from_points(points, projected.map(|projected| projected.depth), varyings)
+    }
+
+    /// Triangle with the vertices in homogeneous clip space, which are divided by w
+    /// to normalized device coordinates and then scaled to the canvas
+    pub fn from_clip_space(vertices: [Vector4f; 3], varyings: [[f64; N]; 3], canvas: &BufferCanvas) -> Self {
+        let half_width = canvas.width as f64 / 2.0;
+        let half_height = canvas.height as f64 / 2.0;
+        let points = vertices.map(|vertex| Point {
+            x: (vertex.x / vertex.w * half_width) as i32,
+            y: (vertex.y / vertex.w * half_height) as i32,
+            h: 1.0,
+            z: vertex.w,
+        });
+        let depths = vertices.map(|vertex| vertex.z / vertex.w);
+        Self::from_points(points, depths, varyings)
+    }
+
+    fn from_points(points: [Point; 3], depths: [f64; 3], varyings: [[f64; N]; 3]) -> Self {
+        let [i0, i1, i2] = sort_bottom_to_top(&points);
+
+        let points = [points[i0], points[i1], points[i2]];
+        let depths = [depths[i0], depths[i1], depths[i2]];
+        let izs = points.map(|point| 1.0 / point.z);
+        let varyings = [varyings[i0], varyings[i1], varyings[i2]];
+        let mut derivatives = [(0.0, 0.0); N];
+        for (i, derivative) in derivatives.iter_mut().enumerate() {
+            *derivative = screen_derivatives(&points, varyings.map(|values| values[i]));
+        }
+
+        Self {
+            points,
+            depths: [0, 1, 2].map(|i| [izs[i], depths[i]]),
+            varyings,
+            iz_derivatives: screen_derivatives(&points, izs),
+            derivatives,
+        }
+    }
+
+    /// The lowest and the highest canvas y covered by the triangle
+    pub fn canvas_y_range(&self) -> (i32, i32) {
+        (self.points[0].y, self.points[2].y)
+    }
+
+    /// Fills the part of the triangle inside the tile, calling `shade` for every canvas point
+    /// that passes the depth test.
+    ///
+    /// Varyings are interpolated linearly in screen space; a varying divided by w gives
+    /// a perspective-correct value when divided by `Fragment::iz` again.
+    pub fn draw(
+        &self,
+        tile: &mut CanvasTile,
+        camera: &dyn Camera,
+        mut shade: impl FnMut(&Fragment<N>) -> Color,
+    ) {
+        self.rasterize(tile, camera, |tile, fragment| {
+            let screen_x = tile.screen_x(fragment.x);
+            let screen_y = tile.screen_y(fragment.y);
+
+            if tile.update_depth_buffer_if_closer(screen_x, screen_y, fragment.depth) {
+                let color = shade(fragment);
+                tile.put_pixel(Pixel { x: screen_x, y: screen_y, color });
+            }
+        });
+    }
+
+    /// Calls `fragment` for every canvas point of the triangle inside the tile, leaving
+    /// the depth test and the output to it
+    pub fn rasterize<'t>(
+        &self,
+        tile: &mut CanvasTile<'t>,
+        camera: &dyn Camera,
+        mut fragment: impl FnMut(&mut CanvasTile<'t>, &Fragment<N>),
+    ) {
+        let rows = tile.canvas_y_range();
+        rasterize_triangle(
+            self.points,
+            self.depths,
+            self.varyings,
+            rows,
+            |x, y, [iz, depth], varyings| {
+                let point = Fragment {
+                    x,
+                    y,
+                    iz,
+                    depth,
+                    varyings: *varyings,
+                    camera,
+                    canvas_height: tile.height,
+                    iz_derivatives: self.iz_derivatives,
+                    derivatives: &self.derivatives,
+                };
+                fragment(tile, &point);
+            },
+        );
+    }
+}
+
+/// Calls `point` for every point of the triangle with y in `rows`, with the depth
+/// interpolated linearly in screen space; for depth-only passes without varyings.
+pub fn rasterize_depth(
+    points: [Point; 3],
+    depths: [f64; 3],
+    rows: RangeInclusive<i32>,
+    mut point: impl FnMut(i32, i32, f64),
+) {
+    let [i0, i1, i2] = sort_bottom_to_top(&points);
+    rasterize_triangle(
+        [points[i0], points[i1], points[i2]],
+        [[depths[i0], 0.0], [depths[i1], 0.0], [depths[i2], 0.0]],
+        [[]; 3],
+        rows,
+        |x, y, [depth, _], _: &[f64; 0]| point(x, y, depth),
+    );
+}
+
+/// Indexes of the points from bottom to top. Points on the same scanline keep the order
+/// they get from the swaps, so the same edges are walked for the same triangle.
+fn sort_bottom_to_top(points: &[Point; 3]) -> [usize; 3] {
+    let mut order = [0, 1, 2];
+    if points[order[1]].y < points[order[0]].y {
+        order.swap(0, 1);
+    }
+    if points[order[2]].y < points[order[0]].y {
+        order.swap(0, 2);
+    }
+    if points[order[2]].y < points[order[1]].y {
+        order.swap(1, 2);
+    }
+    order
+}
+
+/// Derivatives in x and y of the value that changes linearly across the triangle,
+/// taking the given values in its points; zero for a triangle without area
+pub(crate) fn screen_derivatives(points: &[Point; 3], values: [f64; 3]) -> (f64, f64) {
+    let [p0, p1, p2] = points;
+    let (x1, y1) = ((p1.x - p0.x) as f64, (p1.y - p0.y) as f64);
+    let (x2, y2) = ((p2.x - p0.x) as f64, (p2.y - p0.y) as f64);
+    let (v1, v2) = (values[1] - values[0], values[2] - values[0]);
+
+    let determinant = x1 * y2 - x2 * y1;
+    if determinant == 0.0 {
+        (0.0, 0.0)
+    } else {
+        (
+            (v1 * y2 - v2 * y1) / determinant,
+            (v2 * x1 - v1 * x2) / determinant,
+        )
+    }
+}
+
+/// Linear interpolation of the inverse depth, the depth and `N` varyings along a triangle edge,
+/// one scanline per step.
+///
+/// Values are advanced by repeated addition, so every scanline gets exactly the same
+/// numbers the former per-triangle `Vec`s had and the output stays pixel-identical.
+#[derive(Copy, Clone)]
+struct Edge<const N: usize> {
+    x: f64,
+    x_step: f64,
+    /// Inverse depth and depth
+    depths: [f64; 2],
+    depth_steps: [f64; 2],
+    values: [f64; N],
+    steps: [f64; N],
+}
+
+impl<const N: usize> Edge<N> {
+    fn new(start: (Point, [f64; 2], [f64; N]), end: (Point, [f64; 2], [f64; N])) -> Self {
+        let (start, start_depths, start_values) = start;
+        let (end, end_depths, end_values) = end;
+
+        let (x_step, depth_steps, steps) = if start.y == end.y {
+            (0.0, [0.0; 2], [0.0; N])
+        } else {
+            let dy = (end.y - start.y) as f64;
+            (
+                (end.x - start.x) as f64 / dy,
+                interpolation_steps(&start_depths, &end_depths, dy),
+                interpolation_steps(&start_values, &end_values, dy),
+            )
+        };
+
+        Self {
+            x: start.x as f64,
+            x_step,
+            depths: start_depths,
+            depth_steps,
+            values: start_values,
+            steps,
+        }
+    }
+
+    fn x(&self) -> i32 {
+        self.x.round() as i32
+    }
+
+    fn step(&mut self) {
+        self.x += self.x_step;
+        add_steps(&mut self.depths, &self.depth_steps);
+        add_steps(&mut self.values, &self.steps);
+    }
+
+    fn stepped(mut self, count: i32) -> Self {
+        for _ in 0..count {
+            self.step();
+        }
+        self
+    }
+}
+
+fn interpolation_steps<const N: usize>(start: &[f64; N], end: &[f64; N], distance: f64) -> [f64; N] {
+    let mut steps = [0.0; N];
+    for i in 0..N {
+        steps[i] = (end[i] - start[i]) / distance;
+    }
+    steps
+}
+
+fn add_steps<const N: usize>(values: &mut [f64; N], steps: &[f64; N]) {
+    for i in 0..N {
+        values[i] += steps[i];
+    }
+}
+
+/// Walks the triangle scanline by scanline without allocating and calls `fragment`
+/// for every covered canvas point with the depths and values interpolated at that point.
+///
+/// Points must be sorted from bottom to top; `depths` and `values` belong to each point.
+/// Only scanlines with y in `rows` are filled; the edges are stepped through the scanlines
+/// below without filling them, so the values don't depend on the rows.
+fn rasterize_triangle<const N: usize>(
+    points: [Point; 3],
+    depths: [[f64; 2]; 3],
+    values: [[f64; N]; 3],
+    rows: RangeInclusive<i32>,
+    mut fragment: impl FnMut(i32, i32, [f64; 2], &[f64; N]),
+) {
+    let [p0, p1, p2] = points;
+    let v0 = (p0, depths[0], values[0]);
+    let v1 = (p1, depths[1], values[1]);
+    let v2 = (p2, depths[2], values[2]);
+
+    // the long edge p0-p2 is on one side, the short edges p0-p1 and p1-p2 are on the other
+    let mut long_edge = Edge::new(v0, v2);
+    let mut short_edge = Edge::new(v0, v1);
+
+    // the side is decided by comparing both sides in the middle scanline
+    let middle = (p2.y - p0.y + 1) / 2;
+    let short_edge_at_middle = if middle < p1.y - p0.y {
+        short_edge.stepped(middle)
+    } else {
+        Edge::new(v1, v2).stepped(middle - (p1.y - p0.y))
+    };
+    let long_edge_is_left = long_edge.stepped(middle).x() < short_edge_at_middle.x();
+
+    // the edges are stepped by addition up to the first row, so every tile gets the same values
+    let first_row = p0.y.max(*rows.start());
+    long_edge = long_edge.stepped(first_row - p0.y);
+    if first_row > p1.y {
+        short_edge = Edge::new(v1, v2).stepped(first_row - p1.y);
+    } else {
+        short_edge = short_edge.stepped(first_row - p0.y);
+    }
+
+    for y in first_row..(p2.y.min(*rows.end()) + 1) {
+        if y == p1.y {
+            short_edge = Edge::new(v1, v2);
+        }
+
+        let (left, right) = if long_edge_is_left {
+            (&long_edge, &short_edge)
+        } else {
+            (&short_edge, &long_edge)
+        };
+        let x_left = left.x();
+        let x_right = right.x();
+
+        let mut depths = left.depths;
+        let mut values = left.values;
+        let (depth_steps, steps) = if x_left == x_right {
+            ([0.0; 2], [0.0; N])
+        } else {
+            let dx = (x_right - x_left) as f64;
+            (
+                interpolation_steps(&left.depths, &right.depths, dx),
+                interpolation_steps(&left.values, &right.values, dx),
+            )
+        };
+        for x in x_left..(x_right + 1) {
+            fragment(x, y, depths, &values);
+            add_steps(&mut depths, &depth_steps);
+            add_steps(&mut values, &steps);
+        }
+
+        long_edge.step();
+        short_edge.step();
+    }
+}
+
+#[cfg(test)]
+fn interpolated_triangle_reference<const N: usize>(
+    points: [Point; 3],
+    values: [[f64; N]; 3],
+) -> Vec<(i32, i32, [f64; N])> {
+    use crate::{interpolate_float, interpolate_int};
+
+    // scanline algorithm with per-edge and per-scanline vectors, as the shading functions used to do
+    let [p0, _, p2] = points;
+    let edge = |from: usize, to: usize| {
+        let (start, end) = (points[from], points[to]);
+        let x = interpolate_int(start.y, start.x, end.y, end.x);
+        let attributes: Vec<Vec<f64>> = (0..N)
+            .map(|i| interpolate_float(start.y, values[from][i], end.y, values[to][i]))
+            .collect();
+        (x, attributes)
+    };
+
+    let (mut x01, mut attributes01) = edge(0, 1);
+    let (mut x12, mut attributes12) = edge(1, 2);
+    let (x02, attributes02) = edge(0, 2);
+
+    x01.pop();
+    x01.append(&mut x12);
+    for (attribute01, attribute12) in attributes01.iter_mut().zip(attributes12.iter_mut()) {
+        attribute01.pop();
+        attribute01.append(attribute12);
+    }
+    let (x012, attributes012) = (x01, attributes01);
+
+    let m = x02.len() / 2;
+    let ((x_left, attributes_left), (x_right, attributes_right)) = if x02[m] < x012[m] {
+        ((x02, attributes02), (x012, attributes012))
+    } else {
+        ((x012, attributes012), (x02, attributes02))
+    };
+
+    let mut result = Vec::new();
+    for y in p0.y..(p2.y + 1) {
+        let y_index = (y - p0.y) as usize;
+        let x_l = x_left[y_index];
+        let x_r = x_right[y_index];
+        let segments: Vec<Vec<f64>> = (0..N)
+            .map(|i| {
+                interpolate_float(
+                    x_l,
+                    attributes_left[i][y_index],
+                    x_r,
+                    attributes_right[i][y_index],
+                )
+            })
+            .collect();
+        for x in x_l..(x_r + 1) {
+            let x_index = (x - x_l) as usize;
+            let mut interpolated = [0.0; N];
+            for i in 0..N {
+                interpolated[i] = segments[i][x_index];
+            }
+            result.push((x, y, interpolated));
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_rasterize_triangle_matches_vector_interpolation() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(31);
+    let random_point = |rng: &mut rand::rngs::StdRng| Point {
+        x: rng.gen_range(-200..200),
+        y: rng.gen_range(-200..200),
+        h: 1.0,
+        z: rng.gen_range(1.0..100.0),
+    };
+
+    let mut triangles = vec![
+        // flat bottom, flat top and degenerate triangles
+        [(0, 0), (10, 0), (5, 10)],
+        [(0, 0), (10, 10), (0, 10)],
+        [(0, 0), (5, 0), (10, 0)],
+        [(3, -7), (3, -7), (3, -7)],
+        // the long edge ends up a hair short of .5 when stepped by addition, but not when multiplied
+        [(-162, 36), (-90, 50), (-30, 108)],
+    ]
+    .into_iter()
+    .map(|coordinates| coordinates.map(|(x, y)| Point { x, y, h: 1.0, z: 2.0 }))
+    .collect::<Vec<_>>();
+    for _ in 0..200 {
+        triangles.push([random_point(&mut rng), random_point(&mut rng), random_point(&mut rng)]);
+    }
+
+    for points in triangles {
+        let [i0, i1, i2] = sort_bottom_to_top(&points);
+        let points = [points[i0], points[i1], points[i2]];
+        let depths = points.map(|point| [1.0 / point.z, rng.gen::<f64>()]);
+        let values = points.map(|point| [rng.gen::<f64>(), point.x as f64 * 0.37]);
+
+        let mut rasterized = Vec::new();
+        rasterize_triangle(
+            points,
+            depths,
+            values,
+            i32::MIN..=i32::MAX,
+            |x, y, [iz, depth], values| rasterized.push((x, y, [iz, depth, values[0], values[1]])),
+        );
+
+        let reference_values = [0, 1, 2].map(|i| [depths[i][0], depths[i][1], values[i][0], values[i][1]]);
+        let reference = interpolated_triangle_reference(points, reference_values);
+        assert!(rasterized == reference);
+
+        // drawing the triangle in parts gives the same points
+        let mut in_parts = Vec::new();
+        for rows in [i32::MIN..=-1, 0..=20, 21..=i32::MAX] {
+            rasterize_triangle(points, depths, values, rows, |x, y, [iz, depth], values| {
+                in_parts.push((x, y, [iz, depth, values[0], values[1]]))
+            });
+        }
+        assert!(in_parts == reference);
+    }
+}
+
+#[test]
+fn test_screen_derivatives() {
+    let points = [(0, 0), (4, 0), (0, 2)].map(|(x, y)| Point { x, y, h: 1.0, z: 1.0 });
+
+    // the value is 3 + x / 2 - y
+    assert_eq!(screen_derivatives(&points, [3.0, 5.0, 1.0]), (0.5, -1.0));
+
+    let degenerate = [(0, 0), (2, 2), (4, 4)].map(|(x, y)| Point { x, y, h: 1.0, z: 1.0 });
+    assert_eq!(screen_derivatives(&degenerate, [0.0, 1.0, 2.0]), (0.0, 0.0));
+}
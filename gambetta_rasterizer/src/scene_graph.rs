@@ -0,0 +1,417 @@
+//! Hierarchy of nodes with transforms relative to their parents.
+//!
+//! Every node has a local position, scale and rotation, like an `Instance`, and may carry
+//! a model, a light and a camera. World transforms are composed from the root down, so
+//! rotating one node moves everything attached to it: the arm of a robot with its hand,
+//! or the orbit of a planet with the planet and its moons.
+//!
+//! The renderer still takes instances and lights; `instances` and `lights` flatten the visible
+//! part of the graph into them every frame.
+
+use crate::camera::{Camera, Projected};
+use crate::instance::Instance;
+use crate::matrix44f::Matrix44f;
+use crate::model::Model;
+use crate::vector4f::Vector4f;
+use crate::{Plane, Point2D};
+use common::{Light, Vector3f};
+
+/// Rotation, uniform scale and translation, applied in this order
+#[derive(Copy, Clone)]
+pub struct Transform {
+    pub rotation: Matrix44f,
+    pub scale: f64,
+    pub translation: Vector3f,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            rotation: Matrix44f::rotation_x(0.0),
+            scale: 1.0,
+            translation: Vector3f::zero_vector(),
+        }
+    }
+
+    /// Transform of an instance or a node, with the rotation in degrees around x, y and z
+    pub fn from_euler(position: Vector3f, scale: f64, rotation: Vector3f) -> Self {
+        Self {
+            rotation: Matrix44f::rotation_x(rotation.x)
+                .multiply(Matrix44f::rotation_y(rotation.y).multiply(Matrix44f::rotation_z(rotation.z))),
+            scale,
+            translation: position,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix44f {
+        self.rotation
+            .multiply(Matrix44f::uniform_scale(self.scale))
+            .multiply(Matrix44f::translation(self.translation.into()))
+    }
+
+    /// This transform followed by the parent one
+    pub fn then(&self, parent: &Transform) -> Self {
+        let translation = Vector4f::from(self.translation).transform(parent.matrix());
+        Self {
+            rotation: self.rotation.multiply(parent.rotation),
+            scale: self.scale * parent.scale,
+            translation: translation.into(),
+        }
+    }
+}
+
+/// Index of a node in its scene graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+pub struct Node<'a> {
+    pub name: String,
+    /// Hidden nodes hide their children as well
+    pub visible: bool,
+    pub position: Vector3f,
+    pub scale: f64,
+    /// In degrees around x, y and z, like the rotation of an instance
+    pub rotation: Vector3f,
+    pub model: Option<&'a Model<'a>>,
+    /// In the space of the node
+    pub light: Option<Light>,
+    /// Its position and rotation are relative to the node, see `SceneGraph::camera`
+    pub camera: Option<Box<dyn Camera + 'a>>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl<'a> Node<'a> {
+    /// Visible empty node at the origin of its parent
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            visible: true,
+            position: Vector3f::zero_vector(),
+            scale: 1.0,
+            rotation: Vector3f::zero_vector(),
+            model: None,
+            light: None,
+            camera: None,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_model(mut self, model: &'a Model<'a>) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn with_position(mut self, position: Vector3f) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Vector3f) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    pub fn with_camera(mut self, camera: impl Camera + 'a) -> Self {
+        self.camera = Some(Box::new(camera));
+        self
+    }
+
+    pub fn local_transform(&self) -> Transform {
+        Transform::from_euler(self.position, self.scale, self.rotation)
+    }
+}
+
+#[derive(Default)]
+pub struct SceneGraph<'a> {
+    nodes: Vec<Node<'a>>,
+    roots: Vec<NodeId>,
+}
+
+impl<'a> SceneGraph<'a> {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new(), roots: Vec::new() }
+    }
+
+    /// Adds the node as the last child of the parent, or as a root
+    pub fn add(&mut self, mut node: Node<'a>, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        node.parent = parent;
+        node.children.clear();
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<'a> {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node<'a> {
+        &mut self.nodes[id.0]
+    }
+
+    /// The first node with the name, in the order they were added
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name).map(NodeId)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Transform from the space of the node to world space
+    pub fn world_transform(&self, id: NodeId) -> Transform {
+        let node = &self.nodes[id.0];
+        match node.parent {
+            Some(parent) => node.local_transform().then(&self.world_transform(parent)),
+            None => node.local_transform(),
+        }
+    }
+
+    /// Whether the node and all its ancestors are visible
+    pub fn is_visible(&self, id: NodeId) -> bool {
+        let node = &self.nodes[id.0];
+        node.visible && node.parent.is_none_or(|parent| self.is_visible(parent))
+    }
+
+    /// Instances of the models of the visible nodes, placed in world space
+    pub fn instances(&self) -> Vec<Instance<'a>> {
+        let mut instances = Vec::new();
+        self.visit(|node, world| {
+            if let Some(model) = node.model {
+                instances.push(
+                    Instance::new(model, Vector3f::zero_vector(), 1.0, Vector3f::zero_vector())
+                        .with_parent(*world),
+                );
+            }
+        });
+        instances
+    }
+
+    /// Lights of the visible nodes in world space
+    pub fn lights(&self) -> Vec<Light> {
+        let mut lights = Vec::new();
+        self.visit(|node, world| {
+            let light = match node.light {
+                Some(Light::Point { intensity, position }) => {
+                    let position = Vector4f::from(position).transform(world.matrix());
+                    Light::Point { intensity, position: position.into() }
+                }
+                Some(Light::Directional { intensity, direction }) => {
+                    let direction = Vector4f::from(direction).transform(world.rotation);
+                    Light::Directional { intensity, direction: direction.into() }
+                }
+                Some(light) => light,
+                None => return,
+            };
+            lights.push(light);
+        });
+        lights
+    }
+
+    /// Camera attached to the node, following its position and rotation but not its scale
+    pub fn camera(&self, id: NodeId) -> Option<NodeCamera<'_>> {
+        let camera = self.nodes[id.0].camera.as_deref()?;
+        let world = self.world_transform(id);
+        let inverse_rotation = world.rotation.transpose();
+        Some(NodeCamera {
+            camera,
+            inverse_transform: Matrix44f::translation(Vector4f::from(world.translation).negate())
+                .multiply(inverse_rotation),
+            inverse_rotation,
+        })
+    }
+
+    /// Calls `visit` for every visible node, parents before children, with its world transform
+    fn visit(&self, mut visit: impl FnMut(&Node<'a>, &Transform)) {
+        let mut stack: Vec<(NodeId, Transform)> =
+            self.roots.iter().rev().map(|&root| (root, Transform::identity())).collect();
+        while let Some((id, parent)) = stack.pop() {
+            let node = &self.nodes[id.0];
+            if !node.visible {
+                continue;
+            }
+            let world = node.local_transform().then(&parent);
+            visit(node, &world);
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+    }
+}
+
+/// Camera of a node in a scene graph; everything but the placement is the one of the attached camera
+pub struct NodeCamera<'g> {
+    camera: &'g dyn Camera,
+    /// From world space to the space of the node, without the scale
+    inverse_transform: Matrix44f,
+    inverse_rotation: Matrix44f,
+}
+
+impl Camera for NodeCamera<'_> {
+    fn camera_transform(&self) -> Matrix44f {
+        self.inverse_transform.multiply(self.camera.camera_transform())
+    }
+
+    fn rotation_transform(&self) -> Matrix44f {
+        self.inverse_rotation.multiply(self.camera.rotation_transform())
+    }
+
+    fn viewport_size(&self) -> f64 {
+        self.camera.viewport_size()
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        self.camera.aspect_ratio()
+    }
+
+    fn projection_matrix(&self) -> Matrix44f {
+        self.camera.projection_matrix()
+    }
+
+    fn view_direction(&self, point: Vector3f) -> Vector3f {
+        self.camera.view_direction(point)
+    }
+
+    fn unproject(&self, point: Point2D, w: f64, depth: f64) -> Vector3f {
+        self.camera.unproject(point, w, depth)
+    }
+
+    fn project(&self, point: Vector3f) -> Projected {
+        self.camera.project(point)
+    }
+
+    fn clipping_planes(&self) -> Vec<Plane> {
+        self.camera.clipping_planes()
+    }
+}
+
+#[cfg(test)]
+fn assert_close(actual: Vector3f, expected: Vector3f) {
+    let difference = common::vectors::difference(actual, expected);
+    assert!(
+        common::vectors::length(difference) < 1e-9,
+        "[{:.3} {:.3} {:.3}] is not [{:.3} {:.3} {:.3}]",
+        actual.x,
+        actual.y,
+        actual.z,
+        expected.x,
+        expected.y,
+        expected.z,
+    );
+}
+
+#[test]
+fn test_solar_system() {
+    let sphere = crate::model::sphere(8);
+    let mut scene = SceneGraph::new();
+    let sun = scene.add(
+        Node::new("sun")
+            .with_model(&sphere)
+            .with_position(Vector3f { x: 0.0, y: 0.0, z: 10.0 })
+            .with_scale(2.0)
+            .with_light(Light::Point { intensity: 1.0, position: Vector3f::zero_vector() }),
+        None,
+    );
+    let orbit = scene.add(Node::new("earth orbit"), Some(sun));
+    let earth = scene.add(
+        Node::new("earth")
+            .with_model(&sphere)
+            .with_position(Vector3f { x: 3.0, y: 0.0, z: 0.0 })
+            .with_scale(0.5),
+        Some(orbit),
+    );
+    let moon = scene.add(
+        Node::new("moon")
+            .with_model(&sphere)
+            .with_position(Vector3f { x: 2.0, y: 0.0, z: 0.0 })
+            .with_scale(0.5),
+        Some(earth),
+    );
+    assert_eq!(scene.find("earth orbit"), Some(orbit));
+    assert_eq!(scene.parent(moon), Some(earth));
+    assert_eq!(scene.children(sun), &[orbit]);
+
+    // the positions are scaled by the sun
+    let center = |scene: &SceneGraph, id| scene.world_transform(id).translation;
+    assert_close(center(&scene, earth), Vector3f { x: 6.0, y: 0.0, z: 10.0 });
+    assert_close(center(&scene, moon), Vector3f { x: 8.0, y: 0.0, z: 10.0 });
+    assert_eq!(scene.world_transform(moon).scale, 0.5);
+
+    // a quarter of the orbit moves the earth and the moon with it
+    scene.node_mut(orbit).rotation.y = 90.0;
+    assert_close(center(&scene, earth), Vector3f { x: 0.0, y: 0.0, z: 4.0 });
+    assert_close(center(&scene, moon), Vector3f { x: 0.0, y: 0.0, z: 2.0 });
+
+    let instances = scene.instances();
+    assert_eq!(instances.len(), 3);
+    assert_close(
+        instances[2].bounding_sphere().center,
+        Vector3f { x: 0.0, y: 0.0, z: 2.0 },
+    );
+    assert!((instances[2].bounding_sphere().radius - 0.5).abs() < 1e-9);
+    match scene.lights()[..] {
+        [Light::Point { position, .. }] => assert_close(position, Vector3f { x: 0.0, y: 0.0, z: 10.0 }),
+        _ => panic!("expected the light of the sun"),
+    }
+
+    // hiding the earth hides the moon as well
+    scene.node_mut(earth).visible = false;
+    assert!(!scene.is_visible(moon));
+    assert_eq!(scene.instances().len(), 1);
+}
+
+#[test]
+fn test_camera_follows_its_node() {
+    use crate::ProjectiveCamera;
+
+    let mut scene = SceneGraph::new();
+    let arm = scene.add(
+        Node::new("arm").with_position(Vector3f { x: 1.0, y: 0.0, z: 0.0 }).with_scale(3.0),
+        None,
+    );
+    let hand = scene.add(
+        Node::new("hand")
+            .with_position(Vector3f { x: 0.0, y: 0.0, z: 1.0 })
+            .with_camera(ProjectiveCamera {
+                viewport_size: 1.0,
+                projection_plane_z: 1.0,
+                near_distance: 0.1,
+                far_distance: 100.0,
+                aspect_ratio: 1.0,
+                position: Vector4f::zero_vector(),
+                rotation: Matrix44f::rotation_y(0.0),
+            }),
+        Some(arm),
+    );
+    scene.node_mut(arm).rotation.y = 90.0;
+
+    // the hand is at (4, 0, 0) looking along x, the point in front of it is on the axis of the camera
+    let camera = scene.camera(hand).unwrap();
+    let in_front = Vector4f { x: 6.0, y: 0.0, z: 0.0, w: 1.0 }.transform(camera.camera_transform());
+    assert_close(in_front.into(), Vector3f { x: 0.0, y: 0.0, z: 2.0 });
+    let forward = Vector4f { x: 1.0, y: 0.0, z: 0.0, w: 0.0 }.transform(camera.rotation_transform());
+    assert_close(forward.into(), Vector3f { x: 0.0, y: 0.0, z: 1.0 });
+    assert!(scene.camera(arm).is_none());
+}
@@ -0,0 +1,313 @@
+//! Programmable shading stages.
+//!
+//! A vertex shader computes `N` varyings for every vertex of a triangle, the rasterizer
+//! interpolates them across the triangle and a fragment shader turns them into the color
+//! of each visible pixel. Uniforms are simply the fields of the shader structs.
+//!
+//! ```ignore
+//! struct NormalShader;
+//!
+//! impl VertexShader<3> for NormalShader {
+//!     fn shade(&self, face: &Face, index: usize) -> [f64; 3] {
+//!         let normal = face.normals[index];
+//!         [normal.x, normal.y, normal.z]
+//!     }
+//! }
+//!
+//! impl FragmentShader<3> for NormalShader {
+//!     fn shade(&self, _face: &Face, fragment: &Fragment<3>) -> Color {
+//!         let [x, y, z] = fragment.varyings;
+//!         let channel = |value: f64| ((value + 1.0) * 127.5) as u8;
+//!         Color { r: channel(x), g: channel(y), b: channel(z) }
+//!     }
+//! }
+//! ```
+
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::model::UV;
+use crate::scanline::Fragment;
+use crate::shadow::ShadowMap;
+use crate::texture::UvDerivatives;
+use common::vectors;
+use common::{Color, Light, Vector3f};
+
+/// Triangle being drawn, in camera space, with everything the shaders may need
+pub struct Face<'a> {
+    pub vertices: [Vector3f; 3],
+    pub normals: [Vector3f; 3],
+    pub uvs: Option<[UV; 3]>,
+    pub material: &'a Material<'a>,
+    /// Scene lights transformed to camera space
+    pub lights: &'a [Light],
+    /// Shadow maps of the lights, in the same order; empty when shadows are off
+    pub shadow_maps: &'a [Option<ShadowMap>],
+    pub camera: &'a dyn Camera,
+}
+
+impl Face<'_> {
+    pub fn center(&self) -> Vector3f {
+        let [a, b, c] = self.vertices;
+        Vector3f {
+            x: (a.x + b.x + c.x) / 3.0,
+            y: (a.y + b.y + c.y) / 3.0,
+            z: (a.z + b.z + c.z) / 3.0,
+        }
+    }
+
+    /// Illumination of a point of the face by the lights, with its shininess and shadows
+    pub fn illumination(&self, point: Vector3f, normal_direction: Vector3f) -> Illumination {
+        illumination(
+            point,
+            normal_direction,
+            self.camera.view_direction(point),
+            self.lights,
+            self.shadow_maps,
+            self.material.shininess,
+        )
+    }
+}
+
+/// Shaders are called from several threads at once, hence `Sync`
+pub trait VertexShader<const N: usize>: Sync {
+    /// Varyings of the vertex with the given index (0, 1 or 2) in the face
+    fn shade(&self, face: &Face, index: usize) -> [f64; N];
+}
+
+pub trait FragmentShader<const N: usize>: Sync {
+    /// Color of the pixel; called only for fragments that passed the depth test
+    fn shade(&self, face: &Face, fragment: &Fragment<N>) -> Color;
+
+    /// Opacity of the pixel from 0 to 1; called only for faces whose material is not opaque
+    fn opacity(&self, face: &Face, _fragment: &Fragment<N>) -> f64 {
+        face.material.alpha(None, UvDerivatives::default())
+    }
+}
+
+/// One illumination for the whole face, computed in its center.
+/// Material textures are not sampled, as there are no UVs; textured materials use their average color.
+#[derive(Copy, Clone)]
+pub struct FlatShader;
+
+impl VertexShader<2> for FlatShader {
+    fn shade(&self, face: &Face, _index: usize) -> [f64; 2] {
+        let illumination = face.illumination(face.center(), face.normals[0]);
+        [illumination.diffuse, illumination.specular]
+    }
+}
+
+impl FragmentShader<2> for FlatShader {
+    fn shade(&self, face: &Face, fragment: &Fragment<2>) -> Color {
+        let [diffuse, specular] = fragment.varyings;
+        face.material.shade(Illumination { diffuse, specular }, None, UvDerivatives::default())
+    }
+}
+
+/// Illumination computed in the vertices and interpolated across the face.
+/// Material textures are not sampled, as there are no UVs; textured materials use their average color.
+#[derive(Copy, Clone)]
+pub struct GouraudShader;
+
+impl VertexShader<2> for GouraudShader {
+    fn shade(&self, face: &Face, index: usize) -> [f64; 2] {
+        let illumination = face.illumination(face.vertices[index], face.normals[index]);
+        [illumination.diffuse, illumination.specular]
+    }
+}
+
+impl FragmentShader<2> for GouraudShader {
+    fn shade(&self, face: &Face, fragment: &Fragment<2>) -> Color {
+        let [diffuse, specular] = fragment.varyings;
+        face.material.shade(Illumination { diffuse, specular }, None, UvDerivatives::default())
+    }
+}
+
+/// Normals and UVs interpolated across the face and lighting computed for every pixel
+#[derive(Copy, Clone)]
+pub struct PhongShader;
+
+impl VertexShader<5> for PhongShader {
+    fn shade(&self, face: &Face, index: usize) -> [f64; 5] {
+        let normal = face.normals[index];
+        // UVs are divided by w to be interpolated perspective-correctly
+        let w = face.camera.project(face.vertices[index]).w;
+        let uv = face.uvs.map_or(UV { u: 0.0, v: 0.0 }, |uvs| uvs[index]);
+
+        [uv.u / w, uv.v / w, normal.x, normal.y, normal.z]
+    }
+}
+
+impl FragmentShader<5> for PhongShader {
+    fn shade(&self, face: &Face, fragment: &Fragment<5>) -> Color {
+        let [_, _, x, y, z] = fragment.varyings;
+        let illumination = face.illumination(fragment.position(), Vector3f { x, y, z });
+        let (uv, derivatives) = phong_uv(face, fragment);
+
+        face.material.shade(illumination, uv, derivatives)
+    }
+
+    fn opacity(&self, face: &Face, fragment: &Fragment<5>) -> f64 {
+        let (uv, derivatives) = phong_uv(face, fragment);
+        face.material.alpha(uv, derivatives)
+    }
+}
+
+/// Perspective-correct UV of the fragment, if the face has UVs, and its derivatives
+fn phong_uv(face: &Face, fragment: &Fragment<5>) -> (Option<UV>, UvDerivatives) {
+    let [uz, vz, ..] = fragment.varyings;
+    let uv = face.uvs.map(|_| UV { u: uz / fragment.iz, v: vz / fragment.iz });
+    let (du_dx, du_dy) = fragment.perspective_derivatives(0);
+    let (dv_dx, dv_dy) = fragment.perspective_derivatives(1);
+    let derivatives = UvDerivatives {
+        dx: UV { u: du_dx, v: dv_dx },
+        dy: UV { u: du_dy, v: dv_dy },
+    };
+    (uv, derivatives)
+}
+
+/// Light reaching the eye from a point, as fractions of the diffuse and the specular color
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Illumination {
+    /// Ambient and diffuse light
+    pub diffuse: f64,
+    pub specular: f64,
+}
+
+/// Illumination of the point with the given normal, seen from the camera along the view direction,
+/// all in camera space. Lights with a shadow map are dimmed by the visibility of the point in it.
+/// Specular highlights are skipped when `shininess` is not positive.
+pub fn illumination(
+    point: Vector3f,
+    normal_direction: Vector3f,
+    view_direction: Vector3f,
+    lights: &[Light],
+    shadow_maps: &[Option<ShadowMap>],
+    shininess: i32,
+) -> Illumination {
+    let mut result = Illumination::default();
+    let normal = vectors::normalize(normal_direction);
+
+    for (index, light) in lights.iter().enumerate() {
+        let (diffuse, specular) = match *light {
+            Light::Ambient { intensity } => (intensity, 0.0),
+            Light::Point { intensity, position } => {
+                let direction = vectors::difference(position, point);
+                light_from_direction(view_direction, normal, direction, intensity, shininess)
+            }
+            Light::Directional { intensity, direction } => {
+                light_from_direction(view_direction, normal, direction, intensity, shininess)
+            }
+        };
+        let visibility = match shadow_maps.get(index) {
+            Some(Some(shadow_map)) if diffuse > 0.0 || specular > 0.0 => shadow_map.visibility(point, normal),
+            _ => 1.0,
+        };
+        result.diffuse += diffuse * visibility;
+        result.specular += specular * visibility;
+    }
+    result
+}
+
+/// Diffuse and specular intensity of a light
+fn light_from_direction(
+    view_direction: Vector3f,
+    normal: Vector3f,
+    light_direction: Vector3f,
+    light_intensity: f64,
+    shininess: i32,
+) -> (f64, f64) {
+    let mut diffuse = 0.0;
+    let mut specular = 0.0;
+
+    // diffuse
+    let dot = vectors::dot_product(normal, light_direction);
+    if dot > 0.0 {
+        // assuming that normal is a unit vector (has length 1)
+        diffuse += light_intensity * dot / vectors::length(light_direction);
+    }
+
+    // specular
+    // TODO add color of the light to this component
+    if shininess > 0 {
+        let view = vectors::negate(view_direction);
+        let reflection_direction = vectors::reflect(light_direction, normal);
+        let reflection_dot_view = vectors::dot_product(reflection_direction, view);
+        if reflection_dot_view > 0.0 {
+            specular += light_intensity
+                * (reflection_dot_view / (vectors::length(reflection_direction) * vectors::length(view)))
+                    .powi(shininess)
+        }
+    }
+
+    (diffuse, specular)
+}
+
+#[cfg(test)]
+struct NormalShader;
+
+#[cfg(test)]
+impl VertexShader<3> for NormalShader {
+    fn shade(&self, face: &Face, index: usize) -> [f64; 3] {
+        let normal = face.normals[index];
+        [normal.x, normal.y, normal.z]
+    }
+}
+
+#[cfg(test)]
+impl FragmentShader<3> for NormalShader {
+    fn shade(&self, _face: &Face, fragment: &Fragment<3>) -> Color {
+        let [x, y, z] = fragment.varyings;
+        let channel = |value: f64| ((value + 1.0) * 127.5) as u8;
+        Color { r: channel(x), g: channel(y), b: channel(z) }
+    }
+}
+
+#[test]
+fn test_render_scene_with_custom_shaders() {
+    use crate::{BufferCanvas, Instance, Matrix44f, ProjectiveCamera, RenderingMode, RenderingSettings};
+    use crate::{Clipping, ShadingModel, Transparency, Vector4f, WireframeSettings};
+
+    let cube = crate::model::cube(2.0);
+    let instances = vec![Instance::new(
+        &cube,
+        Vector3f { x: 0.0, y: 0.0, z: 5.0 },
+        1.0,
+        Vector3f::zero_vector(),
+    )];
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let settings = RenderingSettings {
+        rendering_mode: RenderingMode::Filled,
+        shading_model: ShadingModel::Flat,
+        show_normals: false,
+        backface_culling: true,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: None,
+        wireframe: WireframeSettings::default(),
+    };
+    let mut canvas = BufferCanvas::new(100, 100);
+
+    crate::render_scene_with_shaders(
+        &instances,
+        &vec![],
+        &camera,
+        &settings,
+        &NormalShader,
+        &NormalShader,
+        &mut canvas,
+    );
+
+    // the front face of the cube looks at the camera, its normal is (0, 0, -1)
+    let center = (50 * canvas.width + 50) * 3;
+    assert_eq!(&canvas.buffer[center..center + 3], &[127, 127, 0]);
+    // the corner of the canvas is outside of the cube
+    assert_eq!(&canvas.buffer[0..3], &[0, 0, 0]);
+}
@@ -0,0 +1,404 @@
+//! Shadow maps: depth renders of the scene from the lights.
+//!
+//! A directional light gets one orthographic map fitted around all the shadow casters,
+//! a point light gets a cube map of six perspective maps with a 90° field of view. The maps
+//! are rendered in camera space, like everything else the shaders see, with the same
+//! scanline rasterization as the canvas. A point is lit by a light when it is not farther
+//! from the light than the depth stored in the map, with percentage-closer filtering (PCF)
+//! softening the edges of the shadows. The point is looked up slightly moved along its normal,
+//! by the size of a texel, so surfaces at grazing angles to the light don't shadow themselves.
+
+use crate::scanline::rasterize_depth;
+use crate::vector4f::Vector4f;
+use crate::{clip_triangles, Plane, PlaneType, Point, Triangle4f};
+use common::{vectors, Color, Light, Vector3f};
+
+/// Maps are not rendered closer than this to a point light
+const NEAR_DISTANCE: f64 = 0.01;
+
+#[derive(Copy, Clone)]
+pub struct ShadowSettings {
+    /// Width and height of a map, or of every face of a cube map, in texels
+    pub map_size: usize,
+    /// Distance, in scene units, a point may be behind the stored depth and still be lit;
+    /// keeps surfaces from shadowing themselves (shadow acne)
+    pub bias: f64,
+    /// Texels to each side of the looked up one that are averaged by PCF; 0 gives hard shadows
+    pub pcf_radius: usize,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self { map_size: 1024, bias: 0.05, pcf_radius: 1 }
+    }
+}
+
+pub enum ShadowMap {
+    Directional(DepthMap),
+    /// Faces looking along +x, -x, +y, -y, +z and -z from the position of the light
+    Point {
+        position: Vector3f,
+        faces: Box<[DepthMap; 6]>,
+    },
+}
+
+impl ShadowMap {
+    /// Renders the map of the light from the triangles in camera space; `None` for ambient lights
+    pub fn render(light: &Light, casters: &[[Vector3f; 3]], settings: &ShadowSettings) -> Option<Self> {
+        match *light {
+            Light::Ambient { .. } => None,
+            Light::Directional { direction, .. } => {
+                // the direction points to the light, so the light looks the opposite way
+                let view = View::looking_along(Vector3f::zero_vector(), vectors::negate(direction));
+                let mut map = DepthMap::orthographic(view, casters, settings);
+                map.render(casters);
+                Some(ShadowMap::Directional(map))
+            }
+            Light::Point { position, .. } => {
+                let faces = CUBE_FACE_DIRECTIONS.map(|direction| {
+                    let view = View::looking_along(position, Vector3f::from_vec(direction));
+                    let mut map = DepthMap::perspective(view, settings);
+                    map.render(casters);
+                    map
+                });
+                Some(ShadowMap::Point { position, faces: Box::new(faces) })
+            }
+        }
+    }
+
+    /// Fraction of the light reaching the point with the given unit normal, from 0 in the shadow to 1
+    pub fn visibility(&self, point: Vector3f, normal: Vector3f) -> f64 {
+        match self {
+            ShadowMap::Directional(map) => map.visibility(point, normal),
+            ShadowMap::Point { position, faces } => {
+                let [x, y, z] = vectors::difference(point, *position).to_vec();
+                let face = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+                    if x > 0.0 {
+                        0
+                    } else {
+                        1
+                    }
+                } else if y.abs() >= z.abs() {
+                    if y > 0.0 {
+                        2
+                    } else {
+                        3
+                    }
+                } else if z > 0.0 {
+                    4
+                } else {
+                    5
+                };
+                faces[face].visibility(point, normal)
+            }
+        }
+    }
+}
+
+const CUBE_FACE_DIRECTIONS: [[f64; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+/// Orthonormal basis of a light looking along `forward`
+#[derive(Copy, Clone)]
+struct View {
+    origin: Vector3f,
+    right: Vector3f,
+    up: Vector3f,
+    forward: Vector3f,
+}
+
+impl View {
+    fn looking_along(origin: Vector3f, direction: Vector3f) -> Self {
+        let forward = vectors::normalize(direction);
+        let any_up = if forward.y.abs() < 0.99 {
+            Vector3f { x: 0.0, y: 1.0, z: 0.0 }
+        } else {
+            Vector3f { x: 1.0, y: 0.0, z: 0.0 }
+        };
+        let right = vectors::normalize(vectors::cross_product(any_up, forward));
+        let up = vectors::cross_product(forward, right);
+        Self { origin, right, up, forward }
+    }
+
+    /// Coordinates of the point in the view: x to the right, y up and z forward
+    fn transform(&self, point: Vector3f) -> Vector3f {
+        let relative = vectors::difference(point, self.origin);
+        Vector3f {
+            x: vectors::dot_product(relative, self.right),
+            y: vectors::dot_product(relative, self.up),
+            z: vectors::dot_product(relative, self.forward),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Projection {
+    /// Texel coordinates are `(x - min_x) * scale` and `(y - min_y) * scale`
+    Orthographic { min_x: f64, min_y: f64, scale: f64 },
+    /// 90° field of view in both directions
+    Perspective,
+}
+
+/// Depths of the closest surfaces seen by a light, along its view direction
+pub struct DepthMap {
+    view: View,
+    projection: Projection,
+    size: usize,
+    depths: Vec<f64>,
+    bias: f64,
+    pcf_radius: usize,
+}
+
+impl DepthMap {
+    /// Map covering all the casters
+    fn orthographic(view: View, casters: &[[Vector3f; 3]], settings: &ShadowSettings) -> Self {
+        if casters.is_empty() {
+            // nothing casts shadows, so the map is fully lit and its bounds don't matter
+            let projection = Projection::Orthographic { min_x: 0.0, min_y: 0.0, scale: 1.0 };
+            return Self::new(view, projection, settings);
+        }
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for point in casters.iter().flatten().map(|&vertex| view.transform(vertex)) {
+            min_x = min_x.min(point.x);
+            min_y = min_y.min(point.y);
+            max_x = max_x.max(point.x);
+            max_y = max_y.max(point.y);
+        }
+        let extent = (max_x - min_x).max(max_y - min_y);
+        // a little margin keeps the farthest casters inside the last texels
+        let scale = if extent > 0.0 {
+            settings.map_size as f64 / (extent * 1.001)
+        } else {
+            1.0
+        };
+
+        Self::new(view, Projection::Orthographic { min_x, min_y, scale }, settings)
+    }
+
+    fn perspective(view: View, settings: &ShadowSettings) -> Self {
+        Self::new(view, Projection::Perspective, settings)
+    }
+
+    fn new(view: View, projection: Projection, settings: &ShadowSettings) -> Self {
+        Self {
+            view,
+            projection,
+            size: settings.map_size,
+            depths: vec![f64::INFINITY; settings.map_size * settings.map_size],
+            bias: settings.bias,
+            pcf_radius: settings.pcf_radius,
+        }
+    }
+
+    /// Texel coordinates of a point in the view, possibly outside of the map
+    fn to_texel(&self, point: Vector3f) -> (f64, f64) {
+        match self.projection {
+            Projection::Orthographic { min_x, min_y, scale } => {
+                ((point.x - min_x) * scale, (point.y - min_y) * scale)
+            }
+            Projection::Perspective => {
+                let half_size = self.size as f64 / 2.0;
+                (
+                    (point.x / point.z + 1.0) * half_size,
+                    (point.y / point.z + 1.0) * half_size,
+                )
+            }
+        }
+    }
+
+    fn render(&mut self, casters: &[[Vector3f; 3]]) {
+        let frustum = match self.projection {
+            Projection::Orthographic { .. } => Vec::new(),
+            Projection::Perspective => perspective_frustum(),
+        };
+
+        for caster in casters {
+            let [a, b, c] = caster.map(|vertex| Vector4f::from(self.view.transform(vertex)));
+            let triangle = Triangle4f {
+                a,
+                b,
+                c,
+                color: Color { r: 0, g: 0, b: 0 },
+                normals: [Vector3f::zero_vector(); 3],
+            };
+            for triangle in clip_triangles(vec![triangle], &frustum) {
+                self.render_triangle([triangle.a, triangle.b, triangle.c].map(Vector3f::from));
+            }
+        }
+    }
+
+    fn render_triangle(&mut self, vertices: [Vector3f; 3]) {
+        let points = vertices.map(|vertex| {
+            let (x, y) = self.to_texel(vertex);
+            Point {
+                x: x.floor() as i32,
+                y: y.floor() as i32,
+                h: 1.0,
+                z: vertex.z,
+            }
+        });
+        // the inverse depth changes linearly across a perspective projection, the depth across an orthographic one
+        let perspective = matches!(self.projection, Projection::Perspective);
+        let depths = vertices.map(|vertex| if perspective { 1.0 / vertex.z } else { vertex.z });
+
+        let size = self.size as i32;
+        rasterize_depth(points, depths, 0..=size - 1, |x, y, depth| {
+            if x < 0 || x >= size {
+                return;
+            }
+            let depth = if perspective { 1.0 / depth } else { depth };
+            let texel = &mut self.depths[y as usize * self.size + x as usize];
+            *texel = texel.min(depth);
+        });
+    }
+
+    /// Size of a texel in scene units at the point in the view
+    fn texel_size(&self, point: Vector3f) -> f64 {
+        match self.projection {
+            Projection::Orthographic { scale, .. } => 1.0 / scale,
+            Projection::Perspective => 2.0 * point.z / self.size as f64,
+        }
+    }
+
+    /// Fraction of the PCF texels around the point in which it is not farther than the stored depth;
+    /// points outside of an orthographic map are lit
+    fn visibility(&self, point: Vector3f, normal: Vector3f) -> f64 {
+        let texel_size = self.texel_size(self.view.transform(point));
+        let point = self.view.transform(vectors::sum(point, vectors::scale(texel_size, normal)));
+        if let Projection::Perspective = self.projection {
+            if point.z <= NEAR_DISTANCE {
+                return 1.0;
+            }
+        }
+        let (x, y) = self.to_texel(point);
+        if let Projection::Orthographic { .. } = self.projection {
+            // far outside of the map the texel coordinates wouldn't even fit into integers
+            let margin = self.pcf_radius as f64 + 1.0;
+            let inside = -margin..self.size as f64 + margin;
+            if !inside.contains(&x) || !inside.contains(&y) {
+                return 1.0;
+            }
+        }
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+        let radius = self.pcf_radius as i64;
+
+        let mut lit = 0;
+        let mut total = 0;
+        for texel_y in y - radius..=y + radius {
+            for texel_x in x - radius..=x + radius {
+                total += 1;
+                match self.depth_at(texel_x, texel_y) {
+                    Some(depth) if point.z - self.bias > depth => {}
+                    _ => lit += 1,
+                }
+            }
+        }
+        lit as f64 / total as f64
+    }
+
+    /// Depth stored in the texel; texels past the edges of a cube map face are clamped to it,
+    /// as its neighbour faces continue there
+    fn depth_at(&self, x: i64, y: i64) -> Option<f64> {
+        let size = self.size as i64;
+        let (x, y) = match self.projection {
+            Projection::Orthographic { .. } if x < 0 || y < 0 || x >= size || y >= size => return None,
+            Projection::Orthographic { .. } => (x, y),
+            Projection::Perspective => (x.clamp(0, size - 1), y.clamp(0, size - 1)),
+        };
+        Some(self.depths[y as usize * self.size + x as usize])
+    }
+}
+
+/// Planes of a 90° frustum along z, beyond the near distance
+fn perspective_frustum() -> Vec<Plane> {
+    let plane = |plane_type, x, y, z| Plane {
+        plane_type,
+        normal: Vector3f { x, y, z },
+        point: Vector3f { x: 0.0, y: 0.0, z: 0.0 },
+    };
+    vec![
+        Plane {
+            plane_type: PlaneType::Near,
+            normal: Vector3f { x: 0.0, y: 0.0, z: 1.0 },
+            point: Vector3f { x: 0.0, y: 0.0, z: NEAR_DISTANCE },
+        },
+        plane(PlaneType::Left, 1.0, 0.0, 1.0),
+        plane(PlaneType::Right, -1.0, 0.0, 1.0),
+        plane(PlaneType::Bottom, 0.0, 1.0, 1.0),
+        plane(PlaneType::Top, 0.0, -1.0, 1.0),
+    ]
+}
+
+#[cfg(test)]
+fn horizontal_square(center: Vector3f, size: f64) -> Vec<[Vector3f; 3]> {
+    let corner = |dx: f64, dz: f64| Vector3f {
+        x: center.x + dx * size / 2.0,
+        y: center.y,
+        z: center.z + dz * size / 2.0,
+    };
+    vec![
+        [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0)],
+        [corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)],
+    ]
+}
+
+#[test]
+fn test_directional_shadow() {
+    // a small square hovering over a floor, lit from straight above
+    let mut casters = horizontal_square(Vector3f { x: 0.0, y: 0.0, z: 5.0 }, 10.0);
+    casters.extend(horizontal_square(Vector3f { x: 0.0, y: 2.0, z: 5.0 }, 2.0));
+    let light = Light::Directional {
+        intensity: 1.0,
+        direction: Vector3f { x: 0.0, y: 1.0, z: 0.0 },
+    };
+    let settings = ShadowSettings { map_size: 256, bias: 0.05, pcf_radius: 1 };
+    let up = Vector3f { x: 0.0, y: 1.0, z: 0.0 };
+
+    let map = ShadowMap::render(&light, &casters, &settings).unwrap();
+
+    assert_eq!(map.visibility(Vector3f { x: 0.0, y: 0.0, z: 5.0 }, up), 0.0);
+    assert_eq!(map.visibility(Vector3f { x: 3.0, y: 0.0, z: 5.0 }, up), 1.0);
+    // the caster doesn't shadow itself
+    assert_eq!(map.visibility(Vector3f { x: 0.0, y: 2.0, z: 5.0 }, up), 1.0);
+    // PCF softens the edge of the shadow
+    let edge = map.visibility(Vector3f { x: 1.0, y: 0.0, z: 5.0 }, up);
+    assert!(edge > 0.0 && edge < 1.0, "visibility at the edge is {}", edge);
+    // points far outside of the map are lit
+    assert_eq!(map.visibility(Vector3f { x: 1e300, y: 0.0, z: 5.0 }, up), 1.0);
+
+    // without casters everything is lit
+    let empty = ShadowMap::render(&light, &[], &settings).unwrap();
+    assert_eq!(empty.visibility(Vector3f { x: 0.0, y: 0.0, z: 5.0 }, up), 1.0);
+
+    assert!(ShadowMap::render(&Light::Ambient { intensity: 1.0 }, &casters, &settings).is_none());
+}
+
+#[test]
+fn test_point_shadow() {
+    // a light between a floor and a ceiling, with a small square under it
+    let mut casters = horizontal_square(Vector3f { x: 0.0, y: -2.0, z: 5.0 }, 10.0);
+    casters.extend(horizontal_square(Vector3f { x: 0.0, y: 2.0, z: 5.0 }, 10.0));
+    casters.extend(horizontal_square(Vector3f { x: 0.0, y: -1.0, z: 5.0 }, 1.0));
+    let light = Light::Point {
+        intensity: 1.0,
+        position: Vector3f { x: 0.0, y: 0.0, z: 5.0 },
+    };
+    let settings = ShadowSettings { map_size: 128, bias: 0.05, pcf_radius: 0 };
+    let up = Vector3f { x: 0.0, y: 1.0, z: 0.0 };
+
+    let map = ShadowMap::render(&light, &casters, &settings).unwrap();
+
+    // the shadow of the square on the floor is twice as big
+    assert_eq!(map.visibility(Vector3f { x: 0.8, y: -2.0, z: 5.0 }, up), 0.0);
+    assert_eq!(map.visibility(Vector3f { x: 1.2, y: -2.0, z: 5.0 }, up), 1.0);
+    // the ceiling is seen through another face of the cube map
+    assert_eq!(map.visibility(Vector3f { x: 0.0, y: 2.0, z: 5.0 }, up), 1.0);
+    assert_eq!(map.visibility(Vector3f { x: 4.0, y: -2.0, z: 7.0 }, up), 1.0);
+}
@@ -1,29 +1,377 @@
+//! Textures stored as flat RGB(A) arrays with a chain of mip levels.
+//!
+//! Every level is half the size of the previous one, down to 1x1. The level to sample
+//! is chosen from the screen-space derivatives of UV: the farther the surface, the more
+//! texels fall into one canvas point and the smaller the level, so distant textures
+//! don't shimmer.
+
+use crate::model::UV;
 use common::Color;
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, ImageResult};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// The nearest texel of the nearest mip level
+    Nearest,
+    /// Four texels of the nearest mip level blended together
+    Bilinear,
+    /// Bilinear samples of the two nearest mip levels blended together
+    Trilinear,
+}
+
+/// What UVs outside of [0, 1] address; textures clamp unless they tile with `with_wrap`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+/// How much UV changes from a canvas point to the next one in x and in y
+#[derive(Copy, Clone, Default)]
+pub struct UvDerivatives {
+    pub dx: UV,
+    pub dy: UV,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<u8>,
+}
 
 pub struct Texture {
-    img: DynamicImage,
-    width: u32,
-    height: u32,
+    /// Mip levels from the full-size image to 1x1
+    levels: Vec<MipLevel>,
+    /// 3 for RGB and 4 for RGBA texels
+    channels: usize,
+    pub filter: Filter,
+    pub wrap: Wrap,
 }
 
 impl Texture {
+    pub fn from_rgb(width: usize, height: usize, texels: Vec<u8>) -> Self {
+        Self::new(width, height, 3, texels)
+    }
+
+    pub fn from_rgba(width: usize, height: usize, texels: Vec<u8>) -> Self {
+        Self::new(width, height, 4, texels)
+    }
+
+    fn new(width: usize, height: usize, channels: usize, texels: Vec<u8>) -> Self {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(texels.len(), width * height * channels, "wrong number of texels");
+
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(level) = levels.last().unwrap().downsampled(channels) {
+            levels.push(level);
+        }
+
+        Self {
+            levels,
+            channels,
+            filter: Filter::Trilinear,
+            wrap: Wrap::Clamp,
+        }
+    }
+
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Self { filter, ..self }
+    }
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self { wrap, ..self }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn mip_level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Color of the full-size texture at the given UV
     pub fn get_texel(&self, u: f64, v: f64) -> Color {
-        let x = (((self.width - 1) as f64) * u) as u32;
-        let y = (((self.height - 1) as f64) * v) as u32;
-        let pixel = self.img.get_pixel(x, y);
+        self.sample(UV { u, v }, UvDerivatives::default())
+    }
 
+    /// Color of the whole texture, taken from its 1x1 mip level
+    pub fn average_color(&self) -> Color {
+        let level = self.levels.last().unwrap();
+        let rgba = self.texel(level, 0, 0);
         Color {
-            r: pixel[0],
-            g: pixel[1],
-            b: pixel[2],
+            r: rgba[0].round() as u8,
+            g: rgba[1].round() as u8,
+            b: rgba[2].round() as u8,
+        }
+    }
+
+    /// Filtered color at the given UV, with the mip level selected by the UV derivatives
+    pub fn sample(&self, uv: UV, derivatives: UvDerivatives) -> Color {
+        let rgba = self.filtered(uv, derivatives);
+        Color {
+            r: rgba[0].round() as u8,
+            g: rgba[1].round() as u8,
+            b: rgba[2].round() as u8,
+        }
+    }
+
+    /// Filtered alpha from 0 to 1 at the given UV; 1 for RGB textures
+    pub fn sample_alpha(&self, uv: UV, derivatives: UvDerivatives) -> f64 {
+        if self.channels < 4 {
+            return 1.0;
+        }
+        self.filtered(uv, derivatives)[3] / 255.0
+    }
+
+    fn filtered(&self, uv: UV, derivatives: UvDerivatives) -> [f64; 4] {
+        let lod = self.level_of_detail(derivatives);
+        match self.filter {
+            Filter::Nearest => self.nearest(lod.round() as usize, uv),
+            Filter::Bilinear => self.bilinear(lod.round() as usize, uv),
+            Filter::Trilinear => {
+                let level = lod.floor() as usize;
+                let fine = self.bilinear(level, uv);
+                if level + 1 == self.levels.len() {
+                    fine
+                } else {
+                    lerp(fine, self.bilinear(level + 1, uv), lod.fract())
+                }
+            }
+        }
+    }
+
+    /// Mip level, possibly fractional, where one canvas point covers about one texel
+    fn level_of_detail(&self, derivatives: UvDerivatives) -> f64 {
+        let width = self.width() as f64;
+        let height = self.height() as f64;
+        let footprint = |d: UV| (d.u * width).hypot(d.v * height);
+
+        let texels_per_point = footprint(derivatives.dx).max(footprint(derivatives.dy));
+        if texels_per_point > 1.0 {
+            texels_per_point.log2().min((self.levels.len() - 1) as f64)
+        } else {
+            0.0
         }
     }
+
+    fn nearest(&self, level: usize, uv: UV) -> [f64; 4] {
+        let level = &self.levels[level];
+        let x = (uv.u * level.width as f64).floor() as i64;
+        let y = (uv.v * level.height as f64).floor() as i64;
+        self.texel(level, x, y)
+    }
+
+    fn bilinear(&self, level: usize, uv: UV) -> [f64; 4] {
+        let level = &self.levels[level];
+        // texel centers are at half-integer coordinates
+        let x = uv.u * level.width as f64 - 0.5;
+        let y = uv.v * level.height as f64 - 0.5;
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let (tx, ty) = (x - x.floor(), y - y.floor());
+
+        let bottom = lerp(self.texel(level, x0, y0), self.texel(level, x0 + 1, y0), tx);
+        let top = lerp(
+            self.texel(level, x0, y0 + 1),
+            self.texel(level, x0 + 1, y0 + 1),
+            tx,
+        );
+        lerp(bottom, top, ty)
+    }
+
+    /// RGBA of the texel with the given coordinates, wrapped into the level
+    fn texel(&self, level: &MipLevel, x: i64, y: i64) -> [f64; 4] {
+        let x = wrap_coordinate(x, level.width, self.wrap);
+        let y = wrap_coordinate(y, level.height, self.wrap);
+        let offset = (y * level.width + x) * self.channels;
+
+        let mut rgba = [255.0; 4];
+        for (channel, &value) in level.texels[offset..offset + self.channels].iter().enumerate() {
+            rgba[channel] = value as f64;
+        }
+        rgba
+    }
+}
+
+impl MipLevel {
+    /// The next level of the chain, averaging 2x2 blocks of texels; `None` for a 1x1 level
+    fn downsampled(&self, channels: usize) -> Option<MipLevel> {
+        if self.width == 1 && self.height == 1 {
+            return None;
+        }
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let mut texels = Vec::with_capacity(width * height * channels);
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..channels {
+                    let mut sum = 0;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        // a side of size 1 is not halved, so the block repeats its texels
+                        let source_x = (2 * x + dx).min(self.width - 1);
+                        let source_y = (2 * y + dy).min(self.height - 1);
+                        sum += self.texels[(source_y * self.width + source_x) * channels + channel] as u32;
+                    }
+                    texels.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        Some(MipLevel { width, height, texels })
+    }
+}
+
+fn wrap_coordinate(coordinate: i64, size: usize, wrap: Wrap) -> usize {
+    let size = size as i64;
+    let wrapped = match wrap {
+        Wrap::Repeat => coordinate.rem_euclid(size),
+        Wrap::Clamp => coordinate.clamp(0, size - 1),
+        Wrap::Mirror => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size {
+                period
+            } else {
+                2 * size - 1 - period
+            }
+        }
+    };
+    wrapped as usize
+}
+
+fn lerp(a: [f64; 4], b: [f64; 4], t: f64) -> [f64; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
 }
 
 pub fn load_from_file(filename: &str) -> Texture {
-    let img = image::open(filename).unwrap();
+    try_load_from_file(filename).unwrap()
+}
+
+/// Like `load_from_file`, returning the error instead of panicking
+pub fn try_load_from_file(path: impl AsRef<Path>) -> ImageResult<Texture> {
+    let img = image::open(path)?;
     let (width, height) = img.dimensions();
+    let (width, height) = (width as usize, height as usize);
+
+    Ok(match img {
+        DynamicImage::ImageLumaA8(_) | DynamicImage::ImageRgba8(_) | DynamicImage::ImageBgra8(_) => {
+            Texture::from_rgba(width, height, img.to_rgba().into_raw())
+        }
+        _ => Texture::from_rgb(width, height, img.to_rgb().into_raw()),
+    })
+}
+
+#[cfg(test)]
+fn checkerboard(size: usize) -> Texture {
+    let mut texels = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            let value = if (x + y) % 2 == 0 { 255 } else { 0 };
+            texels.extend([value, value, value]);
+        }
+    }
+    Texture::from_rgb(size, size, texels)
+}
+
+#[cfg(test)]
+fn red(color: Color) -> u8 {
+    color.r
+}
+
+#[test]
+fn test_mip_chain() {
+    let texture = checkerboard(8);
+    assert_eq!(texture.mip_level_count(), 4);
+
+    // every level averages the checkerboard to gray
+    for level in &texture.levels[1..] {
+        assert!(level.texels.iter().all(|&value| value == 128));
+    }
+
+    assert_eq!(red(checkerboard(8).average_color()), 128);
+
+    let texture = Texture::from_rgba(4, 1, vec![0; 16]);
+    let sizes: Vec<_> = texture.levels.iter().map(|level| (level.width, level.height)).collect();
+    assert_eq!(sizes, vec![(4, 1), (2, 1), (1, 1)]);
+}
+
+#[test]
+fn test_wrap_coordinate() {
+    let wrapped = |wrap| (-3..7).map(|x| wrap_coordinate(x, 3, wrap)).collect::<Vec<_>>();
+    assert_eq!(wrapped(Wrap::Repeat), vec![0, 1, 2, 0, 1, 2, 0, 1, 2, 0]);
+    assert_eq!(wrapped(Wrap::Clamp), vec![0, 0, 0, 0, 1, 2, 2, 2, 2, 2]);
+    assert_eq!(wrapped(Wrap::Mirror), vec![2, 1, 0, 0, 1, 2, 2, 1, 0, 0]);
+}
+
+#[test]
+fn test_filters() {
+    // a black and a white texel, clamped by default
+    let texture = Texture::from_rgb(2, 1, vec![0, 0, 0, 255, 255, 255]);
+    assert_eq!(texture.wrap, Wrap::Clamp);
+    let at = |texture: &Texture, u| red(texture.get_texel(u, 0.5));
+
+    let nearest = texture.with_filter(Filter::Nearest);
+    assert_eq!(
+        (at(&nearest, 0.1), at(&nearest, 0.6), at(&nearest, 1.5)),
+        (0, 255, 255)
+    );
+
+    let bilinear = nearest.with_filter(Filter::Bilinear);
+    assert_eq!(
+        (at(&bilinear, 0.25), at(&bilinear, 0.5), at(&bilinear, 0.75)),
+        (0, 128, 255)
+    );
+
+    // repeating blends the last texel with the first one
+    let repeated = bilinear.with_wrap(Wrap::Repeat);
+    assert_eq!(at(&repeated, 0.0), 128);
+}
+
+#[test]
+fn test_level_of_detail() {
+    let texture = checkerboard(8);
+    let uv = UV { u: 0.0625, v: 0.0625 };
+    let derivatives = |step| UvDerivatives {
+        dx: UV { u: step, v: 0.0 },
+        dy: UV { u: 0.0, v: step },
+    };
+
+    // one texel per canvas point samples the full-size texture
+    assert_eq!(texture.level_of_detail(derivatives(0.125)), 0.0);
+    assert_eq!(red(texture.sample(uv, derivatives(0.125))), 255);
+
+    // the whole texture in one canvas point samples the 1x1 level
+    assert_eq!(texture.level_of_detail(derivatives(1.0)), 3.0);
+    assert_eq!(red(texture.sample(uv, derivatives(1.0))), 128);
+
+    // trilinear filtering blends the neighbouring levels
+    let halfway = derivatives(0.125 * 2f64.sqrt());
+    assert!((texture.level_of_detail(halfway) - 0.5).abs() < 1e-9);
+    assert_eq!(red(texture.sample(uv, halfway)), 192);
+}
+
+#[test]
+fn test_sample_alpha() {
+    let opaque = Texture::from_rgb(1, 1, vec![10, 20, 30]);
+    assert_eq!(
+        opaque.sample_alpha(UV { u: 0.5, v: 0.5 }, UvDerivatives::default()),
+        1.0
+    );
 
-    Texture { img, width, height }
+    // an opaque and a fully transparent texel
+    let texture = Texture::from_rgba(2, 1, vec![0, 0, 0, 255, 0, 0, 0, 0]).with_wrap(Wrap::Clamp);
+    assert_eq!(
+        texture.sample_alpha(UV { u: 0.5, v: 0.5 }, UvDerivatives::default()),
+        0.5
+    );
+    assert_eq!(
+        texture.sample_alpha(UV { u: 1.0, v: 0.5 }, UvDerivatives::default()),
+        0.0
+    );
 }
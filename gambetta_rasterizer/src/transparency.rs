@@ -0,0 +1,109 @@
+//! Weighted blended order-independent transparency (McGuire and Bavoil, 2013).
+//!
+//! Transparent fragments are accumulated in any order: their colors are summed with weights
+//! growing with opacity and closeness to the camera, and their transparencies are multiplied
+//! into the part of the background that stays visible (revealage). The weighted average color
+//! is then composited over the opaque picture once, as if it was a single layer.
+
+use crate::buffer_canvas::CanvasTile;
+use common::{Color, Pixel};
+
+/// Accumulation buffers for the transparent fragments of one canvas tile
+pub struct WeightedBlendedTile {
+    width: usize,
+    first_row: usize,
+    /// Premultiplied color and alpha, each multiplied by the weight of the fragment
+    accumulated: Vec<[f64; 4]>,
+    revealage: Vec<f64>,
+}
+
+impl WeightedBlendedTile {
+    pub fn new(tile: &CanvasTile) -> Self {
+        let pixel_count = tile.width * tile.rows();
+        Self {
+            width: tile.width,
+            first_row: tile.first_row,
+            accumulated: vec![[0.0; 4]; pixel_count],
+            revealage: vec![1.0; pixel_count],
+        }
+    }
+
+    /// Adds a fragment with the given opacity at the given distance `z` from the camera
+    pub fn accumulate(&mut self, pixel: Pixel, alpha: f64, z: f64) {
+        let index = (pixel.y - self.first_row) * self.width + pixel.x;
+        let weight = alpha * weight(z);
+        let color = pixel.color;
+
+        let accumulated = &mut self.accumulated[index];
+        accumulated[0] += color.r as f64 * alpha * weight;
+        accumulated[1] += color.g as f64 * alpha * weight;
+        accumulated[2] += color.b as f64 * alpha * weight;
+        accumulated[3] += alpha * weight;
+        self.revealage[index] *= 1.0 - alpha;
+    }
+
+    /// Composites the accumulated fragments over the picture in the tile
+    pub fn resolve(&self, tile: &mut CanvasTile) {
+        for (index, (accumulated, &revealage)) in self.accumulated.iter().zip(&self.revealage).enumerate() {
+            if revealage == 1.0 {
+                continue;
+            }
+            let x = index % self.width;
+            let y = self.first_row + index / self.width;
+            let average =
+                |channel: usize| (accumulated[channel] / accumulated[3]).round().clamp(0.0, 255.0) as u8;
+            let color = Color { r: average(0), g: average(1), b: average(2) };
+            tile.blend_pixel(Pixel { x, y, color }, 1.0 - revealage);
+        }
+    }
+}
+
+/// Depth weight, equation (9) of the paper, for distances of up to a few hundred units
+fn weight(z: f64) -> f64 {
+    (10.0 / (1e-5 + (z / 5.0).powi(2) + (z / 200.0).powi(6))).clamp(1e-2, 3e3)
+}
+
+#[test]
+fn test_single_layer_matches_blending() {
+    use crate::BufferCanvas;
+
+    let background = Color { r: 200, g: 100, b: 0 };
+    let glass = Pixel { x: 2, y: 1, color: Color { r: 0, g: 0, b: 255 } };
+    let mut blended = BufferCanvas::new(4, 4);
+    let mut accumulated = BufferCanvas::new(4, 4);
+
+    let mut tiles = blended.tiles(4);
+    tiles[0].put_pixel(Pixel { color: background, ..glass });
+    tiles[0].blend_pixel(glass, 0.25);
+
+    let mut tiles = accumulated.tiles(4);
+    tiles[0].put_pixel(Pixel { color: background, ..glass });
+    let mut oit = WeightedBlendedTile::new(&tiles[0]);
+    oit.accumulate(glass, 0.25, 3.0);
+    oit.resolve(&mut tiles[0]);
+
+    assert_eq!(blended.buffer, accumulated.buffer);
+}
+
+#[test]
+fn test_accumulation_order_does_not_matter() {
+    use crate::BufferCanvas;
+
+    let red = Pixel { x: 0, y: 0, color: Color { r: 255, g: 0, b: 0 } };
+    let green = Pixel { x: 0, y: 0, color: Color { r: 0, g: 255, b: 0 } };
+    let render = |layers: [(Pixel, f64); 2]| {
+        let mut canvas = BufferCanvas::new(2, 2);
+        let mut tiles = canvas.tiles(2);
+        let mut oit = WeightedBlendedTile::new(&tiles[0]);
+        for (pixel, z) in layers {
+            oit.accumulate(pixel, 0.5, z);
+        }
+        oit.resolve(&mut tiles[0]);
+        canvas.buffer
+    };
+
+    let front_to_back = render([(red, 2.0), (green, 8.0)]);
+    assert_eq!(front_to_back, render([(green, 8.0), (red, 2.0)]));
+    // the closer layer dominates
+    assert!(front_to_back[0] > front_to_back[1]);
+}
@@ -0,0 +1,475 @@
+//! Edges of the instances for the wireframe, hidden-line and overlay rendering modes.
+//!
+//! Edges are found in the models rather than in the clipped triangles, so the diagonals the
+//! clipping adds never show up, and an edge shared by two triangles is drawn once. Every edge
+//! is clipped by the view volume on its own and drawn as a line with the depth interpolated
+//! along it. For the hidden-line and overlay modes the lines are depth tested against a depth
+//! buffer of the surfaces, which are pushed back by a bias growing with their slope in depth,
+//! like with a polygon offset, so the edges of a surface are not hidden by the surface itself.
+
+use crate::buffer_canvas::BufferCanvas;
+use crate::camera::Camera;
+use crate::model::Model;
+use crate::scanline::{rasterize_depth, screen_derivatives};
+use crate::vector4f::Vector4f;
+use crate::{
+    face_visible_4f, is_point_in_canvas, Instance, Point, RenderingSettings, Triangle4f, ViewVolume,
+    CLIP_SPACE_PLANES, TILE_ROWS,
+};
+use common::{vectors, Color, Pixel, Vector3f};
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Surfaces are pushed back by this fraction of their depth in addition to the slope-scaled bias,
+/// so the edges of surfaces facing the camera are not lost to rounding
+const MIN_DEPTH_OFFSET: f64 = 1e-6;
+
+/// Which edges of the models are drawn
+#[derive(Copy, Clone)]
+pub enum Edges {
+    /// Every edge of the triangles, including the diagonals of triangulated polygons
+    All,
+    /// Edges between faces meeting at an angle of more than `min_angle` degrees, silhouette edges
+    /// between a face turned to the camera and one turned away, and edges with one face or more than two
+    Feature { min_angle: f64 },
+}
+
+#[derive(Copy, Clone)]
+pub struct WireframeSettings {
+    pub edges: Edges,
+    /// How far the surfaces are pushed back for the depth test of the edges, in pixels:
+    /// multiplied by the largest change of the depth from one pixel to the next across a surface
+    pub depth_bias: f64,
+}
+
+impl Default for WireframeSettings {
+    fn default() -> Self {
+        Self { edges: Edges::All, depth_bias: 2.0 }
+    }
+}
+
+/// Edge of a model between two of its vertices
+struct Edge {
+    vertices: [usize; 2],
+    /// Triangles that have the edge
+    faces: Vec<usize>,
+}
+
+/// Edges of a model, found once rather than every frame
+#[derive(Default)]
+pub struct EdgeCache(OnceLock<Vec<Edge>>);
+
+fn model_edges<'m>(model: &'m Model) -> &'m [Edge] {
+    model.edges.0.get_or_init(|| find_edges(model))
+}
+
+/// Edges of the triangles of the model. Vertices at the same position are the same vertex,
+/// so triangles with their own copies of the vertices, for their normals or UVs, share edges.
+fn find_edges(model: &Model) -> Vec<Edge> {
+    let key = |index: usize| {
+        let vertex = model.vertices[index];
+        [vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()]
+    };
+
+    let mut edges = Vec::<Edge>::new();
+    let mut edge_indexes = HashMap::<[[u64; 3]; 2], usize>::new();
+    for (face, triangle) in model.triangles.iter().enumerate() {
+        let [a, b, c] = triangle.indexes;
+        for vertices in [[a, b], [b, c], [c, a]] {
+            let (start, end) = (key(vertices[0]), key(vertices[1]));
+            if start == end {
+                continue;
+            }
+            let edge_key = if start < end { [start, end] } else { [end, start] };
+            match edge_indexes.entry(edge_key) {
+                Entry::Occupied(entry) => edges[*entry.get()].faces.push(face),
+                Entry::Vacant(entry) => {
+                    entry.insert(edges.len());
+                    edges.push(Edge { vertices, faces: vec![face] });
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Edges of the instance to be drawn, with their ends in camera space
+fn instance_segments(
+    instance: &Instance,
+    rendering_settings: &RenderingSettings,
+    camera: &dyn Camera,
+) -> Vec<[Vector4f; 2]> {
+    let model = instance.model;
+    let instance_transform = instance.transform().multiply(camera.camera_transform());
+    let combined_normal_transform = instance.normal_transform().multiply(camera.rotation_transform());
+
+    let vertices: Vec<Vector4f> = model
+        .vertices
+        .iter()
+        .map(|vertex| Vector4f::from(vertex).transform(instance_transform))
+        .collect();
+    // normals of the triangles in camera space and whether they are turned to the camera
+    let faces: Vec<(Vector3f, bool)> = model
+        .triangles
+        .iter()
+        .map(|triangle| {
+            let normal: Vector3f =
+                Vector4f::from(triangle.calculated_normal).transform(combined_normal_transform).into();
+            let view_direction = camera.view_direction(vertices[triangle.indexes[0]].into());
+            (
+                vectors::normalize(normal),
+                face_visible_4f(view_direction, normal),
+            )
+        })
+        .collect();
+
+    model_edges(model)
+        .iter()
+        .filter(|edge| {
+            let front = edge.faces.iter().any(|&face| faces[face].1);
+            if rendering_settings.backface_culling && !front {
+                return false;
+            }
+            match (rendering_settings.wireframe.edges, edge.faces.as_slice()) {
+                (Edges::All, _) => true,
+                (Edges::Feature { min_angle }, &[first, second]) => {
+                    let ((first_normal, first_front), (second_normal, second_front)) =
+                        (faces[first], faces[second]);
+                    first_front != second_front
+                        || vectors::dot_product(first_normal, second_normal) < min_angle.to_radians().cos()
+                }
+                (Edges::Feature { .. }, _) => true,
+            }
+        })
+        .map(|edge| edge.vertices.map(|index| vertices[index]))
+        .collect()
+}
+
+/// Part of the segment in camera space inside the view volume
+fn clip_segment(segment: [Vector4f; 2], view_volume: &ViewVolume) -> Option<[Vector4f; 2]> {
+    let [mut start, mut end] = segment;
+    let plane_count = match view_volume {
+        ViewVolume::Planes(planes) => planes.len(),
+        ViewVolume::ClipSpace(_) => CLIP_SPACE_PLANES.len(),
+    };
+    // distance of the point to a plane of the view volume, non-negative inside it
+    let distance = |plane: usize, point: Vector4f| match view_volume {
+        ViewVolume::Planes(planes) => planes[plane].signed_distance(point.into()),
+        ViewVolume::ClipSpace(projection) => {
+            let [a, b, c, d] = CLIP_SPACE_PLANES[plane];
+            let point = point.transform(*projection);
+            a * point.x + b * point.y + c * point.z + d * point.w
+        }
+    };
+
+    for plane in 0..plane_count {
+        let (start_distance, end_distance) = (distance(plane, start), distance(plane, end));
+        if start_distance < 0.0 && end_distance < 0.0 {
+            return None;
+        }
+        // the projection is linear, so the point on the plane is the same in camera and clip space
+        if start_distance < 0.0 {
+            start = lerp(start, end, start_distance / (start_distance - end_distance));
+        } else if end_distance < 0.0 {
+            end = lerp(end, start, end_distance / (end_distance - start_distance));
+        }
+    }
+    Some([start, end])
+}
+
+fn lerp(a: Vector4f, b: Vector4f, t: f64) -> Vector4f {
+    Vector4f {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+        w: a.w + (b.w - a.w) * t,
+    }
+}
+
+/// Canvas point of the vertex in camera space and its value for the depth buffer,
+/// the same as the triangles get in `ScreenTriangle`
+fn project(
+    vertex: Vector4f,
+    camera: &dyn Camera,
+    view_volume: &ViewVolume,
+    canvas: &BufferCanvas,
+) -> (Point, f64) {
+    match view_volume {
+        ViewVolume::Planes(_) => {
+            let projected = camera.project(vertex.into());
+            (canvas.viewport_to_canvas(projected, camera), projected.depth)
+        }
+        ViewVolume::ClipSpace(projection) => {
+            let vertex = vertex.transform(*projection);
+            let point = Point {
+                x: (vertex.x / vertex.w * canvas.width as f64 / 2.0) as i32,
+                y: (vertex.y / vertex.w * canvas.height as f64 / 2.0) as i32,
+                h: 1.0,
+                z: vertex.w,
+            };
+            (point, vertex.z / vertex.w)
+        }
+    }
+}
+
+/// Depth buffer of the triangles in camera space, pushed back by the depth bias of the settings
+pub fn surface_depths<'t>(
+    triangles: impl Iterator<Item = &'t Triangle4f>,
+    rendering_settings: &RenderingSettings,
+    camera: &dyn Camera,
+    view_volume: &ViewVolume,
+    canvas: &BufferCanvas,
+) -> Vec<f64> {
+    let depth_bias = rendering_settings.wireframe.depth_bias;
+    let triangles: Vec<([Point; 3], [f64; 3])> = triangles
+        .map(|triangle| {
+            let projected = [triangle.a, triangle.b, triangle.c]
+                .map(|vertex| project(vertex, camera, view_volume, canvas));
+            let points = projected.map(|(point, _)| point);
+            let depths = projected.map(|(_, depth)| depth);
+            // greater is closer, so the surface is pushed back by decreasing its depth
+            let (dx, dy) = screen_derivatives(&points, depths);
+            let offset = depth_bias * dx.abs().max(dy.abs());
+            (
+                points,
+                depths.map(|depth| depth - offset - depth.abs() * MIN_DEPTH_OFFSET),
+            )
+        })
+        .collect();
+
+    let mut surfaces = BufferCanvas::new(canvas.width, canvas.height);
+    surfaces.tiles(TILE_ROWS).into_par_iter().for_each(|mut tile| {
+        let rows = tile.canvas_y_range();
+        for &(points, depths) in &triangles {
+            rasterize_depth(points, depths, rows.clone(), |x, y, depth| {
+                let (x, y) = (tile.screen_x(x), tile.screen_y(y));
+                tile.update_depth_buffer_if_closer(x, y, depth);
+            });
+        }
+    });
+    surfaces.depth_buffer
+}
+
+/// Draws the edges of the instances in their wireframe colors. With the depth buffer of the surfaces
+/// only the points of the edges in front of them are drawn, otherwise the edges are drawn through.
+pub fn draw_edges(
+    instances: &[&Instance],
+    surfaces: Option<&[f64]>,
+    rendering_settings: &RenderingSettings,
+    camera: &dyn Camera,
+    view_volume: &ViewVolume,
+    canvas: &mut BufferCanvas,
+) {
+    for instance in instances {
+        let color = instance.wireframe_color();
+        for segment in instance_segments(instance, rendering_settings, camera) {
+            if let Some(segment) = clip_segment(segment, view_volume) {
+                let [start, end] = segment.map(|vertex| project(vertex, camera, view_volume, canvas));
+                draw_segment(start, end, color, surfaces, canvas);
+            }
+        }
+    }
+}
+
+/// Draws the line between the canvas points, both ends included, with the depth interpolated
+/// linearly along it; that is how the depth changes across the triangles in screen space as well
+fn draw_segment(
+    (start, start_depth): (Point, f64),
+    (end, end_depth): (Point, f64),
+    color: Color,
+    surfaces: Option<&[f64]>,
+    canvas: &mut BufferCanvas,
+) {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let steps = dx.abs().max(dy.abs());
+    for step in 0..=steps {
+        let t = if steps == 0 {
+            0.0
+        } else {
+            step as f64 / steps as f64
+        };
+        let point = Point {
+            x: start.x + (dx as f64 * t).round() as i32,
+            y: start.y + (dy as f64 * t).round() as i32,
+            h: 1.0,
+            z: 0.0,
+        };
+        if !is_point_in_canvas(point, canvas) {
+            continue;
+        }
+        let (x, y) = (canvas.screen_x(point.x), canvas.screen_y(point.y));
+        let depth = start_depth + (end_depth - start_depth) * t;
+        if surfaces.is_some_and(|surfaces| depth < surfaces[y * canvas.width + x]) {
+            continue;
+        }
+        canvas.put_pixel(Pixel { x, y, color });
+    }
+}
+
+#[cfg(test)]
+fn render_cube(
+    rendering_mode: crate::RenderingMode,
+    edges: Edges,
+    rotation: Vector3f,
+    backface_culling: bool,
+    cover: Option<crate::material::AlphaMode>,
+) -> (BufferCanvas, Vec<Point>) {
+    use crate::{
+        model, render_scene, Clipping, Material, Matrix44f, ProjectiveCamera, ShadingModel, Transparency,
+    };
+    use common::Light;
+
+    let cube = model::cube(2.0);
+    let instance = Instance::new(&cube, Vector3f { x: 0.0, y: 0.0, z: 5.0 }, 1.0, rotation)
+        .with_wireframe_color(Color { r: 255, g: 0, b: 0 });
+    // a square in front of the cube covering the whole view, with the material in the given mode
+    let cover = cover.map(|alpha_mode| {
+        model::square(
+            4.0,
+            Material::diffuse(Color { r: 0, g: 0, b: 255 }).with_alpha_mode(alpha_mode),
+        )
+    });
+    let mut instances = vec![instance];
+    if let Some(cover) = &cover {
+        instances.push(
+            Instance::new(
+                cover,
+                Vector3f { x: 0.0, y: 0.0, z: 3.0 },
+                1.0,
+                Vector3f::zero_vector(),
+            )
+            .with_wireframe_color(Color { r: 0, g: 0, b: 0 }),
+        );
+    }
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 1.0,
+        far_distance: 100.0,
+        aspect_ratio: 1.0,
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+    let settings = RenderingSettings {
+        rendering_mode,
+        shading_model: ShadingModel::Flat,
+        show_normals: false,
+        backface_culling,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: None,
+        wireframe: WireframeSettings { edges, ..WireframeSettings::default() },
+    };
+    let mut canvas = BufferCanvas::new(100, 100);
+    render_scene(
+        &instances,
+        &vec![Light::Ambient { intensity: 0.5 }],
+        &camera,
+        &settings,
+        &mut canvas,
+    );
+
+    // canvas points of the vertices of the cube, from the closest to the farthest
+    let instance = Instance::new(&cube, Vector3f { x: 0.0, y: 0.0, z: 5.0 }, 1.0, rotation);
+    let mut vertices: Vec<Vector3f> = cube
+        .vertices
+        .iter()
+        .map(|vertex| Vector4f::from(vertex).transform(instance.transform()).into())
+        .collect();
+    vertices.sort_by(|a, b| a.z.total_cmp(&b.z));
+    let points = vertices
+        .into_iter()
+        .map(|vertex| canvas.viewport_to_canvas(camera.project(vertex), &camera))
+        .collect();
+    (canvas, points)
+}
+
+#[cfg(test)]
+fn color_at(canvas: &BufferCanvas, point: Point) -> [u8; 3] {
+    let offset = (canvas.screen_y(point.y) * canvas.width + canvas.screen_x(point.x)) * 3;
+    [canvas.buffer[offset], canvas.buffer[offset + 1], canvas.buffer[offset + 2]]
+}
+
+#[test]
+fn test_hidden_line_and_overlay() {
+    use crate::RenderingMode;
+
+    let red = [255, 0, 0];
+    let rotation = Vector3f { x: 30.0, y: 30.0, z: 0.0 };
+    // the farthest corner of the cube is behind its front faces
+    let (wireframe, points) = render_cube(RenderingMode::Wireframe, Edges::All, rotation, false, None);
+    let (closest, farthest) = (points[0], points[7]);
+    assert_eq!(color_at(&wireframe, closest), red);
+    assert_eq!(color_at(&wireframe, farthest), red);
+
+    let (hidden_line, _) = render_cube(RenderingMode::HiddenLine, Edges::All, rotation, false, None);
+    assert_eq!(color_at(&hidden_line, closest), red);
+    assert_eq!(color_at(&hidden_line, farthest), [0, 0, 0]);
+    let red_pixels = |canvas: &BufferCanvas| canvas.buffer.chunks(3).filter(|pixel| *pixel == red).count();
+    assert!(red_pixels(&hidden_line) < red_pixels(&wireframe));
+
+    // the edges are drawn over the surfaces, which stay in the depth buffer as they are
+    let (overlay, _) = render_cube(RenderingMode::Overlay, Edges::All, rotation, false, None);
+    let (filled, _) = render_cube(RenderingMode::Filled, Edges::All, rotation, false, None);
+    assert_eq!(color_at(&overlay, closest), red);
+    assert_eq!(color_at(&overlay, farthest), color_at(&filled, farthest));
+    assert!(color_at(&filled, farthest) != [0, 0, 0]);
+    assert!(overlay.depth_buffer == filled.depth_buffer);
+}
+
+#[test]
+fn test_feature_edges() {
+    use crate::RenderingMode;
+
+    let center = Point { x: 0, y: 0, h: 1.0, z: 0.0 };
+    // the front face of the cube is split by a diagonal through its center
+    let (all, points) = render_cube(
+        RenderingMode::Wireframe,
+        Edges::All,
+        Vector3f::zero_vector(),
+        true,
+        None,
+    );
+    assert_eq!(color_at(&all, center), [255, 0, 0]);
+
+    // the faces on both sides of the diagonal are in the same plane, while the outline
+    // of the front face is a silhouette
+    let feature = Edges::Feature { min_angle: 30.0 };
+    let (feature, _) = render_cube(
+        RenderingMode::Wireframe,
+        feature,
+        Vector3f::zero_vector(),
+        true,
+        None,
+    );
+    assert_eq!(color_at(&feature, center), [0, 0, 0]);
+    for &corner in &points[..4] {
+        assert_eq!(color_at(&feature, corner), [255, 0, 0]);
+    }
+}
+
+#[test]
+fn test_cutouts_do_not_hide_edges() {
+    use crate::material::AlphaMode;
+    use crate::RenderingMode;
+
+    // the edges behind a cutout may be seen through its holes, which the depth test can't tell
+    let rotation = Vector3f { x: 30.0, y: 30.0, z: 0.0 };
+    let opaque = Some(AlphaMode::Opaque);
+    let (opaque, points) = render_cube(RenderingMode::HiddenLine, Edges::All, rotation, false, opaque);
+    assert_eq!(color_at(&opaque, points[0]), [0, 0, 0]);
+    let mask = Some(AlphaMode::Mask { cutoff: 0.5 });
+    let (cutout, _) = render_cube(RenderingMode::HiddenLine, Edges::All, rotation, false, mask);
+    assert_eq!(color_at(&cutout, points[0]), [255, 0, 0]);
+}
+
+#[test]
+fn test_edges_are_found_once() {
+    let cube = crate::model::cube(2.0);
+    let edges = model_edges(&cube);
+    // the outlines of the faces and the diagonals splitting them into triangles
+    assert_eq!(edges.len(), 18);
+    assert!(std::ptr::eq(edges, model_edges(&cube)));
+}
@@ -0,0 +1,273 @@
+//! Шумоподавление для кадров с малым числом лучей на пиксель.
+//!
+//! Реализован edge-avoiding à-trous wavelet фильтр (Dammertz et al., 2010): несколько проходов
+//! 5x5 B3-сплайн ядра с "дырами" шагом 2^i. Веса соседей дополнительно зависят от разницы
+//! цвета, нормалей, альбедо и глубины, поэтому фильтр не размывает границы объектов.
+//! Перед фильтрацией цвет делится на альбедо, чтобы сглаживалось только освещение,
+//! а текстуры и цвета поверхностей оставались резкими.
+
+use crate::{FrameBuffers, HdrColor};
+use common::vectors;
+use common::Vector3f;
+use rayon::prelude::*;
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Альбедо меньше этого значения (в единицах 0..255) не используется для демодуляции
+const MIN_ALBEDO: f64 = 1.0;
+
+#[derive(Copy, Clone)]
+pub struct DenoiseSettings {
+    /// Количество проходов; радиус фильтра растёт как 2^iterations
+    pub iterations: u32,
+    /// Чувствительность к разнице освещённости (после деления на альбедо)
+    pub color_sigma: f64,
+    /// Степень, в которую возводится косинус угла между нормалями
+    pub normal_power: f64,
+    /// Допустимая относительная разница глубины на один шаг ядра
+    pub depth_sigma: f64,
+    /// Чувствительность к разнице альбедо (в долях от 0..1)
+    pub albedo_sigma: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 4,
+            color_sigma: 0.5,
+            normal_power: 64.0,
+            depth_sigma: 0.05,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Возвращает отфильтрованный HDR цвет кадра, вспомогательные буферы не меняются
+pub fn denoise(frame: &FrameBuffers, settings: &DenoiseSettings) -> Vec<HdrColor> {
+    let size = frame.size;
+
+    let mut illumination: Vec<HdrColor> = frame
+        .color
+        .iter()
+        .zip(frame.albedo.iter())
+        .map(|(color, albedo)| demodulate(*color, *albedo))
+        .collect();
+
+    for iteration in 0..settings.iterations {
+        let step = 1usize << iteration;
+        let color_sigma = settings.color_sigma / (1u64 << iteration) as f64;
+        let mut filtered = vec![HdrColor::black(); size * size];
+
+        filtered.par_chunks_mut(size.max(1)).enumerate().for_each(|(y, row)| {
+            for (x, output) in row.iter_mut().enumerate() {
+                *output = filter_pixel(frame, &illumination, x, y, step, color_sigma, settings);
+            }
+        });
+
+        illumination = filtered;
+    }
+
+    illumination
+        .iter()
+        .zip(frame.albedo.iter())
+        .map(|(color, albedo)| remodulate(*color, *albedo))
+        .collect()
+}
+
+fn filter_pixel(
+    frame: &FrameBuffers,
+    illumination: &[HdrColor],
+    x: usize,
+    y: usize,
+    step: usize,
+    color_sigma: f64,
+    settings: &DenoiseSettings,
+) -> HdrColor {
+    let size = frame.size as i64;
+    let center = y * frame.size + x;
+    let center_color = illumination[center];
+
+    let mut sum = HdrColor::black();
+    let mut weight_sum = 0.0;
+
+    for (ky, kernel_y) in KERNEL.iter().enumerate() {
+        let sample_y = y as i64 + (ky as i64 - 2) * step as i64;
+        if sample_y < 0 || sample_y >= size {
+            continue;
+        }
+
+        for (kx, kernel_x) in KERNEL.iter().enumerate() {
+            let sample_x = x as i64 + (kx as i64 - 2) * step as i64;
+            if sample_x < 0 || sample_x >= size {
+                continue;
+            }
+
+            let sample = (sample_y * size + sample_x) as usize;
+            let sample_color = illumination[sample];
+
+            let weight = kernel_x
+                * kernel_y
+                * color_weight(center_color, sample_color, color_sigma)
+                * geometry_weight(frame, center, sample, step, settings)
+                * albedo_weight(frame.albedo[center], frame.albedo[sample], settings.albedo_sigma);
+
+            sum = sum + sample_color.scale(weight);
+            weight_sum += weight;
+        }
+    }
+
+    if weight_sum > 0.0 {
+        sum.scale(1.0 / weight_sum)
+    } else {
+        center_color
+    }
+}
+
+fn color_weight(center: HdrColor, sample: HdrColor, sigma: f64) -> f64 {
+    let dr = center.r - sample.r;
+    let dg = center.g - sample.g;
+    let db = center.b - sample.b;
+    (-(dr * dr + dg * dg + db * db) / (sigma * sigma)).exp()
+}
+
+fn geometry_weight(
+    frame: &FrameBuffers,
+    center: usize,
+    sample: usize,
+    step: usize,
+    settings: &DenoiseSettings,
+) -> f64 {
+    let center_depth = frame.depth[center];
+    let sample_depth = frame.depth[sample];
+
+    // фон смешивается только с фоном
+    match (center_depth.is_finite(), sample_depth.is_finite()) {
+        (false, false) => return 1.0,
+        (true, true) => {}
+        _ => return 0.0,
+    }
+
+    let normal_weight = normal_weight(frame.normal[center], frame.normal[sample], settings.normal_power);
+    let depth_difference = (center_depth - sample_depth).abs() / center_depth.max(1e-6);
+    let depth_weight = (-depth_difference / (settings.depth_sigma * step as f64)).exp();
+
+    normal_weight * depth_weight
+}
+
+fn normal_weight(center: Vector3f, sample: Vector3f, power: f64) -> f64 {
+    vectors::dot_product(center, sample).max(0.0).powf(power)
+}
+
+fn albedo_weight(center: HdrColor, sample: HdrColor, sigma: f64) -> f64 {
+    let center = center.scale(1.0 / 255.0);
+    let sample = sample.scale(1.0 / 255.0);
+    color_weight(center, sample, sigma)
+}
+
+fn demodulate(color: HdrColor, albedo: HdrColor) -> HdrColor {
+    HdrColor {
+        r: color.r / albedo_channel(albedo.r),
+        g: color.g / albedo_channel(albedo.g),
+        b: color.b / albedo_channel(albedo.b),
+    }
+}
+
+fn remodulate(illumination: HdrColor, albedo: HdrColor) -> HdrColor {
+    HdrColor {
+        r: illumination.r * albedo_channel(albedo.r),
+        g: illumination.g * albedo_channel(albedo.g),
+        b: illumination.b * albedo_channel(albedo.b),
+    }
+}
+
+fn albedo_channel(channel: f64) -> f64 {
+    if channel < MIN_ALBEDO {
+        1.0
+    } else {
+        channel
+    }
+}
+
+#[cfg(test)]
+fn test_frame(
+    size: usize,
+    color: impl Fn(usize, usize) -> HdrColor,
+    normal: impl Fn(usize) -> Vector3f,
+) -> FrameBuffers {
+    let mut frame = FrameBuffers {
+        size,
+        color: Vec::new(),
+        normal: Vec::new(),
+        albedo: Vec::new(),
+        depth: Vec::new(),
+        stats: Default::default(),
+    };
+    for y in 0..size {
+        for x in 0..size {
+            frame.color.push(color(x, y));
+            frame.normal.push(normal(x));
+            frame.albedo.push(HdrColor { r: 200.0, g: 200.0, b: 200.0 });
+            frame.depth.push(5.0);
+        }
+    }
+    frame
+}
+
+#[test]
+fn test_denoise_keeps_constant_image() {
+    let frame = test_frame(
+        16,
+        |_, _| HdrColor { r: 120.0, g: 60.0, b: 30.0 },
+        |_| Vector3f::new(0.0, 0.0, -1.0),
+    );
+
+    let result = denoise(&frame, &DenoiseSettings::default());
+
+    for color in result {
+        assert!(test_utils::roughly_equals(color.r, 120.0));
+        assert!(test_utils::roughly_equals(color.g, 60.0));
+        assert!(test_utils::roughly_equals(color.b, 30.0));
+    }
+}
+
+#[test]
+fn test_denoise_reduces_noise() {
+    // детерминированный "шум" в шахматном порядке вокруг 100
+    let noisy = |x: usize, y: usize| {
+        let value = if (x + y).is_multiple_of(2) { 80.0 } else { 120.0 };
+        HdrColor { r: value, g: value, b: value }
+    };
+    let frame = test_frame(32, noisy, |_| Vector3f::new(0.0, 0.0, -1.0));
+
+    let result = denoise(&frame, &DenoiseSettings::default());
+
+    let center = result[16 * 32 + 16];
+    assert!((center.r - 100.0).abs() < 5.0, "center pixel is {}", center.r);
+}
+
+#[test]
+fn test_denoise_preserves_geometry_edges() {
+    // левая половина смотрит на камеру и освещена, правая - отвёрнута и тёмная
+    let frame = test_frame(
+        32,
+        |x, _| {
+            if x < 16 {
+                HdrColor { r: 200.0, g: 200.0, b: 200.0 }
+            } else {
+                HdrColor::black()
+            }
+        },
+        |x| {
+            if x < 16 {
+                Vector3f::new(0.0, 0.0, -1.0)
+            } else {
+                Vector3f::new(1.0, 0.0, 0.0)
+            }
+        },
+    );
+
+    let result = denoise(&frame, &DenoiseSettings::default());
+
+    assert!(test_utils::roughly_equals(result[8 * 32 + 15].r, 200.0));
+    assert!(test_utils::roughly_equals(result[8 * 32 + 16].r, 0.0));
+}
@@ -0,0 +1,144 @@
+//! Сцены glTF, прочитанные `gltf_import`, в виде фигур трассировщика.
+//!
+//! Каждый узел с сеткой становится `Shape::Transformed` с поворотом и переносом узла, а масштаб,
+//! которого у `Transform` нет, умножается на вершины. Треугольники плоские: нормали вершин,
+//! касательные и координаты текстур не используются.
+//!
+//! Материалы PBR приближаются так: цвет - базовый цвет, умноженный на средний цвет его текстуры,
+//! блик сужается с уменьшением шероховатости, а металлы отражают тем сильнее, чем они глаже.
+
+use crate::{Mesh, Shape, Transform, Triangle};
+use common::{Color, Vector3f};
+use gltf_import::{GltfError, Image, PbrMaterial, Scene};
+use std::path::Path;
+
+/// Загружает файл `.gltf` или `.glb` и возвращает фигуры его сцены по умолчанию
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Shape>, GltfError> {
+    Ok(shapes(&gltf_import::load(path)?))
+}
+
+/// Фигура каждого узла сцены по умолчанию, у которого есть сетка
+pub fn shapes(scene: &Scene) -> Vec<Shape> {
+    let default_material = PbrMaterial::default();
+    scene
+        .mesh_instances()
+        .into_iter()
+        .map(|(mesh, transform)| {
+            let (translation, rotation, scale) = gltf_import::decompose(&transform);
+            // отражение меняет обход вершин, без перестановки нормали смотрели бы внутрь
+            let mirrored = scale.x * scale.y * scale.z < 0.0;
+            let scaled =
+                |vertex: Vector3f| Vector3f::new(vertex.x * scale.x, vertex.y * scale.y, vertex.z * scale.z);
+
+            let mut triangles = vec![];
+            for primitive in &scene.meshes[mesh].primitives {
+                let material = primitive.material.map_or(&default_material, |index| &scene.materials[index]);
+                let color = color(material, &scene.images);
+                let reflective = material.metallic * (1.0 - material.roughness);
+                for &[a, b, c] in &primitive.triangles {
+                    let (b, c) = if mirrored { (c, b) } else { (b, c) };
+                    let triangle = Triangle::new(
+                        scaled(primitive.positions[a]),
+                        scaled(primitive.positions[b]),
+                        scaled(primitive.positions[c]),
+                        color,
+                        material.shininess(),
+                        reflective,
+                    );
+                    triangles.push(triangle.with_roughness(material.roughness));
+                }
+            }
+
+            Shape::Transformed {
+                shape: Box::new(Shape::Mesh(Mesh::new(triangles))),
+                transform: Transform { translation, rotation },
+            }
+        })
+        .collect()
+}
+
+/// Базовый цвет материала, умноженный на средний цвет текстуры, если она есть
+fn color(material: &PbrMaterial, images: &[Image]) -> Color {
+    let [r, g, b, _] = material.base_color;
+    let color = gltf_import::srgb([r, g, b]);
+    let Some(image) = material.base_color_texture.map(|index| &images[index]) else {
+        return color;
+    };
+
+    let texels = (image.width * image.height).max(1) as f64;
+    let average = |channel: usize| {
+        image.rgba.iter().skip(channel).step_by(4).map(|&value| value as f64).sum::<f64>() / texels
+    };
+    let channel = |value: u8, channel: usize| (value as f64 * average(channel) / 255.0).round() as u8;
+    Color {
+        r: channel(color.r, 0),
+        g: channel(color.g, 1),
+        b: channel(color.b, 2),
+    }
+}
+
+#[test]
+fn test_gltf_shapes() {
+    use crate::intersect_ray_with_shape;
+    use gltf_import::{Mesh, Node, Primitive};
+
+    // треугольник в плоскости z = 0, растянутый по x вдвое и сдвинутый на 4 по z
+    let mut transform = gltf_import::IDENTITY;
+    transform[0][0] = 2.0;
+    transform[2][3] = 4.0;
+    let scene = Scene {
+        meshes: vec![Mesh {
+            name: "triangle".to_string(),
+            primitives: vec![Primitive {
+                positions: vec![
+                    Vector3f::new(0.0, 0.0, 0.0),
+                    Vector3f::new(0.0, 1.0, 0.0),
+                    Vector3f::new(1.0, 0.0, 0.0),
+                ],
+                normals: None,
+                tangents: None,
+                uvs: None,
+                triangles: vec![[0, 1, 2]],
+                material: Some(0),
+            }],
+        }],
+        materials: vec![PbrMaterial {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: Some(0),
+            metallic: 1.0,
+            roughness: 0.5,
+            ..PbrMaterial::default()
+        }],
+        images: vec![Image {
+            width: 2,
+            height: 1,
+            rgba: vec![200, 0, 100, 255, 100, 0, 100, 255],
+        }],
+        nodes: vec![Node {
+            name: "node".to_string(),
+            mesh: Some(0),
+            children: vec![],
+            transform,
+        }],
+        roots: vec![0],
+    };
+
+    let shapes = shapes(&scene);
+    assert_eq!(shapes.len(), 1);
+
+    // луч из начала координат попадает в треугольник за x = 1 только благодаря растяжению
+    let hits = intersect_ray_with_shape(
+        Vector3f::new(0.0, 0.0, 0.0),
+        Vector3f::new(1.5, 0.1, 4.0),
+        &shapes[0],
+    );
+    assert_eq!(hits.len(), 1);
+    let hit = &hits[0];
+    assert!((hit.point.z - 4.0).abs() < 1e-9);
+    assert!((hit.point.x - 1.5).abs() < 1e-9);
+    // нормаль смотрит на камеру
+    assert!((hit.normal.z + 1.0).abs() < 1e-9);
+    assert_eq!((hit.color.r, hit.color.g, hit.color.b), (150, 0, 100));
+    assert_eq!(hit.specular, 30);
+    assert_eq!(hit.reflective, 0.5);
+}
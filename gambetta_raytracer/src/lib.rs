@@ -4,8 +4,17 @@
 
 use common::vectors;
 use common::{Color, Light, Pixel, Vector3f};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 use smallvec::SmallVec;
+use stats::{RenderStats, TileStats};
+use std::time::Instant;
+
+pub mod denoise;
+pub mod gltf;
+mod media;
+pub mod stats;
 
 type HitList = SmallVec<[Hit; 4]>;
 
@@ -16,6 +25,24 @@ pub struct Sphere {
     pub color: Color,
     pub specular: i32,
     pub reflective: f64,
+    /// Шероховатость отражения: 0 - идеальное зеркало, 1 - почти диффузное отражение
+    pub roughness: f64,
+}
+
+/// Однородная среда, поглощающая и рассеивающая свет (туман, дым, пыль).
+/// Коэффициенты задаются на единицу расстояния в мировых координатах.
+#[derive(Copy, Clone)]
+pub struct Medium {
+    pub absorption: f64,
+    pub scattering: f64,
+    /// Цвет рассеянного средой света
+    pub color: Color,
+}
+
+impl Medium {
+    fn extinction(&self) -> f64 {
+        self.absorption + self.scattering
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -26,6 +53,7 @@ pub struct Triangle {
     pub color: Color,
     pub specular: i32,
     pub reflective: f64,
+    pub roughness: f64,
     pub normal: Vector3f,
 }
 
@@ -41,7 +69,21 @@ impl Triangle {
         let edge1 = vectors::difference(v1, v0);
         let edge2 = vectors::difference(v2, v0);
         let normal = vectors::normalize(vectors::cross_product(edge1, edge2));
-        Triangle { v0, v1, v2, color, specular, reflective, normal }
+        Triangle {
+            v0,
+            v1,
+            v2,
+            color,
+            specular,
+            reflective,
+            roughness: 0.0,
+            normal,
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness;
+        self
     }
 }
 
@@ -60,6 +102,23 @@ impl Mesh {
         self.transform = Some(transform);
         self
     }
+
+    /// Задаёт одинаковую шероховатость всем треугольникам
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        for triangle in &mut self.triangles {
+            triangle.roughness = roughness;
+        }
+        self
+    }
+
+    /// Копия геометрии для экспорта из `mesh_export`; общие вершины треугольников объединяются,
+    /// а `transform`, как и при трассировке, не учитывается
+    pub fn to_triangle_mesh(&self, name: &str) -> mesh_export::TriangleMesh {
+        mesh_export::TriangleMesh::from_corners(
+            name,
+            self.triangles.iter().map(|triangle| [triangle.v0, triangle.v1, triangle.v2]),
+        )
+    }
 }
 
 #[derive(Clone)]
@@ -76,6 +135,13 @@ pub enum Shape {
         shape: Box<Shape>,
         transform: Transform,
     },
+    /// Объём, заполненный средой. Граница может быть любой замкнутой фигурой, в том числе CSG,
+    /// но сама она невидима: лучи проходят сквозь неё, ослабляясь и собирая рассеянный свет.
+    /// Учитывается только на верхнем уровне сцены.
+    Medium {
+        boundary: Box<Shape>,
+        medium: Medium,
+    },
 }
 
 impl Shape {
@@ -89,14 +155,17 @@ impl Shape {
             }
             Shape::Triangle(triangle) => {
                 let t = triangle;
-                Shape::Triangle(Triangle::new(
-                    vectors::sum(t.v0, translation),
-                    vectors::sum(t.v1, translation),
-                    vectors::sum(t.v2, translation),
-                    t.color,
-                    t.specular,
-                    t.reflective,
-                ))
+                Shape::Triangle(
+                    Triangle::new(
+                        vectors::sum(t.v0, translation),
+                        vectors::sum(t.v1, translation),
+                        vectors::sum(t.v2, translation),
+                        t.color,
+                        t.specular,
+                        t.reflective,
+                    )
+                    .with_roughness(t.roughness),
+                )
             }
             Shape::Mesh(mesh) => {
                 let translated_triangles = mesh
@@ -111,6 +180,7 @@ impl Shape {
                             tri.specular,
                             tri.reflective,
                         )
+                        .with_roughness(tri.roughness)
                     })
                     .collect();
                 Shape::Mesh(Mesh::new(translated_triangles))
@@ -124,6 +194,10 @@ impl Shape {
                 shape: Box::new(shape.translate_all(dx, dy, dz)),
                 transform,
             },
+            Shape::Medium { boundary, medium } => Shape::Medium {
+                boundary: Box::new(boundary.translate_all(dx, dy, dz)),
+                medium,
+            },
         }
     }
 
@@ -143,14 +217,17 @@ impl Shape {
             }
             Shape::Triangle(triangle) => {
                 let t = triangle;
-                Shape::Triangle(Triangle::new(
-                    rotate_point(t.v0),
-                    rotate_point(t.v1),
-                    rotate_point(t.v2),
-                    t.color,
-                    t.specular,
-                    t.reflective,
-                ))
+                Shape::Triangle(
+                    Triangle::new(
+                        rotate_point(t.v0),
+                        rotate_point(t.v1),
+                        rotate_point(t.v2),
+                        t.color,
+                        t.specular,
+                        t.reflective,
+                    )
+                    .with_roughness(t.roughness),
+                )
             }
             Shape::Mesh(mesh) => {
                 let rotated_triangles = mesh
@@ -165,6 +242,7 @@ impl Shape {
                             tri.specular,
                             tri.reflective,
                         )
+                        .with_roughness(tri.roughness)
                     })
                     .collect();
                 Shape::Mesh(Mesh::new(rotated_triangles))
@@ -178,6 +256,10 @@ impl Shape {
                 shape: Box::new(shape.rotate_x_all(angle, point)),
                 transform,
             },
+            Shape::Medium { boundary, medium } => Shape::Medium {
+                boundary: Box::new(boundary.rotate_x_all(angle, point)),
+                medium,
+            },
         }
     }
 
@@ -197,14 +279,17 @@ impl Shape {
             }
             Shape::Triangle(triangle) => {
                 let t = triangle;
-                Shape::Triangle(Triangle::new(
-                    rotate_point(t.v0),
-                    rotate_point(t.v1),
-                    rotate_point(t.v2),
-                    t.color,
-                    t.specular,
-                    t.reflective,
-                ))
+                Shape::Triangle(
+                    Triangle::new(
+                        rotate_point(t.v0),
+                        rotate_point(t.v1),
+                        rotate_point(t.v2),
+                        t.color,
+                        t.specular,
+                        t.reflective,
+                    )
+                    .with_roughness(t.roughness),
+                )
             }
             Shape::Mesh(mesh) => {
                 let rotated_triangles = mesh
@@ -219,6 +304,7 @@ impl Shape {
                             tri.specular,
                             tri.reflective,
                         )
+                        .with_roughness(tri.roughness)
                     })
                     .collect();
                 Shape::Mesh(Mesh::new(rotated_triangles))
@@ -232,6 +318,10 @@ impl Shape {
                 shape: Box::new(shape.rotate_y_all(angle, point)),
                 transform,
             },
+            Shape::Medium { boundary, medium } => Shape::Medium {
+                boundary: Box::new(boundary.rotate_y_all(angle, point)),
+                medium,
+            },
         }
     }
 
@@ -251,14 +341,17 @@ impl Shape {
             }
             Shape::Triangle(triangle) => {
                 let t = triangle;
-                Shape::Triangle(Triangle::new(
-                    rotate_point(t.v0),
-                    rotate_point(t.v1),
-                    rotate_point(t.v2),
-                    t.color,
-                    t.specular,
-                    t.reflective,
-                ))
+                Shape::Triangle(
+                    Triangle::new(
+                        rotate_point(t.v0),
+                        rotate_point(t.v1),
+                        rotate_point(t.v2),
+                        t.color,
+                        t.specular,
+                        t.reflective,
+                    )
+                    .with_roughness(t.roughness),
+                )
             }
             Shape::Mesh(mesh) => {
                 let rotated_triangles = mesh
@@ -273,6 +366,7 @@ impl Shape {
                             tri.specular,
                             tri.reflective,
                         )
+                        .with_roughness(tri.roughness)
                     })
                     .collect();
                 Shape::Mesh(Mesh::new(rotated_triangles))
@@ -286,6 +380,10 @@ impl Shape {
                 shape: Box::new(shape.rotate_z_all(angle, point)),
                 transform,
             },
+            Shape::Medium { boundary, medium } => Shape::Medium {
+                boundary: Box::new(boundary.rotate_z_all(angle, point)),
+                medium,
+            },
         }
     }
 
@@ -343,6 +441,23 @@ impl Transform {
         vectors::sum(rotated, self.translation)
     }
 
+    /// Переводит точку вместе с оценкой её погрешности (Pharr et al., PBRT, 3.9.4)
+    fn transform_point_with_error(&self, point: Vector3f, error: Vector3f) -> (Vector3f, Vector3f) {
+        let abs_rotation = self.rotation.map(|row| row.map(f64::abs));
+        let rotated_error = vectors::multiply_vec_and_mat(abs_components(error).to_vec(), abs_rotation);
+        let rotated_point = vectors::multiply_vec_and_mat(abs_components(point).to_vec(), abs_rotation);
+        let translation = abs_components(self.translation);
+
+        let error = vectors::sum(
+            vectors::scale(1.0 + gamma(3), Vector3f::from_vec(rotated_error)),
+            vectors::scale(
+                gamma(3),
+                vectors::sum(Vector3f::from_vec(rotated_point), translation),
+            ),
+        );
+        (self.transform_point(point), error)
+    }
+
     fn transform_direction(&self, direction: Vector3f) -> Vector3f {
         Vector3f::from_vec(crate::vectors::multiply_vec_and_mat(
             direction.to_vec(),
@@ -359,6 +474,9 @@ pub struct Hit {
     pub color: Color,
     pub specular: i32,
     pub reflective: f64,
+    pub roughness: f64,
+    /// Оценка абсолютной погрешности координат `point`
+    pub error: Vector3f,
 }
 
 impl Hit {
@@ -366,14 +484,157 @@ impl Hit {
         Hit {
             t: self.t,
             point: self.point,
+            error: self.error,
             normal: vectors::negate(self.normal),
             color: self.color,
             specular: self.specular,
             reflective: self.reflective,
+            roughness: self.roughness,
+        }
+    }
+}
+
+/// Цвет без ограничения сверху: те же единицы, что и у `Color` (0..255),
+/// но значения могут выходить за 255 до финального преобразования в `Color`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HdrColor {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl HdrColor {
+    pub fn black() -> Self {
+        HdrColor { r: 0.0, g: 0.0, b: 0.0 }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        HdrColor {
+            r: color.r as f64,
+            g: color.g as f64,
+            b: color.b as f64,
+        }
+    }
+
+    /// Обрезает компоненты до 0..255 так же, как это делает `common::multiply_color`
+    pub fn to_color(self) -> Color {
+        Color {
+            r: self.r.clamp(0.0, 255.0) as u8,
+            g: self.g.clamp(0.0, 255.0) as u8,
+            b: self.b.clamp(0.0, 255.0) as u8,
+        }
+    }
+
+    pub fn scale(self, k: f64) -> Self {
+        HdrColor { r: self.r * k, g: self.g * k, b: self.b * k }
+    }
+
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+}
+
+impl std::ops::Add for HdrColor {
+    type Output = HdrColor;
+
+    fn add(self, other: HdrColor) -> HdrColor {
+        HdrColor {
+            r: self.r + other.r,
+            g: self.g + other.g,
+            b: self.b + other.b,
+        }
+    }
+}
+
+pub struct RenderSettings {
+    /// Количество лучей на пиксель. При 1 луч идёт точно через центр пикселя,
+    /// при большем значении - через случайные точки внутри пикселя.
+    pub samples_per_pixel: u32,
+    /// Максимальная глубина рекурсии для отражений
+    pub max_recursion_depth: i32,
+    /// Отражённый луч не трассируется, если его вклад в цвет пикселя меньше этого значения
+    pub min_contribution: f64,
+    /// Экспоненциальный туман, заполняющий всё пространство. Для простоты он ослабляет
+    /// только лучи от камеры и отражённые лучи, но не лучи к источникам света.
+    pub fog: Option<Medium>,
+    /// Количество точек на отрезке луча внутри среды, в которых считается рассеянный свет
+    pub volume_samples: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            samples_per_pixel: 1,
+            max_recursion_depth: 4,
+            // меньше одного уровня яркости из 255 - на глаз не заметно
+            min_contribution: 1.0 / 255.0,
+            fog: None,
+            volume_samples: 8,
         }
     }
 }
 
+/// Общие для всех лучей кадра параметры трассировки
+struct TraceContext<'a> {
+    scene: &'a Vec<Shape>,
+    lights: &'a Vec<Light>,
+    settings: &'a RenderSettings,
+    /// Объёмы со средой из сцены: граница и параметры среды
+    media: Vec<(&'a Shape, Medium)>,
+}
+
+impl<'a> TraceContext<'a> {
+    fn new(scene: &'a Vec<Shape>, lights: &'a Vec<Light>, settings: &'a RenderSettings) -> Self {
+        let media = scene
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Medium { boundary, medium } => Some((boundary.as_ref(), *medium)),
+                _ => None,
+            })
+            .collect();
+        TraceContext { scene, lights, settings, media }
+    }
+}
+
+/// Результат рендеринга кадра: HDR цвет и вспомогательные буферы первичных попаданий
+/// (нормаль, альбедо и расстояние до камеры), которые использует шумоподавление.
+/// Все буферы хранятся построчно в экранных координатах, `size * size` элементов.
+pub struct FrameBuffers {
+    pub size: usize,
+    pub color: Vec<HdrColor>,
+    pub normal: Vec<Vector3f>,
+    pub albedo: Vec<HdrColor>,
+    /// Для пикселей, в которых луч ни во что не попал, глубина равна `f64::INFINITY`
+    pub depth: Vec<f64>,
+    pub stats: RenderStats,
+}
+
+impl FrameBuffers {
+    pub fn write_to_buffer(&self, buffer: &mut [u8]) {
+        write_hdr_to_buffer(&self.color, buffer);
+    }
+}
+
+pub fn write_hdr_to_buffer(colors: &[HdrColor], buffer: &mut [u8]) {
+    for (index, color) in colors.iter().enumerate() {
+        let color = color.to_color();
+        let offset = index * 3;
+        buffer[offset] = color.r;
+        buffer[offset + 1] = color.g;
+        buffer[offset + 2] = color.b;
+    }
+}
+
+/// Сторона квадратного тайла в пикселях
+const TILE_SIZE: usize = 32;
+
+struct PixelSample {
+    color: HdrColor,
+    normal: Vector3f,
+    albedo: HdrColor,
+    depth: f64,
+}
+
 pub fn render_scene_to_buffer(
     scene: &Vec<Shape>,
     lights: &Vec<Light>,
@@ -381,51 +642,161 @@ pub fn render_scene_to_buffer(
     size: usize,
     origin: Vector3f,
     rotation: [[f64; 3]; 3],
-) {
-    let canvas_width = size as i32;
-    let canvas_height = size as i32;
-    let recursion_depth = 4;
+) -> RenderStats {
+    let frame = render_scene_to_frame(scene, lights, size, origin, rotation, &RenderSettings::default());
+    frame.write_to_buffer(buffer);
+    frame.stats
+}
 
-    // Создадим вектор координат пикселей
-    let pixels: Vec<(i32, i32)> = (-canvas_width / 2..canvas_width / 2)
-        .flat_map(|x| (-canvas_height / 2..canvas_height / 2).map(move |y| (x, y)))
+pub fn render_scene_to_frame(
+    scene: &Vec<Shape>,
+    lights: &Vec<Light>,
+    size: usize,
+    origin: Vector3f,
+    rotation: [[f64; 3]; 3],
+    settings: &RenderSettings,
+) -> FrameBuffers {
+    let start_time = Instant::now();
+    let context = TraceContext::new(scene, lights, settings);
+
+    let tiles: Vec<(usize, usize)> = (0..size)
+        .step_by(TILE_SIZE)
+        .flat_map(|y| (0..size).step_by(TILE_SIZE).map(move |x| (x, y)))
         .collect();
 
-    // Параллельная обработка всех пикселей
-    let colors: Vec<Color> = pixels
-        .par_iter()
-        .map(|&(x, y)| {
-            let direction = Vector3f::from_vec(crate::vectors::multiply_vec_and_mat(
-                canvas_to_viewport(x, y, canvas_width, canvas_height).to_vec(),
-                rotation,
-            ));
-            trace_ray(
-                scene,
-                lights,
-                origin,
+    // Тайлы обрабатываются параллельно, каждый тайл целиком в одном потоке
+    let rendered: Vec<(TileStats, Vec<PixelSample>)> = tiles
+        .into_par_iter()
+        .map(|(x, y)| render_tile(&context, origin, rotation, size, x, y))
+        .collect();
+
+    let mut frame = FrameBuffers {
+        size,
+        color: vec![HdrColor::black(); size * size],
+        normal: vec![Vector3f::zero_vector(); size * size],
+        albedo: vec![HdrColor::black(); size * size],
+        depth: vec![f64::INFINITY; size * size],
+        stats: RenderStats::default(),
+    };
+    for (tile, samples) in rendered {
+        for (i, sample) in samples.into_iter().enumerate() {
+            let index = (tile.y + i / tile.width) * size + tile.x + i % tile.width;
+            frame.color[index] = sample.color;
+            frame.normal[index] = sample.normal;
+            frame.albedo[index] = sample.albedo;
+            frame.depth[index] = sample.depth;
+        }
+        frame.stats.counters = frame.stats.counters + tile.counters;
+        frame.stats.tiles.push(tile);
+    }
+    frame.stats.total_time = start_time.elapsed();
+    frame
+}
+
+/// Рендерит тайл с левым верхним углом в (x, y), пиксели возвращаются построчно
+fn render_tile(
+    context: &TraceContext,
+    origin: Vector3f,
+    rotation: [[f64; 3]; 3],
+    size: usize,
+    x: usize,
+    y: usize,
+) -> (TileStats, Vec<PixelSample>) {
+    let start_time = Instant::now();
+    let width = TILE_SIZE.min(size - x);
+    let height = TILE_SIZE.min(size - y);
+
+    // сбрасываем счётчики, оставшиеся в этом потоке от предыдущей работы
+    stats::take_counters();
+
+    let samples = (0..height)
+        .flat_map(|row| (0..width).map(move |column| (y + row) * size + x + column))
+        .map(|index| render_pixel(context, origin, rotation, size, index))
+        .collect();
+
+    let tile = TileStats {
+        x,
+        y,
+        width,
+        height,
+        time: start_time.elapsed(),
+        counters: stats::take_counters(),
+    };
+    (tile, samples)
+}
+
+fn render_pixel(
+    context: &TraceContext,
+    origin: Vector3f,
+    rotation: [[f64; 3]; 3],
+    size: usize,
+    pixel_index: usize,
+) -> PixelSample {
+    let canvas_width = size as i32;
+    let canvas_height = size as i32;
+    let x = (pixel_index % size) as i32 - canvas_width / 2;
+    let y = canvas_height / 2 - 1 - (pixel_index / size) as i32;
+    let settings = context.settings;
+    let samples_per_pixel = settings.samples_per_pixel.max(1);
+
+    // генератор зависит только от номера пикселя, поэтому кадр воспроизводим
+    let mut rng = StdRng::seed_from_u64(pixel_index as u64);
+
+    let mut color = HdrColor::black();
+    let mut normal = Vector3f::zero_vector();
+    let mut albedo = HdrColor::black();
+    let mut depth = 0.0;
+    let mut hit_count = 0;
+
+    for _ in 0..samples_per_pixel {
+        stats::count(|counters| counters.primary_rays += 1);
+        let mut viewport_point = canvas_to_viewport(x, y, canvas_width, canvas_height);
+        if samples_per_pixel > 1 {
+            viewport_point.x += rng.gen_range(-0.5..0.5) / canvas_width as f64;
+            viewport_point.y += rng.gen_range(-0.5..0.5) / canvas_height as f64;
+        }
+        let direction = Vector3f::from_vec(crate::vectors::multiply_vec_and_mat(
+            viewport_point.to_vec(),
+            rotation,
+        ));
+
+        let closest_hit = closest_intersection(origin, direction, 1.0, f64::INFINITY, context.scene);
+        let surface_color = match &closest_hit {
+            Some(hit) => shade_hit(
+                context,
+                hit,
                 direction,
+                settings.max_recursion_depth,
                 1.0,
-                std::f64::INFINITY,
-                recursion_depth,
-            )
-        })
-        .collect();
+                &mut rng,
+            ),
+            None => HdrColor::black(),
+        };
+        let max_t = closest_hit.map_or(f64::INFINITY, |hit| hit.t);
+        color = color + media::apply_media(context, origin, direction, 1.0, max_t, surface_color, &mut rng);
+
+        if let Some(hit) = closest_hit {
+            normal = vectors::sum(normal, hit.normal);
+            albedo = albedo + HdrColor::from_color(hit.color);
+            depth += hit.t * vectors::length(direction);
+            hit_count += 1;
+        }
+    }
 
-    // Последовательная запись в буфер (чтобы избежать гонок)
-    for ((x, y), color) in pixels.iter().zip(colors.iter()) {
-        let screen_x = screen_x(*x, canvas_width);
-        let screen_y = screen_y(*y, canvas_height);
-        if screen_x < size && screen_y < size {
-            let offset = screen_y * size * 3 + screen_x * 3;
-            buffer[offset] = color.r;
-            buffer[offset + 1] = color.g;
-            buffer[offset + 2] = color.b;
+    let weight = 1.0 / samples_per_pixel as f64;
+    PixelSample {
+        color: color.scale(weight),
+        normal: if hit_count > 0 {
+            vectors::normalize(normal)
         } else {
-            println!(
-                "Warning: Pixel ({}, {}) is out of bounds for size {}",
-                screen_x, screen_y, size
-            );
-        }
+            normal
+        },
+        albedo: albedo.scale(weight),
+        depth: if hit_count > 0 {
+            depth / hit_count as f64
+        } else {
+            f64::INFINITY
+        },
     }
 }
 
@@ -457,106 +828,155 @@ fn canvas_to_viewport(x: i32, y: i32, canvas_width: i32, canvas_height: i32) ->
 }
 
 fn trace_ray(
-    scene: &Vec<Shape>,
-    lights: &Vec<Light>,
+    context: &TraceContext,
     origin: Vector3f,
     direction: Vector3f,
     min_t: f64,
-    max_t: f64,
     recursion_depth: i32,
-) -> Color {
+    contribution: f64,
+    rng: &mut StdRng,
+) -> HdrColor {
+    let closest_hit = closest_intersection(origin, direction, min_t, f64::INFINITY, context.scene);
+    let surface_color = match &closest_hit {
+        Some(hit) => shade_hit(context, hit, direction, recursion_depth, contribution, rng),
+        None => HdrColor::black(),
+    };
+    let max_t = closest_hit.map_or(f64::INFINITY, |hit| hit.t);
+    media::apply_media(context, origin, direction, min_t, max_t, surface_color, rng)
+}
+
+/// `contribution` - доля, с которой цвет этой точки попадёт в итоговый цвет пикселя
+fn shade_hit(
+    context: &TraceContext,
+    hit: &Hit,
+    direction: Vector3f,
+    recursion_depth: i32,
+    contribution: f64,
+    rng: &mut StdRng,
+) -> HdrColor {
     // let mut rng = rand::thread_rng();
 
-    let closest_hit = closest_intersection(origin, direction, min_t, max_t, scene);
-    match closest_hit {
-        Some(hit) => {
-            // just for fun: randomize normal vectors to create "bumpiness"
-            // let point_normal = vectors::difference(point, sphere.center);
-            // let normal = vectors::normalize(Point3D {
-            //     x: point_normal.x + rng.gen_range(-0.05, 0.05),
-            //     y: point_normal.y + rng.gen_range(-0.05, 0.05),
-            //     z: point_normal.z + rng.gen_range(-0.05, 0.05),
-            // });
-
-            let normal = hit.normal;
-            let view = vectors::negate(direction);
-            let intensity = compute_lighting(hit.point, normal, view, lights, hit.specular, scene);
-            let local_color = common::multiply_color(intensity, hit.color);
-            let reflective = hit.reflective;
-
-            if reflective > 0.0 && recursion_depth > 0 {
-                let reflected_color = trace_ray(
-                    scene,
-                    lights,
-                    hit.point,
-                    vectors::reflect(view, normal),
-                    0.0001,
-                    std::f64::INFINITY,
-                    recursion_depth - 1,
-                );
-                add_colors(
-                    common::multiply_color(1.0 - reflective, local_color),
-                    common::multiply_color(reflective, reflected_color),
-                )
-            } else {
-                local_color
-            }
-        }
-        None => Color { r: 0, g: 0, b: 0 },
+    // just for fun: randomize normal vectors to create "bumpiness"
+    // let point_normal = vectors::difference(point, sphere.center);
+    // let normal = vectors::normalize(Point3D {
+    //     x: point_normal.x + rng.gen_range(-0.05, 0.05),
+    //     y: point_normal.y + rng.gen_range(-0.05, 0.05),
+    //     z: point_normal.z + rng.gen_range(-0.05, 0.05),
+    // });
+
+    let normal = hit.normal;
+    let view = vectors::negate(direction);
+    let intensity = compute_lighting(hit, view, context);
+    let local_color = HdrColor::from_color(hit.color).scale(intensity);
+    let reflective = hit.reflective;
+    let reflected_contribution = contribution * reflective;
+
+    if reflective > 0.0 && recursion_depth > 0 && reflected_contribution >= context.settings.min_contribution
+    {
+        let mirror_direction = vectors::reflect(view, normal);
+        let reflected_direction = glossy_reflection(mirror_direction, normal, hit.roughness, rng);
+        stats::count(|counters| counters.reflection_rays += 1);
+        let reflected_color = trace_ray(
+            context,
+            offset_ray_origin(hit, reflected_direction),
+            reflected_direction,
+            0.0,
+            recursion_depth - 1,
+            reflected_contribution,
+            rng,
+        );
+        local_color.scale(1.0 - reflective) + reflected_color.scale(reflective)
+    } else {
+        local_color
     }
 }
 
-fn compute_lighting(
-    point: Vector3f,
+/// Переводит шероховатость в показатель степени Фонга (Walter et al., 2007)
+fn roughness_to_phong_exponent(roughness: f64) -> f64 {
+    2.0 / (roughness * roughness) - 2.0
+}
+
+/// Выбирает направление отражённого луча из лепестка Фонга вокруг зеркального направления.
+/// Плотность выборки пропорциональна лепестку, поэтому вес каждого луча одинаковый
+/// и усреднение по нескольким лучам на пиксель даёт размытое отражение.
+fn glossy_reflection(
+    mirror_direction: Vector3f,
     normal: Vector3f,
-    view: Vector3f,
-    lights: &Vec<Light>,
-    shininess: i32,
-    scene: &Vec<Shape>,
-) -> f64 {
+    roughness: f64,
+    rng: &mut StdRng,
+) -> Vector3f {
+    if roughness <= 0.0 {
+        return mirror_direction;
+    }
+
+    let exponent = roughness_to_phong_exponent(roughness.min(1.0));
+    let axis = vectors::normalize(mirror_direction);
+    let cos_theta = rng.gen::<f64>().powf(1.0 / (exponent + 1.0));
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+
+    // ортонормированный базис вокруг зеркального направления
+    let helper = if axis.x.abs() > 0.9 {
+        Vector3f::new(0.0, 1.0, 0.0)
+    } else {
+        Vector3f::new(1.0, 0.0, 0.0)
+    };
+    let tangent = vectors::normalize(vectors::cross_product(helper, axis));
+    let bitangent = vectors::cross_product(axis, tangent);
+
+    let sampled = vectors::sum(
+        vectors::sum(
+            vectors::scale(sin_theta * phi.cos(), tangent),
+            vectors::scale(sin_theta * phi.sin(), bitangent),
+        ),
+        vectors::scale(cos_theta, axis),
+    );
+
+    // лучи, ушедшие под поверхность, заменяем зеркальным
+    if vectors::dot_product(sampled, normal) > 0.0 {
+        sampled
+    } else {
+        axis
+    }
+}
+
+fn compute_lighting(hit: &Hit, view: Vector3f, context: &TraceContext) -> f64 {
     let mut result = 0.0;
-    for light in lights {
+    for light in context.lights {
         result += match *light {
             Light::Ambient { intensity } => intensity,
             Light::Point { intensity, position } => compute_light_from_direction(
-                point,
-                normal,
+                hit,
                 view,
-                shininess,
-                scene,
+                context,
                 intensity,
-                crate::vectors::difference(position, point),
+                crate::vectors::difference(position, hit.point),
                 1.0,
             ),
-            Light::Directional { intensity, direction } => compute_light_from_direction(
-                point,
-                normal,
-                view,
-                shininess,
-                scene,
-                intensity,
-                direction,
-                std::f64::INFINITY,
-            ),
+            Light::Directional { intensity, direction } => {
+                compute_light_from_direction(hit, view, context, intensity, direction, std::f64::INFINITY)
+            }
         }
     }
     result
 }
 
 fn compute_light_from_direction(
-    point: Vector3f,
-    normal: Vector3f,
+    hit: &Hit,
     view: Vector3f,
-    shininess: i32,
-    scene: &Vec<Shape>,
+    context: &TraceContext,
     light_intensity: f64,
     light_direction: Vector3f,
     max_t: f64,
 ) -> f64 {
+    let normal = hit.normal;
+    let shininess = hit.specular;
     let mut result = 0.0;
 
     // shadow check
-    let closest_hit = closest_intersection(point, light_direction, 0.001, max_t, scene);
+    let shadow_origin = offset_ray_origin(hit, light_direction);
+    stats::count(|counters| counters.shadow_rays += 1);
+    let closest_hit = closest_intersection(shadow_origin, light_direction, 0.0, max_t, context.scene);
     if closest_hit.is_none() {
         // diffuse
         let dot = vectors::dot_product(normal, light_direction);
@@ -576,19 +996,16 @@ fn compute_light_from_direction(
                         .powi(shininess)
             }
         }
+
+        // свет, прошедший через объёмы со средой, ослабляется
+        if result > 0.0 {
+            result *= media::transmittance(context, shadow_origin, light_direction, 0.0, max_t);
+        }
     }
 
     result
 }
 
-fn add_colors(color1: Color, color2: Color) -> Color {
-    Color {
-        r: color1.r + color2.r,
-        g: color1.g + color2.g,
-        b: color1.b + color2.b,
-    }
-}
-
 fn closest_intersection(
     origin: Vector3f,
     direction: Vector3f,
@@ -614,6 +1031,7 @@ fn closest_intersection(
 }
 
 fn intersect_ray_with_sphere(origin: Vector3f, direction: Vector3f, sphere: Sphere) -> Option<(f64, f64)> {
+    stats::count(|counters| counters.sphere_tests += 1);
     let oc = vectors::difference(origin, sphere.center);
     let k1 = vectors::dot_product(direction, direction);
     let k2 = 2.0 * vectors::dot_product(oc, direction);
@@ -624,8 +1042,17 @@ fn intersect_ray_with_sphere(origin: Vector3f, direction: Vector3f, sphere: Sphe
         return None;
     }
 
-    let t1 = (-k2 + discriminant.sqrt()) / (2.0 * k1);
-    let t2 = (-k2 - discriminant.sqrt()) / (2.0 * k1);
+    // устойчивая форма решения: корень, близкий к нулю, не теряет точность из-за вычитания
+    let q = if k2 < 0.0 {
+        -0.5 * (k2 - discriminant.sqrt())
+    } else {
+        -0.5 * (k2 + discriminant.sqrt())
+    };
+    if q == 0.0 {
+        return None;
+    }
+    let t1 = q / k1;
+    let t2 = k3 / q;
     Some((t1, t2))
 }
 
@@ -635,15 +1062,25 @@ fn intersect_ray_with_shape(origin: Vector3f, direction: Vector3f, shape: &Shape
             let mut hits = HitList::new();
             if let Some((t1, t2)) = intersect_ray_with_sphere(origin, direction, *sphere) {
                 for t in [t1, t2] {
+                    // точку возвращаем на поверхность сферы, это уменьшает её погрешность
                     let point = vectors::sum(origin, vectors::scale(t, direction));
-                    let normal = vectors::normalize(vectors::difference(point, sphere.center));
+                    let local = vectors::difference(point, sphere.center);
+                    let local = vectors::scale(sphere.radius / vectors::length(local), local);
+                    let point = vectors::sum(sphere.center, local);
+                    let error = vectors::sum(
+                        vectors::scale(gamma(5), abs_components(local)),
+                        vectors::scale(gamma(1), abs_components(point)),
+                    );
+                    let normal = vectors::normalize(local);
                     hits.push(Hit {
                         t,
                         point,
+                        error,
                         normal,
                         color: sphere.color,
                         specular: sphere.specular,
                         reflective: sphere.reflective,
+                        roughness: sphere.roughness,
                     });
                 }
             }
@@ -670,15 +1107,13 @@ fn intersect_ray_with_shape(origin: Vector3f, direction: Vector3f, shape: &Shape
             let right_hits = intersect_ray_with_shape(origin, direction, right);
             merge_csg_hits(left_hits, right_hits, op)
         }
+        // граница среды не является поверхностью
+        Shape::Medium { .. } => HitList::new(),
         Shape::Transformed { shape, transform } => {
             let inv_transform = transform.inverse();
 
             // Трансформируем луч в локальные координаты
-            let local_origin = vectors::difference(origin, inv_transform.translation);
-            let local_origin = Vector3f::from_vec(crate::vectors::multiply_vec_and_mat(
-                local_origin.to_vec(),
-                inv_transform.rotation,
-            ));
+            let local_origin = inv_transform.transform_point(origin);
             let local_direction = Vector3f::from_vec(crate::vectors::multiply_vec_and_mat(
                 direction.to_vec(),
                 inv_transform.rotation,
@@ -690,7 +1125,7 @@ fn intersect_ray_with_shape(origin: Vector3f, direction: Vector3f, shape: &Shape
             // Преобразуем точки и нормали обратно в мировые координаты
             let mut world_hits = HitList::new();
             for mut hit in local_hits {
-                hit.point = transform.transform_point(hit.point);
+                (hit.point, hit.error) = transform.transform_point_with_error(hit.point, hit.error);
                 hit.normal = vectors::normalize(transform.transform_direction(hit.normal));
                 world_hits.push(hit);
             }
@@ -700,6 +1135,7 @@ fn intersect_ray_with_shape(origin: Vector3f, direction: Vector3f, shape: &Shape
 }
 
 fn merge_csg_hits(left_hits: HitList, right_hits: HitList, op: &CSGOperation) -> HitList {
+    stats::count(|counters| counters.csg_merges += 1);
     let mut all_events = Vec::new();
     all_events.extend(left_hits.iter().map(|h| (h, true)));
     all_events.extend(right_hits.iter().map(|h| (h, false)));
@@ -756,40 +1192,370 @@ fn is_inside_csg(in_left: bool, in_right: bool, op: &CSGOperation) -> bool {
     }
 }
 
+/// Водонепроницаемый тест пересечения луча с треугольником (Woop, Benthin, Wald, 2013).
+/// Вершины переводятся в систему координат, где луч выходит из начала координат вдоль оси z,
+/// и попадание определяется знаками рёберных функций. Если луч проходит точно через общее ребро
+/// или вершину, он попадает хотя бы в один из соседних треугольников, поэтому в сетках не бывает щелей.
+/// Минимальное t выводится из оценки погрешности вычислений (Pharr et al., PBRT, 3.9.6),
+/// вместо фиксированного порога.
 fn intersect_ray_with_triangle(origin: Vector3f, direction: Vector3f, triangle: Triangle) -> Option<Hit> {
-    let edge1 = vectors::difference(triangle.v1, triangle.v0);
-    let edge2 = vectors::difference(triangle.v2, triangle.v0);
-    let h = vectors::cross_product(direction, edge2);
-    let a = vectors::dot_product(edge1, h);
-    if a > -1e-8 && a < 1e-8 {
-        return None; // параллель
-    }
-    let f = 1.0 / a;
-    let s = vectors::difference(origin, triangle.v0);
-    let u = f * vectors::dot_product(s, h);
-    if u < 0.0 || u > 1.0 {
+    stats::count(|counters| counters.triangle_tests += 1);
+    let d = direction.to_vec();
+
+    // ось z - та, вдоль которой луч направлен сильнее всего
+    let kz = if d[0].abs() > d[1].abs() {
+        if d[0].abs() > d[2].abs() {
+            0
+        } else {
+            2
+        }
+    } else if d[1].abs() > d[2].abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    // сохраняем ориентацию треугольника
+    if d[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let shear_x = d[kx] / d[kz];
+    let shear_y = d[ky] / d[kz];
+    let shear_z = 1.0 / d[kz];
+
+    let a = vectors::difference(triangle.v0, origin).to_vec();
+    let b = vectors::difference(triangle.v1, origin).to_vec();
+    let c = vectors::difference(triangle.v2, origin).to_vec();
+
+    let ax = a[kx] - shear_x * a[kz];
+    let ay = a[ky] - shear_y * a[kz];
+    let bx = b[kx] - shear_x * b[kz];
+    let by = b[ky] - shear_y * b[kz];
+    let cx = c[kx] - shear_x * c[kz];
+    let cy = c[ky] - shear_y * c[kz];
+
+    // рёберные функции, они же ненормированные барицентрические координаты вершин
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None; // луч параллелен плоскости или треугольник вырожден
+    }
+
+    let az = shear_z * a[kz];
+    let bz = shear_z * b[kz];
+    let cz = shear_z * c[kz];
+    let scaled_t = u * az + v * bz + w * cz;
+    if (det < 0.0 && scaled_t >= 0.0) || (det > 0.0 && scaled_t <= 0.0) {
         return None;
     }
-    let q = vectors::cross_product(s, edge1);
-    let v = f * vectors::dot_product(direction, q);
-    if v < 0.0 || u + v > 1.0 {
+
+    let inv_det = 1.0 / det;
+    let t = scaled_t * inv_det;
+
+    // t должно быть больше погрешности его вычисления
+    let max_z = az.abs().max(bz.abs()).max(cz.abs());
+    let max_x = ax.abs().max(bx.abs()).max(cx.abs());
+    let max_y = ay.abs().max(by.abs()).max(cy.abs());
+    let delta_z = gamma(3) * max_z;
+    let delta_x = gamma(5) * (max_x + max_z);
+    let delta_y = gamma(5) * (max_y + max_z);
+    let delta_e = 2.0 * (gamma(2) * max_x * max_y + delta_y * max_x + delta_x * max_y);
+    let max_e = u.abs().max(v.abs()).max(w.abs());
+    let delta_t = 3.0 * (gamma(3) * max_e * max_z + delta_e * max_z + delta_z * max_e) * inv_det.abs();
+    if t <= delta_t {
         return None;
     }
-    let t = f * vectors::dot_product(edge2, q);
-    if t > 1e-8 {
-        Some(Hit {
-            t,
-            point: vectors::sum(origin, vectors::scale(t, direction)),
-            normal: triangle.normal,
-            color: triangle.color,
-            specular: triangle.specular,
-            reflective: triangle.reflective,
-        })
-    } else {
-        None
+
+    // точка по барицентрическим координатам точнее, чем origin + t * direction
+    let b0 = vectors::scale(u * inv_det, triangle.v0);
+    let b1 = vectors::scale(v * inv_det, triangle.v1);
+    let b2 = vectors::scale(w * inv_det, triangle.v2);
+    let point = vectors::sum(vectors::sum(b0, b1), b2);
+    let error = vectors::scale(
+        gamma(7),
+        vectors::sum(
+            vectors::sum(abs_components(b0), abs_components(b1)),
+            abs_components(b2),
+        ),
+    );
+
+    Some(Hit {
+        t,
+        point,
+        error,
+        normal: triangle.normal,
+        color: triangle.color,
+        specular: triangle.specular,
+        reflective: triangle.reflective,
+        roughness: triangle.roughness,
+    })
+}
+
+/// Граница относительной погрешности n последовательных операций с плавающей точкой
+fn gamma(n: u32) -> f64 {
+    let epsilon = f64::EPSILON * 0.5;
+    n as f64 * epsilon / (1.0 - n as f64 * epsilon)
+}
+
+fn abs_components(vector: Vector3f) -> Vector3f {
+    Vector3f::new(vector.x.abs(), vector.y.abs(), vector.z.abs())
+}
+
+/// Начало вторичного луча, сдвинутое вдоль нормали за пределы области погрешности точки попадания,
+/// в ту сторону поверхности, куда уходит луч. Так луч не пересекает поверхность, с которой стартует.
+fn offset_ray_origin(hit: &Hit, direction: Vector3f) -> Vector3f {
+    let distance = vectors::dot_product(abs_components(hit.normal), hit.error);
+    let mut offset = vectors::scale(distance, hit.normal);
+    if vectors::dot_product(direction, hit.normal) < 0.0 {
+        offset = vectors::negate(offset);
+    }
+    let origin = vectors::sum(hit.point, offset).to_vec();
+
+    // округление при сложении тоже может вернуть точку внутрь области погрешности
+    let offset = offset.to_vec();
+    Vector3f::from_vec([0, 1, 2].map(|i| {
+        if offset[i] > 0.0 {
+            origin[i].next_up()
+        } else if offset[i] < 0.0 {
+            origin[i].next_down()
+        } else {
+            origin[i]
+        }
+    }))
+}
+
+#[test]
+fn test_glossy_reflection_without_roughness_is_mirror() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mirror = Vector3f::new(0.0, 1.0, 1.0);
+    let direction = glossy_reflection(mirror, Vector3f::new(0.0, 1.0, 0.0), 0.0, &mut rng);
+    assert!(test_utils::roughly_equals(direction.x, mirror.x));
+    assert!(test_utils::roughly_equals(direction.y, mirror.y));
+    assert!(test_utils::roughly_equals(direction.z, mirror.z));
+}
+
+#[test]
+fn test_glossy_reflection_stays_near_mirror_and_above_surface() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let normal = Vector3f::new(0.0, 1.0, 0.0);
+    let mirror = vectors::normalize(Vector3f::new(0.0, 1.0, 1.0));
+
+    let mut average_cos = 0.0;
+    for _ in 0..1000 {
+        let direction = glossy_reflection(mirror, normal, 0.3, &mut rng);
+        assert!(test_utils::roughly_equals(vectors::length(direction), 1.0));
+        assert!(vectors::dot_product(direction, normal) > 0.0);
+        average_cos += vectors::dot_product(direction, mirror) / 1000.0;
+    }
+    // при шероховатости 0.3 лучи отклоняются от зеркального направления в среднем на ~15 градусов
+    assert!(
+        average_cos > 0.9 && average_cos < 1.0,
+        "average cos is {}",
+        average_cos
+    );
+}
+
+#[cfg(test)]
+fn test_quad(offset: Vector3f) -> Vec<Shape> {
+    // квадрат из двух треугольников с общей диагональю от (0, 0) до (1, 1)
+    let vertex = |x: f64, y: f64| vectors::sum(offset, Vector3f::new(x, y, 0.0));
+    let color = Color { r: 255, g: 255, b: 255 };
+    vec![Shape::Mesh(Mesh::new(vec![
+        Triangle::new(
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1.0),
+            color,
+            0,
+            0.0,
+        ),
+        Triangle::new(
+            vertex(0.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+            color,
+            0,
+            0.0,
+        ),
+    ]))]
+}
+
+#[test]
+fn test_triangle_hit_on_edge_and_vertex() {
+    let triangle = Triangle::new(
+        Vector3f::new(0.0, 0.0, 5.0),
+        Vector3f::new(1.0, 0.0, 5.0),
+        Vector3f::new(0.0, 1.0, 5.0),
+        Color { r: 255, g: 255, b: 255 },
+        0,
+        0.0,
+    );
+    let origin = Vector3f::new(0.2, 0.3, 0.0);
+
+    for target in [
+        Vector3f::new(0.5, 0.0, 5.0), // на ребре
+        Vector3f::new(0.0, 0.0, 5.0), // в вершине
+        Vector3f::new(1.0, 0.0, 5.0), // в вершине
+    ] {
+        let direction = vectors::difference(target, origin);
+        let hit = intersect_ray_with_triangle(origin, direction, triangle)
+            .unwrap_or_else(|| panic!("{} {} should be hit", target.x, target.y));
+        assert!(test_utils::roughly_equals(hit.t, 1.0));
+    }
+
+    let outside = vectors::difference(Vector3f::new(0.6, 0.6, 5.0), origin);
+    assert!(intersect_ray_with_triangle(origin, outside, triangle).is_none());
+}
+
+#[test]
+fn test_shared_vertex_of_triangle_fan_is_hit() {
+    // веер треугольников вокруг общей вершины: лучи в вершину и вдоль общих рёбер
+    // должны попадать хотя бы в один треугольник
+    let center = Vector3f::new(0.1, 0.2, 5.0);
+    let spoke = |i: usize| {
+        let angle = (i % 6) as f64 * std::f64::consts::PI / 3.0 + 0.1;
+        vectors::sum(center, Vector3f::new(angle.cos(), angle.sin(), 0.0))
+    };
+    let color = Color { r: 255, g: 255, b: 255 };
+    let scene: Vec<Shape> = (0..6)
+        .map(|i| Shape::Triangle(Triangle::new(center, spoke(i), spoke(i + 1), color, 0, 0.0)))
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..1000 {
+        let origin = Vector3f::new(
+            rng.gen_range(-2.0..2.0),
+            rng.gen_range(-2.0..2.0),
+            rng.gen_range(-2.0..0.0),
+        );
+        let s: f64 = rng.gen();
+        let on_edge = vectors::sum(
+            center,
+            vectors::scale(s, vectors::difference(spoke(rng.gen_range(0..6)), center)),
+        );
+        for target in [center, on_edge] {
+            let direction = vectors::difference(target, origin);
+            assert!(closest_intersection(origin, direction, 0.0, f64::INFINITY, &scene).is_some());
+        }
+    }
+}
+
+#[test]
+fn test_shared_edge_has_no_cracks_far_from_origin() {
+    for offset in [Vector3f::zero_vector(), Vector3f::new(1e6, -1e6, 1e6)] {
+        let scene = test_quad(offset);
+        let origin = vectors::sum(offset, Vector3f::new(0.3, -0.7, -3.0));
+
+        // лучи точно в общую диагональ, в том числе в её концы
+        for i in 0..=1000 {
+            let s = i as f64 / 1000.0;
+            let target = vectors::sum(offset, Vector3f::new(s, s, 0.0));
+            let direction = vectors::difference(target, origin);
+            assert!(
+                closest_intersection(origin, direction, 0.0, f64::INFINITY, &scene).is_some(),
+                "crack at {}",
+                s
+            );
+        }
     }
 }
 
+#[test]
+fn test_secondary_rays_do_not_hit_own_surface_far_from_origin() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let offset = Vector3f::new(1e6, -1e6, 1e6);
+    let sphere = Shape::Sphere(Sphere {
+        center: vectors::sum(offset, Vector3f::new(0.5, 0.5, 1000.0)),
+        radius: 999.0,
+        color: Color { r: 255, g: 255, b: 255 },
+        specular: 0,
+        reflective: 0.0,
+        roughness: 0.0,
+    });
+
+    for scene in [test_quad(offset), vec![sphere]] {
+        let origin = vectors::sum(offset, Vector3f::new(0.3, -0.7, -3.0));
+        for _ in 0..1000 {
+            let target = vectors::sum(offset, Vector3f::new(rng.gen(), rng.gen(), 0.0));
+            let direction = vectors::difference(target, origin);
+            let hit = closest_intersection(origin, direction, 0.0, f64::INFINITY, &scene).unwrap();
+
+            // случайное направление в полусферу перед поверхностью: ни квадрат, ни выпуклая сфера
+            // не могут оказаться на пути такого луча
+            let random = Vector3f::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let outgoing = if vectors::dot_product(random, hit.normal) > 0.0 {
+                random
+            } else {
+                vectors::negate(random)
+            };
+            let secondary_origin = offset_ray_origin(&hit, outgoing);
+            assert!(closest_intersection(secondary_origin, outgoing, 0.0, f64::INFINITY, &scene).is_none());
+        }
+    }
+}
+
+#[test]
+fn test_render_stats() {
+    let scene = vec![Shape::Sphere(Sphere {
+        center: Vector3f::new(0.0, 0.0, 5.0),
+        radius: 1.0,
+        color: Color { r: 255, g: 0, b: 0 },
+        specular: 0,
+        reflective: 0.5,
+        roughness: 0.0,
+    })];
+    let lights = vec![Light::Point {
+        intensity: 1.0,
+        position: Vector3f::new(0.0, 5.0, 0.0),
+    }];
+    let size = 40;
+    let mut buffer = vec![0u8; size * size * 3];
+
+    let stats = render_scene_to_buffer(
+        &scene,
+        &lights,
+        &mut buffer,
+        size,
+        Vector3f::zero_vector(),
+        vectors::rotate_y(0.0),
+    );
+
+    // 40x40 пикселей делятся на тайлы 32x32, 8x32, 32x8 и 8x8
+    assert_eq!(stats.tiles.len(), 4);
+    assert_eq!(
+        stats.tiles.iter().map(|tile| tile.width * tile.height).sum::<usize>(),
+        size * size
+    );
+    if !cfg!(feature = "stats") {
+        // без feature `stats` лучи не считаются
+        assert_eq!(stats.counters, stats::RayCounters::default());
+        return;
+    }
+    assert_eq!(stats.counters.primary_rays, (size * size) as u64);
+    // на каждое попадание в сферу - один теневой и один отражённый луч, отражение от выпуклой сферы
+    // ни во что не попадает, поэтому тестов сферы столько же, сколько всех лучей
+    let hits = stats.counters.shadow_rays;
+    assert!(hits > 0);
+    assert_eq!(stats.counters.reflection_rays, hits);
+    assert_eq!(
+        stats.counters.sphere_tests,
+        stats.counters.primary_rays + 2 * hits
+    );
+    assert_eq!(stats.counters.triangle_tests, 0);
+}
+
 #[test]
 fn test_canvas_to_viewport() {
     let point = canvas_to_viewport(500, 500, 1000, 1000);
@@ -797,3 +1563,28 @@ fn test_canvas_to_viewport() {
     assert!(test_utils::roughly_equals(point.y, 0.5));
     assert!(test_utils::roughly_equals(point.z, 1.0));
 }
+
+#[test]
+fn test_mesh_to_triangle_mesh() {
+    let color = Color { r: 255, g: 255, b: 255 };
+    let (a, b, c, d) = (
+        Vector3f::new(0.0, 0.0, 0.0),
+        Vector3f::new(0.0, 1.0, 0.0),
+        Vector3f::new(1.0, 1.0, 0.0),
+        Vector3f::new(1.0, 0.0, 0.0),
+    );
+    let mesh = Mesh::new(vec![
+        Triangle::new(a, b, c, color, 0, 0.0),
+        Triangle::new(a, c, d, color, 0, 0.0),
+    ]);
+
+    // у квадрата четыре вершины, две из них общие
+    let exported = mesh.to_triangle_mesh("square");
+    assert_eq!(exported.name, "square");
+    assert_eq!(exported.positions.len(), 4);
+    assert_eq!(exported.triangles[1].positions, [0, 2, 3]);
+    assert!(exported.triangles.iter().all(|triangle| triangle.normals.is_none()));
+    // нормаль грани та же, что у треугольника трассировщика
+    let normal = exported.face_normal(&exported.triangles[0]);
+    assert_eq!(normal.to_vec(), mesh.triangles[0].normal.to_vec());
+}
@@ -0,0 +1,314 @@
+//! Однородные участвующие среды: глобальный туман и объёмы `Shape::Medium`.
+//!
+//! Вдоль луча среда ослабляет свет по закону Бугера-Ламберта и добавляет свет, рассеянный
+//! в сторону камеры (однократное рассеяние). Рассеянный ambient свет считается аналитически,
+//! а свет от точечных и направленных источников - в нескольких точках отрезка с проверкой тени,
+//! поэтому в тумане за объектами появляются световые лучи. Фазовая функция изотропная,
+//! её нормировка входит в коэффициент рассеяния.
+
+use crate::{closest_intersection, intersect_ray_with_shape, HdrColor, Medium, Shape, TraceContext};
+use common::vectors;
+use common::{Light, Vector3f};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+/// Оптическая толщина, после которой через среду проходит меньше 0.1% света
+const MAX_OPTICAL_DEPTH: f64 = 6.9;
+
+/// Участок луча (в единицах параметра t) с постоянными свойствами среды
+struct Segment {
+    start: f64,
+    end: f64,
+    /// Коэффициент ослабления на единицу t
+    extinction: f64,
+    /// Цвет рассеянного света, умноженный на коэффициент рассеяния на единицу t
+    scattering: HdrColor,
+}
+
+/// Ослабляет цвет поверхности средами на отрезке луча `[min_t, max_t]` и добавляет рассеянный ими свет
+pub fn apply_media(
+    context: &TraceContext,
+    origin: Vector3f,
+    direction: Vector3f,
+    min_t: f64,
+    max_t: f64,
+    surface_color: HdrColor,
+    rng: &mut StdRng,
+) -> HdrColor {
+    if context.media.is_empty() && context.settings.fog.is_none() {
+        return surface_color;
+    }
+
+    let ambient: f64 = context
+        .lights
+        .iter()
+        .map(|light| match *light {
+            Light::Ambient { intensity } => intensity,
+            _ => 0.0,
+        })
+        .sum();
+    let samples = context.settings.volume_samples.max(1);
+
+    let mut transmittance = 1.0;
+    let mut in_scattered = HdrColor::black();
+
+    for segment in segments(context, origin, direction, min_t, max_t, true) {
+        // бесконечный участок обрезаем там, где свет практически полностью поглощён
+        let end = if segment.end.is_finite() {
+            segment.end
+        } else {
+            segment.start + MAX_OPTICAL_DEPTH / segment.extinction
+        };
+        let length = end - segment.start;
+        let segment_transmittance = (-segment.extinction * length).exp();
+
+        let mut light = ambient * (1.0 - segment_transmittance) / segment.extinction;
+
+        // стратифицированная выборка точек на участке
+        let dt = length / samples as f64;
+        for i in 0..samples {
+            let t = segment.start + (i as f64 + rng.gen::<f64>()) * dt;
+            let point = vectors::sum(origin, vectors::scale(t, direction));
+            light += direct_light(context, point) * (-segment.extinction * (t - segment.start)).exp() * dt;
+        }
+
+        in_scattered = in_scattered + segment.scattering.scale(light * transmittance);
+        transmittance *= segment_transmittance;
+    }
+
+    surface_color.scale(transmittance) + in_scattered
+}
+
+/// Доля света, прошедшего через объёмы со средой на отрезке луча. Туман здесь не учитывается.
+pub fn transmittance(
+    context: &TraceContext,
+    origin: Vector3f,
+    direction: Vector3f,
+    min_t: f64,
+    max_t: f64,
+) -> f64 {
+    if context.media.is_empty() {
+        return 1.0;
+    }
+
+    let mut optical_depth = 0.0;
+    for segment in segments(context, origin, direction, min_t, max_t, false) {
+        if !segment.end.is_finite() {
+            return 0.0;
+        }
+        optical_depth += segment.extinction * (segment.end - segment.start);
+    }
+    (-optical_depth).exp()
+}
+
+/// Прямой свет от точечных и направленных источников, дошедший до точки внутри среды
+fn direct_light(context: &TraceContext, point: Vector3f) -> f64 {
+    let mut result = 0.0;
+    for light in context.lights {
+        let (intensity, light_direction, max_t) = match *light {
+            Light::Ambient { .. } => continue,
+            Light::Point { intensity, position } => (intensity, vectors::difference(position, point), 1.0),
+            Light::Directional { intensity, direction } => (intensity, direction, f64::INFINITY),
+        };
+        // точка внутри среды не лежит на поверхности, поэтому смещать начало луча не нужно
+        crate::stats::count(|counters| counters.shadow_rays += 1);
+        if closest_intersection(point, light_direction, 0.0, max_t, context.scene).is_none() {
+            result += intensity * transmittance(context, point, light_direction, 0.0, max_t);
+        }
+    }
+    result
+}
+
+/// Разбивает отрезок луча на участки, внутри которых свойства среды не меняются.
+/// Пересекающиеся объёмы складываются.
+fn segments(
+    context: &TraceContext,
+    origin: Vector3f,
+    direction: Vector3f,
+    min_t: f64,
+    max_t: f64,
+    include_fog: bool,
+) -> Vec<Segment> {
+    // коэффициенты заданы на единицу мирового расстояния, а участки измеряются в t
+    let direction_length = vectors::length(direction);
+
+    let mut intervals: Vec<(f64, f64, &Medium)> = Vec::new();
+    if include_fog {
+        if let Some(fog) = &context.settings.fog {
+            intervals.push((min_t, max_t, fog));
+        }
+    }
+    for (boundary, medium) in &context.media {
+        for (start, end) in medium_intervals(boundary, origin, direction, min_t, max_t) {
+            intervals.push((start, end, medium));
+        }
+    }
+
+    let mut breakpoints: Vec<f64> = intervals.iter().flat_map(|(start, end, _)| [*start, *end]).collect();
+    breakpoints.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    breakpoints.dedup();
+
+    let mut result = Vec::new();
+    for pair in breakpoints.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let middle = if end.is_finite() {
+            (start + end) / 2.0
+        } else {
+            start + 1.0
+        };
+
+        let mut extinction = 0.0;
+        let mut scattering = HdrColor::black();
+        for (interval_start, interval_end, medium) in &intervals {
+            if *interval_start <= middle && middle < *interval_end {
+                extinction += medium.extinction() * direction_length;
+                scattering = scattering
+                    + HdrColor::from_color(medium.color).scale(medium.scattering * direction_length);
+            }
+        }
+
+        if extinction > 0.0 {
+            result.push(Segment { start, end, extinction, scattering });
+        }
+    }
+    result
+}
+
+/// Участки луча внутри замкнутой границы. Вход и выход различаются по направлению нормали,
+/// поэтому луч, начинающийся внутри объёма, тоже обрабатывается правильно.
+fn medium_intervals(
+    boundary: &Shape,
+    origin: Vector3f,
+    direction: Vector3f,
+    min_t: f64,
+    max_t: f64,
+) -> Vec<(f64, f64)> {
+    let mut hits: Vec<_> = intersect_ray_with_shape(origin, direction, boundary)
+        .into_iter()
+        .filter(|hit| hit.t > min_t)
+        .collect();
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+
+    let mut intervals = Vec::new();
+    let mut start = match hits.first() {
+        Some(hit) if vectors::dot_product(direction, hit.normal) > 0.0 => Some(min_t),
+        _ => None,
+    };
+
+    for hit in hits {
+        if hit.t >= max_t {
+            break;
+        }
+        let entering = vectors::dot_product(direction, hit.normal) < 0.0;
+        if entering {
+            start.get_or_insert(hit.t);
+        } else if let Some(start) = start.take() {
+            intervals.push((start, hit.t));
+        }
+    }
+    if let Some(start) = start {
+        intervals.push((start, max_t));
+    }
+
+    intervals
+}
+
+#[cfg(test)]
+fn test_medium_sphere(absorption: f64, scattering: f64) -> Shape {
+    Shape::Medium {
+        boundary: Box::new(Shape::Sphere(crate::Sphere {
+            center: Vector3f::new(0.0, 0.0, 5.0),
+            radius: 1.0,
+            color: common::Color { r: 0, g: 0, b: 0 },
+            specular: 0,
+            reflective: 0.0,
+            roughness: 0.0,
+        })),
+        medium: Medium {
+            absorption,
+            scattering,
+            color: common::Color { r: 255, g: 255, b: 255 },
+        },
+    }
+}
+
+#[test]
+fn test_medium_is_invisible_to_surfaces() {
+    let scene = vec![test_medium_sphere(1.0, 0.0)];
+    let hit = closest_intersection(
+        Vector3f::zero_vector(),
+        Vector3f::new(0.0, 0.0, 1.0),
+        0.0,
+        f64::INFINITY,
+        &scene,
+    );
+    assert!(hit.is_none());
+}
+
+#[test]
+fn test_transmittance_through_medium() {
+    let scene = vec![test_medium_sphere(0.3, 0.2)];
+    let lights = vec![];
+    let settings = crate::RenderSettings::default();
+    let context = TraceContext::new(&scene, &lights, &settings);
+
+    // луч проходит через весь шар: путь длиной 2 в среде
+    let through = transmittance(
+        &context,
+        Vector3f::zero_vector(),
+        Vector3f::new(0.0, 0.0, 2.0),
+        0.0,
+        10.0,
+    );
+    assert!(test_utils::roughly_equals(through, (-1.0f64).exp()));
+
+    // луч начинается в центре шара: путь длиной 1
+    let from_inside = transmittance(
+        &context,
+        Vector3f::new(0.0, 0.0, 5.0),
+        Vector3f::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+    assert!(test_utils::roughly_equals(from_inside, (-0.5f64).exp()));
+
+    // луч проходит мимо шара
+    let missed = transmittance(
+        &context,
+        Vector3f::zero_vector(),
+        Vector3f::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+    assert!(test_utils::roughly_equals(missed, 1.0));
+}
+
+#[test]
+fn test_dense_fog_hides_background() {
+    let scene = vec![];
+    let lights = vec![Light::Ambient { intensity: 1.0 }];
+    let settings = crate::RenderSettings {
+        fog: Some(Medium {
+            absorption: 0.0,
+            scattering: 0.5,
+            color: common::Color { r: 100, g: 150, b: 200 },
+        }),
+        ..Default::default()
+    };
+    let context = TraceContext::new(&scene, &lights, &settings);
+    let mut rng = <StdRng as rand::SeedableRng>::seed_from_u64(0);
+
+    // без поглощения бесконечно плотный туман виден цветом рассеянного ambient света
+    let color = apply_media(
+        &context,
+        Vector3f::zero_vector(),
+        Vector3f::new(0.0, 0.0, 1.0),
+        1.0,
+        f64::INFINITY,
+        HdrColor::black(),
+        &mut rng,
+    );
+    assert!((color.r - 100.0).abs() < 0.5, "r is {}", color.r);
+    assert!((color.g - 150.0).abs() < 0.5, "g is {}", color.g);
+    assert!((color.b - 200.0).abs() < 0.5, "b is {}", color.b);
+}
@@ -0,0 +1,182 @@
+//! Статистика рендеринга кадра: количество лучей и тестов пересечения, время по тайлам.
+//!
+//! Счётчики хранятся в thread-local переменной потока, который рендерит тайл, поэтому
+//! функции пересечения не нужно передавать никакого дополнительного состояния,
+//! а потоки rayon не конкурируют за общие атомарные счётчики. Лучи и тесты считаются
+//! только с feature `stats`, без неё счётчики остаются нулевыми, а время тайлов измеряется всегда.
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Add;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RayCounters {
+    pub primary_rays: u64,
+    pub shadow_rays: u64,
+    pub reflection_rays: u64,
+    pub triangle_tests: u64,
+    pub sphere_tests: u64,
+    pub csg_merges: u64,
+}
+
+impl Add for RayCounters {
+    type Output = RayCounters;
+
+    fn add(self, other: RayCounters) -> RayCounters {
+        RayCounters {
+            primary_rays: self.primary_rays + other.primary_rays,
+            shadow_rays: self.shadow_rays + other.shadow_rays,
+            reflection_rays: self.reflection_rays + other.reflection_rays,
+            triangle_tests: self.triangle_tests + other.triangle_tests,
+            sphere_tests: self.sphere_tests + other.sphere_tests,
+            csg_merges: self.csg_merges + other.csg_merges,
+        }
+    }
+}
+
+impl RayCounters {
+    fn write_json_fields(&self, out: &mut String) {
+        out.push_str(&format!(
+            "\"primary_rays\": {}, \"shadow_rays\": {}, \"reflection_rays\": {}, \
+             \"triangle_tests\": {}, \"sphere_tests\": {}, \"csg_merges\": {}",
+            self.primary_rays,
+            self.shadow_rays,
+            self.reflection_rays,
+            self.triangle_tests,
+            self.sphere_tests,
+            self.csg_merges
+        ));
+    }
+}
+
+/// Прямоугольник кадра в экранных координатах и время его рендеринга
+#[derive(Clone, Debug)]
+pub struct TileStats {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub time: Duration,
+    pub counters: RayCounters,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    /// Сумма счётчиков по всем тайлам
+    pub counters: RayCounters,
+    pub tiles: Vec<TileStats>,
+    /// Время рендеринга всего кадра (тайлы рендерятся параллельно, поэтому это не сумма их времени)
+    pub total_time: Duration,
+}
+
+impl RenderStats {
+    pub fn slowest_tile(&self) -> Option<&TileStats> {
+        self.tiles.iter().max_by_key(|tile| tile.time)
+    }
+
+    /// Статистика в виде JSON, чтобы сравнивать производительность между коммитами
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"total_time_ms\": {:.3},\n  ",
+            milliseconds(self.total_time)
+        ));
+        self.counters.write_json_fields(&mut out);
+        out.push_str(",\n  \"tiles\": [");
+        for (index, tile) in self.tiles.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "\n    {{\"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \"time_ms\": {:.3}, ",
+                tile.x,
+                tile.y,
+                tile.width,
+                tile.height,
+                milliseconds(tile.time)
+            ));
+            tile.counters.write_json_fields(&mut out);
+            out.push('}');
+        }
+        out.push_str("\n  ]\n}\n");
+        out
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counters = &self.counters;
+        writeln!(f, "frame time:       {:?}", self.total_time)?;
+        writeln!(f, "primary rays:     {}", counters.primary_rays)?;
+        writeln!(f, "shadow rays:      {}", counters.shadow_rays)?;
+        writeln!(f, "reflection rays:  {}", counters.reflection_rays)?;
+        writeln!(f, "triangle tests:   {}", counters.triangle_tests)?;
+        writeln!(f, "sphere tests:     {}", counters.sphere_tests)?;
+        write!(f, "CSG merges:       {}", counters.csg_merges)?;
+        if let Some(tile) = self.slowest_tile() {
+            write!(
+                f,
+                "\nslowest tile:     {:?} at ({}, {}) of {} tiles",
+                tile.time,
+                tile.x,
+                tile.y,
+                self.tiles.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+thread_local! {
+    static COUNTERS: Cell<RayCounters> = Cell::new(RayCounters::default());
+}
+
+/// Без feature `stats` ничего не делает, так что обычный рендеринг не тратит на счётчики время
+#[inline(always)]
+pub(crate) fn count(update: impl FnOnce(&mut RayCounters)) {
+    #[cfg(feature = "stats")]
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        update(&mut value);
+        counters.set(value);
+    });
+    #[cfg(not(feature = "stats"))]
+    let _ = update;
+}
+
+/// Возвращает накопленные в текущем потоке счётчики и обнуляет их
+pub(crate) fn take_counters() -> RayCounters {
+    COUNTERS.with(|counters| counters.take())
+}
+
+#[test]
+fn test_stats_json() {
+    let counters = RayCounters {
+        primary_rays: 4,
+        shadow_rays: 2,
+        ..Default::default()
+    };
+    let stats = RenderStats {
+        counters,
+        tiles: vec![TileStats {
+            x: 0,
+            y: 32,
+            width: 32,
+            height: 16,
+            time: Duration::from_micros(1500),
+            counters,
+        }],
+        total_time: Duration::from_millis(2),
+    };
+
+    let json = stats.to_json();
+
+    assert!(json.contains("\"total_time_ms\": 2.000"));
+    assert!(json.contains("\"primary_rays\": 4, \"shadow_rays\": 2, \"reflection_rays\": 0"));
+    assert!(json.contains("{\"x\": 0, \"y\": 32, \"width\": 32, \"height\": 16, \"time_ms\": 1.500, "));
+}
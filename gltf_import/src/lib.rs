@@ -0,0 +1,761 @@
+//! Importer of glTF 2.0 scenes, both `.gltf` files and binary `.glb` ones.
+//!
+//! Reads the meshes with their indices, normals, tangents and first texture coordinates,
+//! the PBR metallic-roughness materials, the images and the node hierarchy of the default scene.
+//! Buffers and images come from the binary chunk, from base64 data URIs or from files relative
+//! to the scene; other URIs are refused, nothing is downloaded. Cameras, lights, skins,
+//! morph targets, animations and samplers are skipped.
+//!
+//! glTF is right-handed with the camera looking down -z, the renderers here look down +z,
+//! so every position, direction and transform is mirrored along z on the way in. The mirror also
+//! turns the counter-clockwise front faces of glTF into the clockwise ones of the renderers,
+//! and texture coordinates start at the top left corner in both.
+//!
+//! The result doesn't depend on a renderer: `gambetta_rasterizer::gltf` and
+//! `gambetta_raytracer::gltf` turn it into their models.
+
+use base64::Engine;
+use common::{vectors, Color, Vector3f};
+use gltf::mesh::Mode;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Transform of homogeneous column vectors, indexed by row and then column
+pub type Matrix = [[f64; 4]; 4];
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The JSON, the binary container or the references between objects are invalid
+    Gltf(gltf::Error),
+    /// Neither a data URI nor a relative path
+    UnsupportedUri(String),
+    /// A data URI that isn't base64
+    InvalidDataUri(String),
+    /// A buffer of a `.glb` file refers to the binary chunk, but there is none
+    MissingBinaryChunk,
+    /// A buffer has fewer bytes than it declares
+    BufferTooShort {
+        buffer: usize,
+    },
+    Image {
+        image: usize,
+        error: image::ImageError,
+    },
+    /// A primitive without positions, with unreadable attributes or with indices out of range
+    InvalidPrimitive {
+        mesh: usize,
+        primitive: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Io { path, error } => write!(f, "error reading {}: {}", path.display(), error),
+            GltfError::Gltf(error) => write!(f, "invalid glTF: {}", error),
+            GltfError::UnsupportedUri(uri) => write!(f, "unsupported URI {}", uri),
+            GltfError::InvalidDataUri(uri) => write!(f, "invalid data URI {}", uri),
+            GltfError::MissingBinaryChunk => write!(f, "the binary chunk is missing"),
+            GltfError::BufferTooShort { buffer } => write!(f, "buffer {} is too short", buffer),
+            GltfError::Image { image, error } => write!(f, "error decoding image {}: {}", image, error),
+            GltfError::InvalidPrimitive { mesh, primitive, reason } => {
+                write!(f, "invalid primitive {} of mesh {}: {}", primitive, mesh, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Io { error, .. } => Some(error),
+            GltfError::Gltf(error) => Some(error),
+            GltfError::Image { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(error: gltf::Error) -> Self {
+        GltfError::Gltf(error)
+    }
+}
+
+pub struct Scene {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub images: Vec<Image>,
+    pub nodes: Vec<Node>,
+    /// Nodes at the top of the default scene, or of the first one when there is no default
+    pub roots: Vec<usize>,
+}
+
+impl Scene {
+    /// Every mesh of the scene with the world transform of the node that carries it,
+    /// parents before their children
+    pub fn mesh_instances(&self) -> Vec<(usize, Matrix)> {
+        let mut instances = vec![];
+        let mut stack: Vec<(usize, Matrix)> = self.roots.iter().rev().map(|&root| (root, IDENTITY)).collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let world = multiply(&parent, &node.transform);
+            if let Some(mesh) = node.mesh {
+                instances.push((mesh, world));
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        instances
+    }
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+}
+
+/// Triangles with one material; points and lines are skipped
+pub struct Primitive {
+    pub positions: Vec<Vector3f>,
+    pub normals: Option<Vec<Vector3f>>,
+    /// Direction of growing u, with the sign of the bitangent in the fourth element
+    pub tangents: Option<Vec<[f64; 4]>>,
+    /// From the first set of texture coordinates
+    pub uvs: Option<Vec<[f64; 2]>>,
+    /// Indexes into the attributes, clockwise seen from the front
+    pub triangles: Vec<[usize; 3]>,
+    /// Index into `Scene::materials`, `None` for the default material
+    pub material: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Points with the alpha below the cutoff are discarded
+    Mask {
+        cutoff: f64,
+    },
+    Blend,
+}
+
+/// Metallic-roughness material; textures are indexes into `Scene::images`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrMaterial {
+    pub name: String,
+    /// Linear RGBA, multiplied by the texture
+    pub base_color: [f64; 4],
+    pub base_color_texture: Option<usize>,
+    /// From 0 (dielectric) to 1 (metal)
+    pub metallic: f64,
+    /// From 0 (mirror) to 1
+    pub roughness: f64,
+    /// Roughness in the green channel, metallic in the blue one
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    /// Linear RGB
+    pub emissive: [f64; 3],
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for PbrMaterial {
+    /// Material of primitives without one
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            emissive: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl PbrMaterial {
+    /// Phong exponent with a highlight about as wide as the roughness gives,
+    /// 0 without highlights on fully rough surfaces
+    pub fn shininess(&self) -> i32 {
+        if self.roughness >= 1.0 {
+            0
+        } else {
+            (2.0 / self.roughness.max(0.05).powi(4) - 2.0).clamp(1.0, 1000.0) as i32
+        }
+    }
+}
+
+/// Decoded image with 4 bytes per texel, rows from the top
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+    /// Relative to the parent
+    pub transform: Matrix,
+}
+
+/// Loads a `.gltf` or `.glb` file with the files it refers to
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, GltfError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|error| GltfError::Io { path: path.to_path_buf(), error })?;
+    parse(&bytes, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Parses the contents of a `.gltf` or `.glb` file; relative URIs are resolved from the directory
+pub fn parse(bytes: &[u8], directory: &Path) -> Result<Scene, GltfError> {
+    let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice(bytes)?;
+
+    let mut buffers = vec![];
+    for buffer in document.buffers() {
+        let mut data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob.take().ok_or(GltfError::MissingBinaryChunk)?,
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, directory)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(GltfError::BufferTooShort { buffer: buffer.index() });
+        }
+        data.truncate(buffer.length());
+        buffers.push(data);
+    }
+
+    let images = document
+        .images()
+        .map(|image| {
+            let bytes = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    let start = view.offset();
+                    buffers[view.buffer().index()]
+                        .get(start..start + view.length())
+                        .ok_or(GltfError::BufferTooShort { buffer: view.buffer().index() })?
+                        .to_vec()
+                }
+                gltf::image::Source::Uri { uri, .. } => read_uri(uri, directory)?,
+            };
+            let decoded = image::load_from_memory(&bytes)
+                .map_err(|error| GltfError::Image { image: image.index(), error })?
+                .to_rgba();
+            Ok(Image {
+                width: decoded.width() as usize,
+                height: decoded.height() as usize,
+                rgba: decoded.into_raw(),
+            })
+        })
+        .collect::<Result<_, GltfError>>()?;
+
+    let materials = document.materials().map(material).collect();
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| {
+            let primitives = mesh
+                .primitives()
+                .filter(|primitive| {
+                    matches!(
+                        primitive.mode(),
+                        Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+                    )
+                })
+                .map(|primitive| read_primitive(&mesh, &primitive, &buffers))
+                .collect::<Result<_, _>>()?;
+            Ok(Mesh {
+                name: mesh.name().unwrap_or_default().to_string(),
+                primitives,
+            })
+        })
+        .collect::<Result<_, GltfError>>()?;
+
+    let nodes = document
+        .nodes()
+        .map(|node| {
+            let columns = node.transform().matrix();
+            let mut transform = IDENTITY;
+            for (row, elements) in transform.iter_mut().enumerate() {
+                for (column, element) in elements.iter_mut().enumerate() {
+                    *element = columns[column][row] as f64 * mirror(row) * mirror(column);
+                }
+            }
+            Node {
+                name: node.name().unwrap_or_default().to_string(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+                transform,
+            }
+        })
+        .collect();
+
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map_or(vec![], |scene| scene.nodes().map(|node| node.index()).collect());
+
+    Ok(Scene { meshes, materials, images, nodes, roots })
+}
+
+/// Product of two transforms: `second` is applied first
+pub fn multiply(first: &Matrix, second: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (row, elements) in result.iter_mut().enumerate() {
+        for (column, element) in elements.iter_mut().enumerate() {
+            *element = (0..4).map(|k| first[row][k] * second[k][column]).sum();
+        }
+    }
+    result
+}
+
+/// Translation, rotation and scale of a transform: the point is scaled along the axes,
+/// rotated and then translated. The rotation is for column vectors. A mirroring transform
+/// gets a negative scale along x; shear is lost.
+pub fn decompose(matrix: &Matrix) -> (Vector3f, [[f64; 3]; 3], Vector3f) {
+    let translation = Vector3f::new(matrix[0][3], matrix[1][3], matrix[2][3]);
+    let axis = |column: usize| Vector3f::new(matrix[0][column], matrix[1][column], matrix[2][column]);
+    let (x, y, z) = (axis(0), axis(1), axis(2));
+
+    let mirrored = vectors::dot_product(vectors::cross_product(x, y), z) < 0.0;
+    let scale = Vector3f::new(
+        if mirrored {
+            -vectors::length(x)
+        } else {
+            vectors::length(x)
+        },
+        vectors::length(y),
+        vectors::length(z),
+    );
+    let unscaled = |axis: Vector3f, scale: f64| {
+        if scale == 0.0 {
+            axis
+        } else {
+            vectors::scale(1.0 / scale, axis)
+        }
+    };
+    let (x, y, z) = (unscaled(x, scale.x), unscaled(y, scale.y), unscaled(z, scale.z));
+    let rotation = [[x.x, y.x, z.x], [x.y, y.y, z.y], [x.z, y.z, z.z]];
+    (translation, rotation, scale)
+}
+
+/// Color of the screen, in sRGB, from linear components like the factors of the materials
+pub fn srgb(linear: [f64; 3]) -> Color {
+    let channel = |linear: f64| {
+        let linear = linear.clamp(0.0, 1.0);
+        let encoded = if linear <= 0.0031308 {
+            12.92 * linear
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (encoded * 255.0).round() as u8
+    };
+    Color {
+        r: channel(linear[0]),
+        g: channel(linear[1]),
+        b: channel(linear[2]),
+    }
+}
+
+/// Sign of the coordinate in the mirror along z
+fn mirror(coordinate: usize) -> f64 {
+    if coordinate == 2 {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+fn mirrored(vector: [f32; 3]) -> Vector3f {
+    Vector3f::new(vector[0] as f64, vector[1] as f64, -vector[2] as f64)
+}
+
+fn material(material: gltf::Material) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let image = |info: Option<gltf::texture::Info>| info.map(|info| info.texture().source().index());
+    PbrMaterial {
+        name: material.name().unwrap_or_default().to_string(),
+        base_color: pbr.base_color_factor().map(f64::from),
+        base_color_texture: image(pbr.base_color_texture()),
+        metallic: pbr.metallic_factor() as f64,
+        roughness: pbr.roughness_factor() as f64,
+        metallic_roughness_texture: image(pbr.metallic_roughness_texture()),
+        normal_texture: material.normal_texture().map(|normal| normal.texture().source().index()),
+        emissive: material.emissive_factor().map(f64::from),
+        emissive_texture: image(material.emissive_texture()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5) as f64,
+            },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn read_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[Vec<u8>],
+) -> Result<Primitive, GltfError> {
+    let invalid = |reason: &str| GltfError::InvalidPrimitive {
+        mesh: mesh.index(),
+        primitive: primitive.index(),
+        reason: reason.to_string(),
+    };
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+
+    let positions: Vec<Vector3f> =
+        reader.read_positions().ok_or_else(|| invalid("no positions"))?.map(mirrored).collect();
+    let count = positions.len();
+    let normals: Option<Vec<_>> = reader.read_normals().map(|normals| normals.map(mirrored).collect());
+    let tangents: Option<Vec<_>> = reader
+        .read_tangents()
+        .map(|tangents| tangents.map(|[x, y, z, w]| [x as f64, y as f64, -z as f64, -w as f64]).collect());
+    let uvs: Option<Vec<_>> = reader
+        .read_tex_coords(0)
+        .map(|uvs| uvs.into_f32().map(|[u, v]| [u as f64, v as f64]).collect());
+    let lengths = [
+        normals.as_ref().map(Vec::len),
+        tangents.as_ref().map(Vec::len),
+        uvs.as_ref().map(Vec::len),
+    ];
+    if lengths.iter().flatten().any(|&length| length != count) {
+        return Err(invalid("attributes don't match the positions"));
+    }
+
+    let indices: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+        None => (0..count).collect(),
+    };
+    if indices.iter().any(|&index| index >= count) {
+        return Err(invalid("index out of range"));
+    }
+    let triangles = match primitive.mode() {
+        Mode::TriangleStrip => (2..indices.len())
+            .map(|i| {
+                // every other triangle of a strip is reversed to keep the winding
+                if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (2..indices.len()).map(|i| [indices[i - 1], indices[i], indices[0]]).collect(),
+        _ => indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect(),
+    };
+
+    Ok(Primitive {
+        positions,
+        normals,
+        tangents,
+        uvs,
+        triangles,
+        material: primitive.material().index(),
+    })
+}
+
+/// Contents of a base64 data URI or of a file relative to the directory
+fn read_uri(uri: &str, directory: &Path) -> Result<Vec<u8>, GltfError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, encoded) =
+            data.split_once(',').ok_or_else(|| GltfError::InvalidDataUri(shortened(uri)))?;
+        if !header.ends_with(";base64") {
+            return Err(GltfError::InvalidDataUri(shortened(uri)));
+        }
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| GltfError::InvalidDataUri(shortened(uri)));
+    }
+    if uri.contains(':') || uri.starts_with('/') {
+        return Err(GltfError::UnsupportedUri(uri.to_string()));
+    }
+
+    let path = directory.join(percent_decoded(uri));
+    fs::read(&path).map_err(|error| GltfError::Io { path, error })
+}
+
+/// Beginning of a data URI for the error messages
+fn shortened(uri: &str) -> String {
+    uri.chars().take(40).collect()
+}
+
+/// Replaces the escapes like `%20` of a relative URI
+fn percent_decoded(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Quad of the xy plane from 0 to 1 facing +z: positions, normals, texture coordinates, tangents
+/// and 16-bit indices of two triangles
+#[cfg(test)]
+fn quad_buffer() -> Vec<u8> {
+    let floats: [f32; 48] = [
+        0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, // positions
+        0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, // normals
+        0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, // texture coordinates
+        1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, // tangents
+    ];
+    let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+    let mut bytes: Vec<u8> = floats.iter().flat_map(|value| value.to_le_bytes()).collect();
+    bytes.extend(indices.iter().flat_map(|index| index.to_le_bytes()));
+    bytes
+}
+
+/// Scene with the quad of `quad_buffer` drawn in the mode; `buffer` is the JSON of the buffer
+/// and `rest` the other members of the top-level object
+#[cfg(test)]
+fn quad_json(buffer: &str, mode: u32, rest: &str) -> String {
+    format!(
+        r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{buffer}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+                {{ "buffer": 0, "byteOffset": 48, "byteLength": 48 }},
+                {{ "buffer": 0, "byteOffset": 96, "byteLength": 32 }},
+                {{ "buffer": 0, "byteOffset": 128, "byteLength": 64 }},
+                {{ "buffer": 0, "byteOffset": 192, "byteLength": 12 }},
+                {{ "buffer": 0, "byteOffset": 204, "byteLength": 0 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": 4, "type": "VEC2" }},
+                {{ "bufferView": 3, "componentType": 5126, "count": 4, "type": "VEC4" }},
+                {{ "bufferView": 4, "componentType": 5123, "count": 6, "type": "SCALAR" }}
+            ],
+            "meshes": [{{
+                "name": "quad",
+                "primitives": [{{
+                    "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2, "TANGENT": 3 }},
+                    "indices": 4,
+                    "mode": {mode},
+                    "material": 0
+                }}]
+            }}],
+            {rest}
+        }}"#
+    )
+}
+
+#[cfg(test)]
+const QUAD_NODES: &str = r#""nodes": [{ "mesh": 0 }], "scenes": [{ "nodes": [0] }]"#;
+
+#[cfg(test)]
+fn data_uri(bytes: &[u8]) -> String {
+    format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+#[test]
+fn test_embedded_gltf() {
+    let buffer = format!(
+        r#"{{ "byteLength": 204, "uri": "{}" }}"#,
+        data_uri(&quad_buffer())
+    );
+    let rest = r#"
+        "materials": [{
+            "name": "gold",
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [1, 0.5, 0, 1], "metallicFactor": 1, "roughnessFactor": 0.25
+            },
+            "emissiveFactor": [0, 0, 0.5],
+            "alphaMode": "MASK",
+            "alphaCutoff": 0.25,
+            "doubleSided": true
+        }],
+        "nodes": [
+            { "name": "parent", "translation": [1, 2, 3], "rotation": [0, 0.70710677, 0, 0.70710677],
+              "children": [1] },
+            { "name": "child", "mesh": 0, "scale": [2, 2, 2], "translation": [0, 0, 1] },
+            { "name": "elsewhere", "mesh": 0 }
+        ],
+        "scenes": [{ "nodes": [2] }, { "nodes": [0] }],
+        "scene": 1"#;
+    let scene = parse(quad_json(&buffer, 4, rest).as_bytes(), Path::new("")).unwrap();
+
+    // mirrored along z: positions and normals, and the handedness of the tangents
+    let quad = &scene.meshes[0].primitives[0];
+    assert_eq!(scene.meshes[0].name, "quad");
+    assert_eq!(quad.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    assert_eq!(quad.positions[2].to_vec(), [1.0, 1.0, 0.0]);
+    assert_eq!(quad.normals.as_ref().unwrap()[0].to_vec(), [0.0, 0.0, -1.0]);
+    assert_eq!(quad.tangents.as_ref().unwrap()[0], [1.0, 0.0, 0.0, -1.0]);
+    assert_eq!(quad.uvs.as_ref().unwrap()[0], [0.0, 1.0]);
+    assert_eq!(quad.material, Some(0));
+
+    let material = &scene.materials[0];
+    assert_eq!(material.name, "gold");
+    assert_eq!(material.base_color, [1.0, 0.5, 0.0, 1.0]);
+    assert_eq!((material.metallic, material.roughness), (1.0, 0.25));
+    assert_eq!(material.emissive, [0.0, 0.0, 0.5]);
+    assert_eq!(material.shininess(), 510);
+    let gold = srgb([1.0, 0.5, 0.0]);
+    assert_eq!((gold.r, gold.g, gold.b), (255, 188, 0));
+    assert_eq!(material.alpha_mode, AlphaMode::Mask { cutoff: 0.25 });
+    assert!(material.double_sided);
+
+    // only the default scene, with the child moved and turned by its parent
+    assert_eq!(scene.roots, vec![0]);
+    let instances = scene.mesh_instances();
+    assert_eq!(instances.len(), 1);
+    let (translation, rotation, scale) = decompose(&instances[0].1);
+    let close = |a: [f64; 3], b: [f64; 3]| (0..3).all(|i| (a[i] - b[i]).abs() < 1e-6);
+    assert!(close(translation.to_vec(), [2.0, 2.0, -3.0]));
+    assert!(close(scale.to_vec(), [2.0, 2.0, 2.0]));
+    // the quarter turn around y takes x to -z in glTF, to +z once mirrored
+    assert!(close(
+        [rotation[0][0], rotation[1][0], rotation[2][0]],
+        [0.0, 0.0, 1.0]
+    ));
+}
+
+#[test]
+fn test_glb() {
+    let mut texture = vec![];
+    image::png::PNGEncoder::new(&mut texture)
+        .encode(&[255, 0, 0, 255, 0, 0, 255, 128], 2, 1, image::ColorType::RGBA(8))
+        .unwrap();
+    let mut binary = quad_buffer();
+    binary.extend(&texture);
+    while !binary.len().is_multiple_of(4) {
+        binary.push(0);
+    }
+
+    let buffer = format!(r#"{{ "byteLength": {} }}"#, binary.len());
+    let rest = format!(
+        r#""images": [{{ "bufferView": 5, "mimeType": "image/png" }}],
+           "textures": [{{ "source": 0 }}],
+           "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }} }}],
+           {}"#,
+        QUAD_NODES
+    );
+    let mut json = quad_json(&buffer, 5, &rest).replace(
+        r#""byteOffset": 204, "byteLength": 0"#,
+        &format!(r#""byteOffset": 204, "byteLength": {}"#, texture.len()),
+    );
+    while !json.len().is_multiple_of(4) {
+        json.push(' ');
+    }
+
+    let mut glb = b"glTF".to_vec();
+    glb.extend(2u32.to_le_bytes());
+    glb.extend(((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json.as_bytes());
+    glb.extend((binary.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(&binary);
+    let scene = parse(&glb, Path::new("")).unwrap();
+
+    let image = &scene.images[0];
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.rgba, vec![255, 0, 0, 255, 0, 0, 255, 128]);
+    assert_eq!(scene.materials[0].base_color_texture, Some(0));
+    assert_eq!(
+        scene.materials[0],
+        PbrMaterial {
+            base_color_texture: Some(0),
+            ..PbrMaterial::default()
+        }
+    );
+
+    // the strip 0 1 2 0 2 3, every other triangle reversed
+    let strip = &scene.meshes[0].primitives[0].triangles;
+    assert_eq!(strip, &vec![[0, 1, 2], [2, 1, 0], [2, 0, 2], [2, 0, 3]]);
+    assert_eq!(scene.mesh_instances().len(), 1);
+}
+
+#[test]
+fn test_files_and_errors() {
+    let directory = std::env::temp_dir().join("gltf_import_files_and_errors");
+    fs::create_dir_all(&directory).unwrap();
+    let write = |name: &str, buffer: &str| {
+        let json = quad_json(buffer, 4, &format!(r#""materials": [{{}}], {}"#, QUAD_NODES));
+        fs::write(directory.join(name), json).unwrap();
+        load(directory.join(name))
+    };
+
+    // relative files, with escapes in their URIs
+    fs::write(directory.join("quad data.bin"), quad_buffer()).unwrap();
+    let scene = write(
+        "relative.gltf",
+        r#"{ "byteLength": 204, "uri": "quad%20data.bin" }"#,
+    )
+    .unwrap();
+    assert_eq!(scene.meshes[0].primitives[0].positions.len(), 4);
+
+    let missing = write("missing.gltf", r#"{ "byteLength": 204, "uri": "missing.bin" }"#);
+    assert!(matches!(missing, Err(GltfError::Io { path, .. }) if path.ends_with("missing.bin")));
+    let remote = write(
+        "remote.gltf",
+        r#"{ "byteLength": 204, "uri": "https://example.com/quad.bin" }"#,
+    );
+    assert!(matches!(remote, Err(GltfError::UnsupportedUri(_))));
+    let binary = write("binary.gltf", r#"{ "byteLength": 204 }"#);
+    assert!(matches!(binary, Err(GltfError::MissingBinaryChunk)));
+    let short = write(
+        "short.gltf",
+        &format!(r#"{{ "byteLength": 204, "uri": "{}" }}"#, data_uri(&[0; 8])),
+    );
+    assert!(matches!(short, Err(GltfError::BufferTooShort { buffer: 0 })));
+
+    let mut buffer = quad_buffer();
+    buffer[200] = 9;
+    let index = write(
+        "index.gltf",
+        &format!(r#"{{ "byteLength": 204, "uri": "{}" }}"#, data_uri(&buffer)),
+    );
+    assert!(matches!(
+        index,
+        Err(GltfError::InvalidPrimitive { mesh: 0, primitive: 0, .. })
+    ));
+
+    assert!(matches!(
+        parse(b"{ \"asset\": 1 }", &directory),
+        Err(GltfError::Gltf(_))
+    ));
+}
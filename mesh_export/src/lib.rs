@@ -0,0 +1,150 @@
+//! Exporters of triangle meshes to Wavefront OBJ, PLY and binary STL.
+//!
+//! The renderers turn their meshes into a `TriangleMesh`, the writers turn it into a file that
+//! other tools open, or that the importers of `gambetta_rasterizer` read back. Coordinates are
+//! written as they are, like the importers read them, so faces stay clockwise seen from the front;
+//! only the v texture coordinate is flipped, because it grows upwards in these formats and down
+//! the rows of textures here.
+
+use common::{vectors, Vector3f};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+/// Triangles sharing a list of positions, with optional normals and texture coordinates
+/// at their corners
+pub struct TriangleMesh {
+    pub name: String,
+    pub positions: Vec<Vector3f>,
+    pub triangles: Vec<Triangle>,
+}
+
+pub struct Triangle {
+    /// Indexes into `TriangleMesh::positions`
+    pub positions: [usize; 3],
+    pub normals: Option<[Vector3f; 3]>,
+    /// u and v, v down the rows of the texture
+    pub uvs: Option<[[f64; 2]; 3]>,
+}
+
+impl TriangleMesh {
+    /// Mesh of triangles given by their corners, with the positions they share merged
+    pub fn from_corners(name: &str, corners: impl IntoIterator<Item = [Vector3f; 3]>) -> Self {
+        let mut positions = vec![];
+        let mut indexes = std::collections::HashMap::new();
+        let triangles = corners
+            .into_iter()
+            .map(|corners| Triangle {
+                positions: corners.map(|position| {
+                    *indexes.entry(bits(position)).or_insert_with(|| {
+                        positions.push(position);
+                        positions.len() - 1
+                    })
+                }),
+                normals: None,
+                uvs: None,
+            })
+            .collect();
+        Self { name: name.to_string(), positions, triangles }
+    }
+
+    /// Unit normal of the front of the triangle, following the clockwise winding
+    pub fn face_normal(&self, triangle: &Triangle) -> Vector3f {
+        let [a, b, c] = triangle.positions.map(|index| self.positions[index]);
+        let normal = vectors::cross_product(vectors::difference(b, a), vectors::difference(c, a));
+        let length = vectors::length(normal);
+        // degenerate triangles get a zero normal
+        if length == 0.0 {
+            normal
+        } else {
+            vectors::scale(1.0 / length, normal)
+        }
+    }
+}
+
+/// Writes the mesh in the format of the extension of the path: `obj`, `ply`, in binary,
+/// or `stl`
+pub fn save(mesh: &TriangleMesh, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+    let write: fn(&TriangleMesh, &mut dyn Write) -> io::Result<()> = match extension.as_deref() {
+        Some("obj") => |mesh, writer| obj::write(mesh, writer),
+        Some("ply") => |mesh, writer| ply::write(mesh, writer, ply::Format::BinaryLittleEndian),
+        Some("stl") => |mesh, writer| stl::write(mesh, writer),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown mesh format of {}", path.display()),
+            ))
+        }
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(mesh, &mut writer)?;
+    writer.flush()
+}
+
+/// Key of a vector that compares its exact bits
+fn bits(vector: Vector3f) -> [u64; 3] {
+    vector.to_vec().map(f64::to_bits)
+}
+
+/// Two triangles of a unit square in the xy plane facing -z, with normals and texture coordinates,
+/// and a third one without them
+#[cfg(test)]
+fn test_mesh() -> TriangleMesh {
+    let normal = Vector3f::new(0.0, 0.0, -1.0);
+    TriangleMesh {
+        name: "square".to_string(),
+        positions: vec![
+            Vector3f::new(0.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Vector3f::new(1.0, 1.0, 0.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+        ],
+        triangles: vec![
+            Triangle {
+                positions: [0, 1, 2],
+                normals: Some([normal; 3]),
+                uvs: Some([[0.0, 1.0], [0.0, 0.0], [1.0, 0.0]]),
+            },
+            Triangle {
+                positions: [0, 2, 3],
+                normals: Some([normal; 3]),
+                uvs: Some([[0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]),
+            },
+            Triangle { positions: [0, 4, 1], normals: None, uvs: None },
+        ],
+    }
+}
+
+#[test]
+fn test_from_corners() {
+    let (a, b, c, d) = (
+        Vector3f::new(0.0, 0.0, 0.0),
+        Vector3f::new(0.0, 1.0, 0.0),
+        Vector3f::new(1.0, 1.0, 0.0),
+        Vector3f::new(1.0, 0.0, 0.0),
+    );
+    let mesh = TriangleMesh::from_corners("square", [[a, b, c], [a, c, d]]);
+    assert_eq!(mesh.positions.len(), 4);
+    assert_eq!(mesh.triangles[1].positions, [0, 2, 3]);
+    // clockwise seen from -z
+    assert_eq!(mesh.face_normal(&mesh.triangles[0]).to_vec(), [0.0, 0.0, -1.0]);
+}
+
+#[test]
+fn test_save() {
+    let directory = std::env::temp_dir().join("mesh_export_save");
+    std::fs::create_dir_all(&directory).unwrap();
+    for name in ["square.obj", "square.PLY", "square.stl"] {
+        save(&test_mesh(), directory.join(name)).unwrap();
+        assert!(std::fs::metadata(directory.join(name)).unwrap().len() > 0);
+    }
+    let unknown = save(&test_mesh(), directory.join("square.fbx")).unwrap_err();
+    assert_eq!(unknown.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,80 @@
+//! Wavefront OBJ: positions, texture coordinates and normals, each written once and shared
+//! by the faces that use them. Smoothing is off, so faces without normals stay flat.
+
+use crate::{bits, TriangleMesh};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub fn write(mesh: &TriangleMesh, mut writer: impl Write) -> io::Result<()> {
+    // the first texture coordinate or normal with the same bits is used for all of them
+    let mut uvs = vec![];
+    let mut uv_indexes = HashMap::new();
+    let mut normals = vec![];
+    let mut normal_indexes = HashMap::new();
+    let faces: Vec<[Corner; 3]> = mesh
+        .triangles
+        .iter()
+        .map(|triangle| {
+            let mut corners = [(0, None, None); 3];
+            for (corner, (index, uv, normal)) in corners.iter_mut().enumerate() {
+                *index = triangle.positions[corner] + 1;
+                *uv = triangle.uvs.map(|triangle_uvs| {
+                    let [u, v] = triangle_uvs[corner];
+                    *uv_indexes.entry([u.to_bits(), v.to_bits()]).or_insert_with(|| {
+                        uvs.push([u, v]);
+                        uvs.len()
+                    })
+                });
+                *normal = triangle.normals.map(|triangle_normals| {
+                    let direction = triangle_normals[corner];
+                    *normal_indexes.entry(bits(direction)).or_insert_with(|| {
+                        normals.push(direction);
+                        normals.len()
+                    })
+                });
+            }
+            corners
+        })
+        .collect();
+
+    writeln!(writer, "# {} triangles", mesh.triangles.len())?;
+    writeln!(writer, "o {}", mesh.name)?;
+    for position in &mesh.positions {
+        writeln!(writer, "v {} {} {}", position.x, position.y, position.z)?;
+    }
+    for [u, v] in uvs {
+        // v goes up in OBJ files
+        writeln!(writer, "vt {} {}", u, 1.0 - v)?;
+    }
+    for normal in normals {
+        writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+    writeln!(writer, "s off")?;
+    for corners in faces {
+        write!(writer, "f")?;
+        for corner in corners {
+            match corner {
+                (index, None, None) => write!(writer, " {}", index)?,
+                (index, Some(uv), None) => write!(writer, " {}/{}", index, uv)?,
+                (index, None, Some(normal)) => write!(writer, " {}//{}", index, normal)?,
+                (index, Some(uv), Some(normal)) => write!(writer, " {}/{}/{}", index, uv, normal)?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Indexes of a position, texture coordinate and normal, counted from 1
+type Corner = (usize, Option<usize>, Option<usize>);
+
+#[test]
+fn test_obj() {
+    let mut output = vec![];
+    write(&crate::test_mesh(), &mut output).unwrap();
+    let expected = "# 3 triangles\no square\n\
+                    v 0 0 0\nv 0 1 0\nv 1 1 0\nv 1 0 0\nv 0 0 1\n\
+                    vt 0 0\nvt 0 1\nvt 1 1\nvt 1 0\nvn 0 0 -1\ns off\n\
+                    f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\nf 1 5 2\n";
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
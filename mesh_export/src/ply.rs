@@ -0,0 +1,165 @@
+//! PLY in ASCII or binary: one vertex for every distinct combination of a position, a normal and
+//! a texture coordinate, with the faces as lists of their indexes. Normals and texture coordinates
+//! are written only when every triangle has them; coordinates are doubles, so nothing is rounded.
+
+use crate::{bits, TriangleMesh};
+use common::Vector3f;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct Vertex {
+    position: Vector3f,
+    normal: Option<Vector3f>,
+    uv: Option<[f64; 2]>,
+}
+
+pub fn write(mesh: &TriangleMesh, mut writer: impl Write, format: Format) -> io::Result<()> {
+    let with_normals = mesh.triangles.iter().all(|triangle| triangle.normals.is_some());
+    let with_uvs = mesh.triangles.iter().all(|triangle| triangle.uvs.is_some());
+
+    // corners of the triangles that share the position but not the normal or the texture
+    // coordinate get vertices of their own
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indexes = HashMap::new();
+    let faces: Vec<[usize; 3]> = mesh
+        .triangles
+        .iter()
+        .map(|triangle| {
+            [0, 1, 2].map(|corner| {
+                let position = triangle.positions[corner];
+                let normal = triangle.normals.filter(|_| with_normals).map(|normals| normals[corner]);
+                let uv = triangle.uvs.filter(|_| with_uvs).map(|uvs| uvs[corner]);
+                let key = (
+                    position,
+                    normal.map(bits),
+                    uv.map(|[u, v]| [u.to_bits(), v.to_bits()]),
+                );
+                *indexes.entry(key).or_insert_with(|| {
+                    vertices.push(Vertex { position: mesh.positions[position], normal, uv });
+                    vertices.len() - 1
+                })
+            })
+        })
+        .collect();
+
+    let format_name = match format {
+        Format::Ascii => "ascii",
+        Format::BinaryLittleEndian => "binary_little_endian",
+        Format::BinaryBigEndian => "binary_big_endian",
+    };
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", format_name)?;
+    writeln!(writer, "comment {}", mesh.name)?;
+    writeln!(writer, "element vertex {}", vertices.len())?;
+    for property in ["x", "y", "z"] {
+        writeln!(writer, "property double {}", property)?;
+    }
+    if with_normals {
+        for property in ["nx", "ny", "nz"] {
+            writeln!(writer, "property double {}", property)?;
+        }
+    }
+    if with_uvs {
+        for property in ["u", "v"] {
+            writeln!(writer, "property double {}", property)?;
+        }
+    }
+    writeln!(writer, "element face {}", faces.len())?;
+    writeln!(writer, "property list uchar int vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for vertex in &vertices {
+        let mut values = vertex.position.to_vec().to_vec();
+        if let Some(normal) = vertex.normal {
+            values.extend(normal.to_vec());
+        }
+        if let Some([u, v]) = vertex.uv {
+            // v goes up in PLY files
+            values.extend([u, 1.0 - v]);
+        }
+        match format {
+            Format::Ascii => {
+                let line: Vec<String> = values.iter().map(f64::to_string).collect();
+                writeln!(writer, "{}", line.join(" "))?;
+            }
+            Format::BinaryLittleEndian => {
+                values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))?
+            }
+            Format::BinaryBigEndian => {
+                values.iter().try_for_each(|value| writer.write_all(&value.to_be_bytes()))?
+            }
+        }
+    }
+    for face in faces {
+        let indexes = face.map(|index| index as i32);
+        match format {
+            Format::Ascii => writeln!(writer, "3 {} {} {}", indexes[0], indexes[1], indexes[2])?,
+            Format::BinaryLittleEndian => {
+                writer.write_all(&[3])?;
+                indexes.iter().try_for_each(|index| writer.write_all(&index.to_le_bytes()))?;
+            }
+            Format::BinaryBigEndian => {
+                writer.write_all(&[3])?;
+                indexes.iter().try_for_each(|index| writer.write_all(&index.to_be_bytes()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_ascii() {
+    let mut output = vec![];
+    write(&crate::test_mesh(), &mut output, Format::Ascii).unwrap();
+    // the triangle without normals leaves them out of the whole file
+    let expected = "ply\nformat ascii 1.0\ncomment square\nelement vertex 5\n\
+                    property double x\nproperty double y\nproperty double z\n\
+                    element face 3\nproperty list uchar int vertex_indices\nend_header\n\
+                    0 0 0\n0 1 0\n1 1 0\n1 0 0\n0 0 1\n\
+                    3 0 1 2\n3 0 2 3\n3 0 4 1\n";
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+    // texture coordinates that differ at a position split it
+    let mut mesh = crate::test_mesh();
+    mesh.triangles.pop();
+    mesh.triangles[1].uvs.as_mut().unwrap()[0] = [0.5, 0.5];
+    let mut output = vec![];
+    write(&mesh, &mut output, Format::Ascii).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("element vertex 5\n"));
+    assert!(output.contains("property double nz\nproperty double u\nproperty double v\n"));
+    assert!(output.contains("\n0 0 0 0 0 -1 0 0\n"));
+    assert!(output.contains("\n0 0 0 0 0 -1 0.5 0.5\n"));
+    assert!(output.ends_with("3 0 1 2\n3 3 2 4\n"));
+}
+
+#[test]
+fn test_binary() {
+    let mut little = vec![];
+    write(&crate::test_mesh(), &mut little, Format::BinaryLittleEndian).unwrap();
+    let mut big = vec![];
+    write(&crate::test_mesh(), &mut big, Format::BinaryBigEndian).unwrap();
+
+    let header_end = |bytes: &[u8]| {
+        let end = b"end_header\n";
+        bytes.windows(end.len()).position(|window| window == end).unwrap() + end.len()
+    };
+    let (little_body, big_body) = (&little[header_end(&little)..], &big[header_end(&big)..]);
+    // 5 vertices of 3 doubles, 3 faces of a count and 3 indexes
+    assert_eq!(little_body.len(), 5 * 3 * 8 + 3 * (1 + 3 * 4));
+    assert_eq!(little_body.len(), big_body.len());
+    assert_eq!(f64::from_le_bytes(little_body[32..40].try_into().unwrap()), 1.0);
+    assert_eq!(f64::from_be_bytes(big_body[32..40].try_into().unwrap()), 1.0);
+    let last_index = little_body.len() - 4;
+    assert_eq!(
+        i32::from_le_bytes(little_body[last_index..].try_into().unwrap()),
+        1
+    );
+}
@@ -0,0 +1,50 @@
+//! Binary STL: every triangle with its face normal and three corners in single precision.
+//! Shared positions, normals of the corners and texture coordinates are lost.
+
+use crate::TriangleMesh;
+use std::io::{self, Write};
+
+pub fn write(mesh: &TriangleMesh, mut writer: impl Write) -> io::Result<()> {
+    // a header starting with "solid" would be taken for an ASCII file
+    let mut header = [0u8; 80];
+    let title = format!("binary STL: {}", mesh.name);
+    let length = title.len().min(header.len());
+    header[..length].copy_from_slice(&title.as_bytes()[..length]);
+    writer.write_all(&header)?;
+    writer.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+
+    for triangle in &mesh.triangles {
+        let corners = triangle.positions.map(|index| mesh.positions[index]);
+        for vector in std::iter::once(mesh.face_normal(triangle)).chain(corners) {
+            for coordinate in vector.to_vec() {
+                writer.write_all(&(coordinate as f32).to_le_bytes())?;
+            }
+        }
+        // attribute byte count, unused
+        writer.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_stl() {
+    let mut output = vec![];
+    write(&crate::test_mesh(), &mut output).unwrap();
+
+    assert_eq!(output.len(), 80 + 4 + 3 * 50);
+    assert!(output.starts_with(b"binary STL: square\0"));
+    assert_eq!(u32::from_le_bytes(output[80..84].try_into().unwrap()), 3);
+    let float = |offset: usize| f32::from_le_bytes(output[offset..offset + 4].try_into().unwrap());
+    // normal and corners of the first triangle, then of the third one, facing -x
+    let first = 84;
+    let values: Vec<f32> = (0..12).map(|i| float(first + 4 * i)).collect();
+    assert_eq!(
+        values,
+        vec![0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0]
+    );
+    let third = 84 + 2 * 50;
+    assert_eq!(
+        [float(third), float(third + 4), float(third + 8)],
+        [-1.0, 0.0, 0.0]
+    );
+}
@@ -3,83 +3,79 @@
 mod ply2;
 
 use common::{Color, Light, Vector3f};
+use gambetta_rasterizer::animation::{Interpolation, Playback, Track};
+use gambetta_rasterizer::material::AlphaMode;
 use gambetta_rasterizer::model;
 use gambetta_rasterizer::{
-    texture, BufferCanvas, Instance, Matrix44f, ProjectiveCamera, RenderingMode, RenderingSettings,
-    ShadingModel, Vector4f,
+    texture, Animation, BufferCanvas, Clipping, Edges, Instance, Material, Matrix44f, ProjectiveCamera,
+    Quaternion, RenderingMode, RenderingSettings, ShadingModel, ShadowSettings, Texture, Transparency,
+    Vector4f, WireframeSettings,
 };
+#[cfg(feature = "window")]
+use gambetta_rasterizer::{Camera, IsometricView, ObliqueCamera, OrthographicCamera};
 use image::png::PNGEncoder;
 use image::ColorType;
-use sdl3::event::Event;
-use sdl3::keyboard::Keycode;
-use sdl3::pixels::PixelFormat;
+#[cfg(feature = "window")]
+use sdl3::{event::Event, keyboard::Keycode, pixels::PixelFormat};
+use std::env;
+use std::fs;
 use std::fs::File;
+use std::path::Path;
+#[cfg(feature = "window")]
 use std::time::Instant;
 
 fn main() {
     env_logger::init();
+    let args: Vec<String> = env::args().collect();
+    let headless_options = HeadlessOptions::from_args(&args);
 
-    let mut rendering_settings = RenderingSettings {
+    let rendering_settings = RenderingSettings {
         rendering_mode: RenderingMode::Filled,
         shading_model: ShadingModel::Phong,
         show_normals: false,
         backface_culling: true,
+        transparency: Transparency::Sorted,
+        clipping: Clipping::CameraSpace,
+        shadows: Some(ShadowSettings::default()),
+        wireframe: WireframeSettings::default(),
     };
-    let mut buffer_canvas = BufferCanvas::new(900);
-
-    let sdl_context = sdl3::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem
-        .window("Durer", buffer_canvas.size as u32, buffer_canvas.size as u32)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas();
-    let texture_creator = canvas.texture_creator();
-    let mut texture = texture_creator
-        .create_texture_static(
-            PixelFormat::RGB24,
-            buffer_canvas.size as u32,
-            buffer_canvas.size as u32,
-        )
-        .unwrap();
-
-    texture.update(None, &buffer_canvas.buffer, buffer_canvas.size * 3).unwrap();
-    canvas.clear();
-    canvas.copy(&texture, None, None).unwrap();
-    canvas.present();
-
-    let viewport_size_delta = 0.1;
-    let mut viewport_size = 1.0;
-    let projection_plane_z_delta = 0.1;
-    let mut projection_plane_z = 1.0;
-    let mut x_position = 0.0;
-    let mut y_position = 0.0;
-    let mut z_position = 0.0;
-    let mut angle = 0.0;
 
     let red = Color { r: 255, g: 0, b: 0 };
     let green = Color { r: 0, g: 255, b: 0 };
     let blue = Color { r: 0, g: 0, b: 255 };
     let white = Color { r: 255, g: 255, b: 255 };
 
-    let wooden_crate = texture::load_from_file("resources/textures/wooden-crate.jpg");
-    let bricks = texture::load_from_file("resources/textures/bricks.jpg");
+    let wooden_crate = load_texture("resources/textures/wooden-crate.jpg");
+    let bricks = load_texture("resources/textures/bricks.jpg");
 
     //    let cube = two_unit_cube();
     // let sphere = model::sphere(50);
     // let random_cubes = model::random_cubes_scene(50, 25.0);
     // println!("{random_cubes}");
     // let cube = model::cube(0.9);
-    let wooden_cube = model::textured_cube(0.9, &wooden_crate);
+    let mut wooden_cube = model::textured_cube(0.9, &wooden_crate);
+    // wood is matte
+    wooden_cube.materials[0].shininess = 0;
     let brick_cube = model::textured_cube(1.0, &bricks);
+    let glass_pane = model::square(
+        1.2,
+        Material::diffuse(Color { r: 170, g: 210, b: 230 })
+            .with_specular(white, 200)
+            .with_opacity(0.3)
+            .with_alpha_mode(AlphaMode::Blend),
+    );
+    let leaves = foliage_texture();
+    let foliage = model::square(
+        1.0,
+        Material::textured(&leaves).with_alpha_mode(AlphaMode::Mask { cutoff: 0.5 }),
+    );
     //    let triangle = triangle(5.0);
     // let torus = ply2::load_model("resources/torus.ply2");
     //    let twirl = ply2::load_model("resources/twirl.ply2");
     //    let octo_flower = ply2::load_model("resources/octa-flower.ply2");
     //    let statue = ply2::load_model("resources/statue.ply2");
+    // let teapot = gambetta_rasterizer::obj::load("../raytraced_spheres/resources/teapot.obj").unwrap();
+    // let teapot = teapot.model();
 
     let mut current_instance_index: Option<usize> = None;
 
@@ -107,6 +103,20 @@ fn main() {
             Vector3f { x: -0.3, y: -0.4, z: 3.5 },
             1.0,
             Vector3f { x: 25.0, y: 20.0, z: 10.0 },
+        )
+        .with_wireframe_color(red),
+        Instance::new(
+            &glass_pane,
+            Vector3f { x: 0.2, y: 0.0, z: 2.5 },
+            1.0,
+            Vector3f { x: 0.0, y: 20.0, z: 0.0 },
+        )
+        .with_wireframe_color(blue),
+        Instance::new(
+            &foliage,
+            Vector3f { x: -1.3, y: 0.6, z: 3.0 },
+            1.0,
+            Vector3f { x: 0.0, y: -15.0, z: 0.0 },
         ),
         //    Instance::new(
         //        &torus,
@@ -172,12 +182,76 @@ fn main() {
     ];
 
     //    rendering::render_scene(&scene, &camera, &mut buffer_canvas, &clipping_planes);
-    //    texture.update(None, &buffer_canvas.buffer, buffer_canvas.size * 3).unwrap();
+    //    texture.update(None, &buffer_canvas.buffer, buffer_canvas.width * 3).unwrap();
     //    canvas.clear();
     //    canvas.copy(&texture, None, None).unwrap();
     //    canvas.present();
     //
 
+    // instance index and its animation, played in time rather than in frames
+    let animations = [
+        (
+            0,
+            Animation::new(Playback::Loop).with_orientation(spinning(
+                instances[0].orientation(),
+                Vector3f { x: 4.5, y: 2.0, z: 6.5 },
+                6.0,
+            )),
+        ),
+        (
+            1,
+            Animation::new(Playback::Loop)
+                .with_orientation(spinning(
+                    instances[1].orientation(),
+                    Vector3f { x: 0.0, y: 8.0, z: 3.0 },
+                    4.0,
+                ))
+                .with_position(
+                    Track::new()
+                        .with_keyframe(
+                            0.0,
+                            Vector3f { x: -0.3, y: -0.4, z: 3.5 },
+                            Interpolation::ease_in_out(),
+                        )
+                        .with_keyframe(
+                            2.0,
+                            Vector3f { x: -0.3, y: -0.4, z: 4.5 },
+                            Interpolation::ease_in_out(),
+                        )
+                        .with_keyframe(4.0, Vector3f { x: -0.3, y: -0.4, z: 3.5 }, Interpolation::Linear),
+                ),
+        ),
+    ];
+
+    if let Some(options) = headless_options {
+        render_headless(&options, &mut instances, &lights, &animations, rendering_settings);
+        return;
+    }
+
+    #[cfg(feature = "window")]
+    run_window(&mut instances, &lights, &animations, rendering_settings);
+    #[cfg(not(feature = "window"))]
+    usage("built without the window feature, only --headless rendering is available");
+}
+
+/// Renders the scene in a window, moving the camera and switching the settings with the keys
+#[cfg(feature = "window")]
+fn run_window(
+    instances: &mut Vec<Instance>,
+    lights: &Vec<Light>,
+    animations: &[(usize, Animation)],
+    mut rendering_settings: RenderingSettings,
+) {
+    let viewport_size_delta = 0.1;
+    let mut viewport_size = 1.0;
+    let projection_plane_z_delta = 0.1;
+    let mut projection_plane_z = 1.0;
+    let mut x_position = 0.0;
+    let mut y_position = 0.0;
+    let mut z_position = 0.0;
+    let mut angle = 0.0;
+    let mut camera_mode = CameraMode::Perspective;
+
     let step_increase = 0.005;
     let angle_increase = 0.1;
     let mut delta_x;
@@ -198,18 +272,33 @@ fn main() {
         delta_angle = 1.0;
     };
 
-    instances[0].rotation_delta.x = angle_increase * 4.5;
-    instances[0].rotation_delta.y = angle_increase * 2.0;
-    instances[0].rotation_delta.z = angle_increase * 6.5;
-    //    instances[0].position_delta.x = -0.005;
-    //    instances[0].position_delta.z = 0.03;
-    //    instances[0].scale_delta = 0.008;
+    let mut buffer_canvas = BufferCanvas::new(1200, 900);
+    let sdl_context = sdl3::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
 
-    instances[1].rotation_delta.y = angle_increase * 8.0;
-    instances[1].rotation_delta.z = angle_increase * 3.0;
-    instances[1].position_delta.z = 0.01;
+    let window = video_subsystem
+        .window("Durer", buffer_canvas.width as u32, buffer_canvas.height as u32)
+        .position_centered()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_static(
+            PixelFormat::RGB24,
+            buffer_canvas.width as u32,
+            buffer_canvas.height as u32,
+        )
+        .unwrap();
+
+    texture.update(None, &buffer_canvas.buffer, buffer_canvas.width * 3).unwrap();
+    canvas.clear();
+    canvas.copy(&texture, None, None).unwrap();
+    canvas.present();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let start_time = Instant::now();
     let mut last_frame_time = Instant::now();
     let mut last_fps_time = Instant::now();
     let mut frame_count = 0;
@@ -244,33 +333,62 @@ fn main() {
             z_position += delta_z;
             angle += delta_angle;
 
-            instances[0].apply_deltas();
-            instances[1].apply_deltas();
+            let time = current_time.duration_since(start_time).as_secs_f64();
+            for (index, animation) in animations {
+                animation.apply(&mut instances[*index], time);
+            }
         };
 
-        let camera = ProjectiveCamera {
+        let position = Vector4f {
+            x: x_position,
+            y: y_position,
+            z: z_position,
+            w: 0.0,
+        };
+        let perspective = ProjectiveCamera {
             viewport_size,
             projection_plane_z,
-            position: Vector4f {
+            near_distance: 0.1,
+            far_distance: 100.0,
+            aspect_ratio: buffer_canvas.aspect_ratio(),
+            position,
+            rotation: Matrix44f::rotation_y(angle),
+        };
+        // the parallel cameras see at the distance of the scene what the perspective one does
+        let orthographic = OrthographicCamera {
+            viewport_size: viewport_size * SCENE_DISTANCE / projection_plane_z,
+            near_distance: 0.0,
+            far_distance: 100.0,
+            aspect_ratio: buffer_canvas.aspect_ratio(),
+            position,
+            rotation: Matrix44f::rotation_y(angle),
+        };
+        let isometric = OrthographicCamera::isometric(
+            IsometricView::FrontLeft,
+            Vector3f {
                 x: x_position,
                 y: y_position,
-                z: z_position,
-                w: 0.0,
+                z: z_position + SCENE_DISTANCE,
             },
-            rotation: Matrix44f::rotation_y(angle),
+            10.0,
+            orthographic.viewport_size,
+            orthographic.aspect_ratio,
+        );
+        let cabinet = ObliqueCamera::cabinet(orthographic, SCENE_DISTANCE);
+        let cavalier = ObliqueCamera::cavalier(orthographic, SCENE_DISTANCE);
+        let camera: &dyn Camera = match camera_mode {
+            CameraMode::Perspective => &perspective,
+            CameraMode::Orthographic => &orthographic,
+            CameraMode::Isometric => &isometric,
+            CameraMode::Cabinet => &cabinet,
+            CameraMode::Cavalier => &cavalier,
         };
 
         buffer_canvas.clear();
 
-        gambetta_rasterizer::render_scene(
-            &instances,
-            &lights,
-            &camera,
-            &rendering_settings,
-            &mut buffer_canvas,
-        );
+        gambetta_rasterizer::render_scene(instances, lights, camera, &rendering_settings, &mut buffer_canvas);
 
-        texture.update(None, &buffer_canvas.buffer, buffer_canvas.size * 3).unwrap();
+        texture.update(None, &buffer_canvas.buffer, buffer_canvas.width * 3).unwrap();
         canvas.clear();
         canvas.copy(&texture, None, None).unwrap();
         canvas.present();
@@ -355,11 +473,21 @@ fn main() {
                         projection_plane_z -= projection_plane_z_delta;
                     }
                     Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
-                        rendering_settings.rendering_mode = RenderingMode::Wireframe
+                        rendering_settings.rendering_mode = match rendering_settings.rendering_mode {
+                            RenderingMode::Wireframe => RenderingMode::HiddenLine,
+                            RenderingMode::HiddenLine => RenderingMode::Overlay,
+                            _ => RenderingMode::Wireframe,
+                        }
                     }
                     Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
                         rendering_settings.rendering_mode = RenderingMode::Filled
                     }
+                    Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                        rendering_settings.wireframe.edges = match rendering_settings.wireframe.edges {
+                            Edges::All => Edges::Feature { min_angle: FEATURE_ANGLE },
+                            Edges::Feature { .. } => Edges::All,
+                        }
+                    }
                     Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
                         rendering_settings.shading_model = ShadingModel::Flat
                     }
@@ -369,15 +497,42 @@ fn main() {
                     Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
                         rendering_settings.shading_model = ShadingModel::Phong
                     }
+                    Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                        rendering_settings.transparency = match rendering_settings.transparency {
+                            Transparency::Sorted => Transparency::WeightedBlended,
+                            Transparency::WeightedBlended => Transparency::Sorted,
+                        }
+                    }
+                    Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                        rendering_settings.shadows = match rendering_settings.shadows {
+                            Some(_) => None,
+                            None => Some(ShadowSettings::default()),
+                        }
+                    }
                     Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
                         rendering_settings.backface_culling = !rendering_settings.backface_culling
                     }
                     Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
                         rendering_settings.show_normals = !rendering_settings.show_normals
                     }
+                    Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                        rendering_settings.clipping = match rendering_settings.clipping {
+                            Clipping::CameraSpace => Clipping::ClipSpace,
+                            Clipping::ClipSpace => Clipping::CameraSpace,
+                        }
+                    }
+                    Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                        camera_mode = camera_mode.next();
+                        println!("Camera: {:?}", camera_mode);
+                    }
                     Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
-                        write_image(&mut buffer_canvas.buffer, buffer_canvas.size)
-                            .expect("Error writing image to file");
+                        write_image(
+                            "screenshot.png",
+                            &buffer_canvas.buffer,
+                            buffer_canvas.width,
+                            buffer_canvas.height,
+                        )
+                        .expect("Error writing image to file");
                     }
                     Event::KeyDown { keycode, scancode, keymod, .. } => {
                         println!(
@@ -409,7 +564,7 @@ fn main() {
     //        green_sphere_position_z += 0.01;
     //        blue_sphere_position_x += 0.01;
 
-    //    write_image(&mut buffer_canvas.buffer, buffer_canvas.size).expect("Error writing image to file");
+    //    write_image(&mut buffer_canvas.buffer, buffer_canvas.width, buffer_canvas.height).expect("Error writing image to file");
     //    show_buffer_in_window(&mut buffer_canvas.buffer, buffer_canvas.size);
 
     //    rotating_cube_window(&mut buffer, size);
@@ -424,11 +579,269 @@ fn main() {
     //    show_buffer_in_window(&mut buffer, size);
 }
 
-fn write_image(buffer: &[u8], size: usize) -> Result<(), std::io::Error> {
-    let output = File::create("screenshot.png")?;
+/// Rendering to PNG files without a window, for build servers and comparing renders:
+/// `--headless <dir>` with optional `--width <pixels>`, `--height <pixels>`,
+/// `--shading flat|gouraud|phong`, `--wireframe`, `--hidden-line`, `--overlay`,
+/// `--feature-edges <degrees>`, `--no-backface-culling`, `--frames <count>` and `--fps <frames per second>`
+struct HeadlessOptions {
+    output_dir: String,
+    width: usize,
+    height: usize,
+    shading_model: Option<ShadingModel>,
+    rendering_mode: Option<RenderingMode>,
+    /// Only the edges between faces meeting at more than this angle are drawn when set
+    feature_angle: Option<f64>,
+    backface_culling: bool,
+    frames: usize,
+    /// Frames are rendered at the animation time of their number divided by it
+    frame_rate: f64,
+}
+
+/// Flags followed by a value
+const VALUE_FLAGS: [&str; 7] = [
+    "--headless",
+    "--width",
+    "--height",
+    "--shading",
+    "--feature-edges",
+    "--frames",
+    "--fps",
+];
+
+/// Flags without a value
+const SWITCHES: [&str; 4] = ["--wireframe", "--hidden-line", "--overlay", "--no-backface-culling"];
+
+impl HeadlessOptions {
+    /// Any argument that is not a known flag or the value of one stops the program with the usage
+    fn from_args(args: &[String]) -> Option<Self> {
+        let mut arguments = args.iter().skip(1);
+        while let Some(arg) = arguments.next() {
+            if VALUE_FLAGS.contains(&arg.as_str()) {
+                // a flag right after another one is a forgotten value rather than the value
+                match arguments.next() {
+                    Some(value) if !value.starts_with("--") => {}
+                    _ => usage(&format!("{} requires a value", arg)),
+                }
+            } else if !SWITCHES.contains(&arg.as_str()) {
+                usage(&format!("unknown argument '{}'", arg));
+            }
+        }
+
+        let value = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| &args[index + 1]);
+        let number = |flag: &str, default: usize| {
+            value(flag).map_or(default, |value| match value.parse() {
+                Ok(number) if number > 0 => number,
+                _ => usage(&format!("{} must be a positive number", flag)),
+            })
+        };
+
+        let output_dir = value("--headless")?.clone();
+        let shading_model = value("--shading").map(|value| match value.as_str() {
+            "flat" => ShadingModel::Flat,
+            "gouraud" => ShadingModel::Gouraud,
+            "phong" => ShadingModel::Phong,
+            _ => usage(&format!("unknown shading model '{}'", value)),
+        });
+        let rendering_mode = [
+            ("--wireframe", RenderingMode::Wireframe),
+            ("--hidden-line", RenderingMode::HiddenLine),
+            ("--overlay", RenderingMode::Overlay),
+        ]
+        .into_iter()
+        .find(|(flag, _)| args.iter().any(|arg| arg == flag))
+        .map(|(_, rendering_mode)| rendering_mode);
+        let feature_angle = value("--feature-edges").map(|value| {
+            value.parse().unwrap_or_else(|_| usage("--feature-edges must be a number of degrees"))
+        });
+        let frame_rate = value("--fps").map_or(30.0, |value| match value.parse::<f64>() {
+            Ok(frame_rate) if frame_rate > 0.0 && frame_rate.is_finite() => frame_rate,
+            _ => usage("--fps must be a positive number"),
+        });
+
+        Some(HeadlessOptions {
+            output_dir,
+            width: number("--width", 1200),
+            height: number("--height", 900),
+            shading_model,
+            rendering_mode,
+            feature_angle,
+            backface_culling: !args.iter().any(|arg| arg == "--no-backface-culling"),
+            frames: number("--frames", 1),
+            frame_rate,
+        })
+    }
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!(
+        "Usage: --headless <dir> [--width <pixels>] [--height <pixels>] [--shading flat|gouraud|phong] \
+         [--wireframe|--hidden-line|--overlay] [--feature-edges <degrees>] [--no-backface-culling] \
+         [--frames <count>] [--fps <frames per second>]"
+    );
+    std::process::exit(1);
+}
+
+/// Renders the frames of the animations with the perspective camera the window starts with,
+/// to `frame_000001.png` and so on in the output directory
+fn render_headless(
+    options: &HeadlessOptions,
+    instances: &mut Vec<Instance>,
+    lights: &Vec<Light>,
+    animations: &[(usize, Animation)],
+    mut rendering_settings: RenderingSettings,
+) {
+    if let Some(shading_model) = options.shading_model {
+        rendering_settings.shading_model = shading_model;
+    }
+    if let Some(rendering_mode) = options.rendering_mode {
+        rendering_settings.rendering_mode = rendering_mode;
+    }
+    if let Some(min_angle) = options.feature_angle {
+        rendering_settings.wireframe.edges = Edges::Feature { min_angle };
+    }
+    rendering_settings.backface_culling = options.backface_culling;
+
+    fs::create_dir_all(&options.output_dir).expect("Error creating the output directory");
+    let mut buffer_canvas = BufferCanvas::new(options.width, options.height);
+    let camera = ProjectiveCamera {
+        viewport_size: 1.0,
+        projection_plane_z: 1.0,
+        near_distance: 0.1,
+        far_distance: 100.0,
+        aspect_ratio: buffer_canvas.aspect_ratio(),
+        position: Vector4f::zero_vector(),
+        rotation: Matrix44f::rotation_y(0.0),
+    };
+
+    for frame in 0..options.frames {
+        let time = frame as f64 / options.frame_rate;
+        for (index, animation) in animations {
+            animation.apply(&mut instances[*index], time);
+        }
+
+        buffer_canvas.clear();
+        gambetta_rasterizer::render_scene(
+            instances,
+            lights,
+            &camera,
+            &rendering_settings,
+            &mut buffer_canvas,
+        );
+
+        let path = Path::new(&options.output_dir).join(format!("frame_{:06}.png", frame + 1));
+        write_image(
+            &path.to_string_lossy(),
+            &buffer_canvas.buffer,
+            buffer_canvas.width,
+            buffer_canvas.height,
+        )
+        .expect("Error writing image to file");
+        println!("Saved {}", path.display());
+    }
+}
+
+/// Degrees between the faces of the feature edges switched on with V
+#[cfg(feature = "window")]
+const FEATURE_ANGLE: f64 = 30.0;
+
+/// Distance from the camera to the middle of the scene
+#[cfg(feature = "window")]
+const SCENE_DISTANCE: f64 = 3.5;
+
+/// Cameras switched with F11
+#[cfg(feature = "window")]
+#[derive(Copy, Clone, Debug)]
+enum CameraMode {
+    Perspective,
+    Orthographic,
+    Isometric,
+    Cabinet,
+    Cavalier,
+}
+
+#[cfg(feature = "window")]
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::Perspective => CameraMode::Orthographic,
+            CameraMode::Orthographic => CameraMode::Isometric,
+            CameraMode::Isometric => CameraMode::Cabinet,
+            CameraMode::Cabinet => CameraMode::Cavalier,
+            CameraMode::Cavalier => CameraMode::Perspective,
+        }
+    }
+}
+
+/// Full turns around the axis of the model, one every period in seconds
+fn spinning(initial: Quaternion, axis: Vector3f, period: f64) -> Track<Quaternion> {
+    // slerp takes the shorter way, so a turn needs keyframes less than 180 degrees apart
+    (0..=3).fold(Track::new(), |track, third| {
+        let turn = Quaternion::from_axis_angle(axis, 120.0 * third as f64);
+        track.with_keyframe(
+            period * third as f64 / 3.0,
+            turn.then(initial),
+            Interpolation::Linear,
+        )
+    })
+}
+
+fn write_image(path: &str, buffer: &[u8], width: usize, height: usize) -> Result<(), std::io::Error> {
+    let output = File::create(path)?;
 
     let encoder = PNGEncoder::new(output);
-    encoder.encode(&buffer, size as u32, size as u32, ColorType::RGB(8))?;
+    encoder.encode(&buffer, width as u32, height as u32, ColorType::RGB(8))?;
 
     Ok(())
 }
+
+/// Texture from the file, or a checkerboard when it can't be read, so that the scene
+/// still renders without the resources directory
+fn load_texture(filename: &str) -> Texture {
+    if Path::new(filename).exists() {
+        return texture::load_from_file(filename);
+    }
+
+    eprintln!("Texture {} not found, using a checkerboard", filename);
+    let size = 64;
+    let mut texels = Vec::with_capacity(size * size * 3);
+    for y in 0..size {
+        for x in 0..size {
+            let value = if (x / 8 + y / 8) % 2 == 0 { 200 } else { 90 };
+            texels.extend([value, value, value]);
+        }
+    }
+    Texture::from_rgb(size, size, texels)
+}
+
+/// Leaves on a transparent background, for alpha-tested cutouts
+fn foliage_texture() -> Texture {
+    let size = 64;
+    // centers, radii along and across and angles of the leaves
+    let leaves: [(f64, f64, f64, f64, f64); 5] = [
+        (20.0, 22.0, 16.0, 7.0, 0.6),
+        (42.0, 20.0, 15.0, 6.0, -0.5),
+        (32.0, 42.0, 18.0, 8.0, 1.5),
+        (14.0, 46.0, 12.0, 5.0, -1.0),
+        (50.0, 44.0, 12.0, 5.0, 1.0),
+    ];
+
+    let mut texels = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+            let leaf = leaves.iter().position(|&(cx, cy, along, across, angle)| {
+                let (dx, dy) = (x - cx, y - cy);
+                let u = dx * angle.cos() + dy * angle.sin();
+                let v = -dx * angle.sin() + dy * angle.cos();
+                (u / along).powi(2) + (v / across).powi(2) <= 1.0
+            });
+            match leaf {
+                Some(index) => texels.extend([40 + 15 * index as u8, 110 + 20 * index as u8, 30, 255]),
+                None => texels.extend([0, 0, 0, 0]),
+            }
+        }
+    }
+
+    Texture::from_rgba(size, size, texels)
+}
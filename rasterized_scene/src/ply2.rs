@@ -1,7 +1,7 @@
 use crate::model::Model;
 use crate::Vector3f;
 use common::Color;
-use gambetta_rasterizer::Triangle;
+use gambetta_rasterizer::{Material, Triangle};
 use std::fs::File;
 use std::io::prelude::*;
 use std::str::FromStr;
@@ -32,7 +32,7 @@ pub fn load_model(filename: &str) -> Model {
     let mut current_face = 0;
     let mut vertices = Vec::new();
     let mut faces = Vec::new();
-    let mut colors = Vec::new();
+    let mut material_indexes = Vec::new();
     // let rng = rand::thread_rng();
 
     for line in contents.split("\n") {
@@ -79,8 +79,7 @@ pub fn load_model(filename: &str) -> Model {
                     face.push(faces_list[i as usize]);
                 }
                 faces.push(face);
-                //                colors.push(Color { r: rng.gen(), g: rng.gen(), b: rng.gen() });
-                colors.push(Color { r: 119, g: 136, b: 153 });
+                material_indexes.push(0);
 
                 current_face += 1;
                 if current_face == num_faces {
@@ -111,8 +110,9 @@ pub fn load_model(filename: &str) -> Model {
         name: "filename",
         vertices,
         triangles,
-        colors,
-        textures: None,
+        materials: vec![Material::diffuse(Color { r: 119, g: 136, b: 153 })],
+        material_indexes,
         uvs: None,
+        edges: Default::default(),
     }
 }
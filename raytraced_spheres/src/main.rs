@@ -1,6 +1,8 @@
 use common::vectors;
 use common::{Color, Light, Vector3f};
-use gambetta_raytracer::{CSGOperation, Mesh, Shape, Sphere, Transform, Triangle};
+use gambetta_raytracer::denoise::{self, DenoiseSettings};
+use gambetta_raytracer::stats::RenderStats;
+use gambetta_raytracer::{CSGOperation, Medium, Mesh, RenderSettings, Shape, Sphere, Transform, Triangle};
 use image::RgbImage;
 use sdl3::{event::Event, keyboard::Keycode, pixels::PixelFormat};
 use std::env;
@@ -13,8 +15,55 @@ use std::time::Duration;
 use std::time::Instant;
 use sysinfo::Components;
 
+/// Куда выводить статистику рендеринга: `--stats` печатает её после каждого кадра,
+/// `--stats-json <file>` сохраняет в JSON (в режиме анимации к имени файла добавляется номер кадра)
+/// Лучи и тесты пересечения считаются только при сборке с `--features stats`, без неё эти флаги не принимаются
+struct StatsOutput {
+    print: bool,
+    json_path: Option<String>,
+}
+
+impl StatsOutput {
+    fn from_args(args: &[String]) -> Self {
+        let json_path = args
+            .iter()
+            .position(|a| a == "--stats-json")
+            .map(|index| args.get(index + 1).expect("--stats-json requires a file name").clone());
+        let output = StatsOutput {
+            print: args.iter().any(|a| a == "--stats"),
+            json_path,
+        };
+        // без счётчиков статистика состояла бы из нулей, похожих на настоящие данные
+        if (output.print || output.json_path.is_some()) && !cfg!(feature = "stats") {
+            eprintln!("Статистика лучей не собирается: пересоберите с --features stats");
+            std::process::exit(1);
+        }
+        output
+    }
+
+    fn report(&self, stats: &RenderStats, frame: Option<usize>) {
+        if self.print {
+            println!("{}", stats);
+        }
+        if let Some(json_path) = &self.json_path {
+            let path = match frame {
+                Some(frame) => {
+                    let path = Path::new(json_path);
+                    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("stats");
+                    path.with_file_name(format!("{}_{:06}.json", stem, frame))
+                }
+                None => Path::new(json_path).to_path_buf(),
+            };
+            fs::write(&path, stats.to_json()).unwrap_or_else(|e| {
+                eprintln!("Ошибка при сохранении статистики в {}: {}", path.display(), e);
+            });
+        }
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let stats_output = StatsOutput::from_args(&args);
 
     // Проверка на наличие флага --animate-to
     if let Some(dir_index) = args.iter().position(|a| a == "--animate-to") {
@@ -34,21 +83,23 @@ fn main() {
             // Создаём директорию
             fs::create_dir_all(output_dir).expect("Не удалось создать директорию");
 
-            start_animation_mode(output_dir, frames_limit, delta);
+            start_animation_mode(output_dir, frames_limit, delta, &stats_output);
 
             println!("Анимация завершена. Кадры сохранены в '{}'.", output_dir);
             return;
         } else {
-            eprintln!("Использование: --animate-to <dir> --frames-limit <число> --delta <значение>");
+            eprintln!(
+                "Использование: --animate-to <dir> --frames-limit <число> --delta <значение> [--stats] [--stats-json <file>]"
+            );
             std::process::exit(1);
         }
     }
 
     // === Основной интерактивный режим (GUI) ===
-    open_interactive_window();
+    open_interactive_window(&stats_output);
 }
 
-fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64) {
+fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64, stats_output: &StatsOutput) {
     let size = 900;
     let mut buffer = vec![0u8; size as usize * size as usize * 3];
 
@@ -92,6 +143,7 @@ fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64) {
             color: Color { r: 100, g: 100, b: 0 },
             specular: 50,
             reflective: 0.0,
+            roughness: 0.0,
         });
         // let complex_shape = create_complex_shape();
         // let complex_shape_with_transform = Shape::Transformed {
@@ -138,7 +190,8 @@ fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64) {
         let start_time = Instant::now();
 
         // Рендерим кадр
-        gambetta_raytracer::render_scene_to_buffer(&scene, &lights, &mut buffer, size, origin, rotation);
+        let stats =
+            gambetta_raytracer::render_scene_to_buffer(&scene, &lights, &mut buffer, size, origin, rotation);
 
         let render_time = start_time.elapsed();
         println!(
@@ -147,6 +200,7 @@ fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64) {
             frames_limit,
             render_time
         );
+        stats_output.report(&stats, Some(frame + 1));
 
         // Конвертируем буфер в изображение
         let img = RgbImage::from_raw(size as u32, size as u32, buffer.clone())
@@ -160,7 +214,7 @@ fn start_animation_mode(output_dir: &String, frames_limit: usize, delta: f64) {
     }
 }
 
-fn open_interactive_window() {
+fn open_interactive_window(stats_output: &StatsOutput) {
     let size = 900;
     let mut buffer = vec![0u8; size as usize * size as usize * 3];
 
@@ -170,6 +224,10 @@ fn open_interactive_window() {
 
     let mut angle = 0.0;
 
+    let mut render_settings = RenderSettings::default();
+    let denoise_settings = DenoiseSettings::default();
+    let mut denoise_enabled = false;
+
     let origin = Vector3f { x: x_position, y: y_position, z: z_position };
     let rotation = vectors::rotate_y_deg(angle);
 
@@ -191,6 +249,7 @@ fn open_interactive_window() {
         color: Color { r: 100, g: 100, b: 0 },
         specular: 50,
         reflective: 0.0,
+        roughness: 0.0,
     });
 
     let complex_shape = create_complex_shape();
@@ -217,7 +276,8 @@ fn open_interactive_window() {
         .rotate_x_all_deg(20.0, Vector3f { x: 0.0, y: 0.0, z: 0.0 })
         .translate_all(3.0, 5.0, 3.5);
 
-    let cube = create_cube_mesh(2.0, Color { r: 80, g: 0, b: 150 }, 300, 0.4);
+    // матовый металл: размытое отражение хорошо видно при нескольких лучах на пиксель
+    let cube = create_cube_mesh(2.0, Color { r: 80, g: 0, b: 150 }, 300, 0.4).with_roughness(0.15);
     let cube_shape = Shape::Mesh(cube);
     let transformed_cube = cube_shape
         .rotate_x_all_deg(45.0, Vector3f { x: 0.0, y: 0.0, z: 0.0 })
@@ -240,6 +300,9 @@ fn open_interactive_window() {
     println!(" - use W, A, S, D to move camera");
     println!(" - use Q, E to rotate camera left/right");
     println!(" - use R, F to raise/lower camera");
+    println!(" - use 1, 2, 3, 4 to set samples per pixel");
+    println!(" - use N to toggle denoising");
+    println!(" - use G to toggle fog");
 
     // let scene = vec![
     //     Shape::Sphere(Sphere {
@@ -311,10 +374,36 @@ fn open_interactive_window() {
         let start_time = Instant::now();
 
         // Render frame
-        gambetta_raytracer::render_scene_to_buffer(&scene, &lights, &mut buffer, size, origin, rotation);
+        let frame = gambetta_raytracer::render_scene_to_frame(
+            &scene,
+            &lights,
+            size,
+            origin,
+            rotation,
+            &render_settings,
+        );
+        if denoise_enabled {
+            gambetta_raytracer::write_hdr_to_buffer(
+                &denoise::denoise(&frame, &denoise_settings),
+                &mut buffer,
+            );
+        } else {
+            frame.write_to_buffer(&mut buffer);
+        }
 
         let render_time = start_time.elapsed();
-        println!("Rendering took: {:?}", render_time);
+        println!(
+            "Rendering took: {:?} ({} spp, denoising {}, fog {})",
+            render_time,
+            render_settings.samples_per_pixel,
+            if denoise_enabled { "on" } else { "off" },
+            if render_settings.fog.is_some() {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        stats_output.report(&frame.stats, None);
 
         texture.update(None, &buffer, size * 3).unwrap();
         canvas.clear();
@@ -356,6 +445,37 @@ fn open_interactive_window() {
                     y_position -= 0.5;
                     break 'event_loop;
                 }
+                Event::KeyDown { keycode: Some(Keycode::_1), .. } => {
+                    render_settings.samples_per_pixel = 1;
+                    break 'event_loop;
+                }
+                Event::KeyDown { keycode: Some(Keycode::_2), .. } => {
+                    render_settings.samples_per_pixel = 2;
+                    break 'event_loop;
+                }
+                Event::KeyDown { keycode: Some(Keycode::_3), .. } => {
+                    render_settings.samples_per_pixel = 3;
+                    break 'event_loop;
+                }
+                Event::KeyDown { keycode: Some(Keycode::_4), .. } => {
+                    render_settings.samples_per_pixel = 4;
+                    break 'event_loop;
+                }
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                    denoise_enabled = !denoise_enabled;
+                    break 'event_loop;
+                }
+                Event::KeyDown { keycode: Some(Keycode::G), .. } => {
+                    render_settings.fog = match render_settings.fog {
+                        Some(_) => None,
+                        None => Some(Medium {
+                            absorption: 0.01,
+                            scattering: 0.03,
+                            color: Color { r: 180, g: 190, b: 200 },
+                        }),
+                    };
+                    break 'event_loop;
+                }
                 Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
                     // Сохраняем скриншот
                     let img = RgbImage::from_raw(size as u32, size as u32, buffer.clone())
@@ -399,6 +519,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 255, g: 0, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let blue_inside_sphere = Shape::Sphere(Sphere {
         center: Vector3f { x: 0.0, y: 0.0, z: 0.0 },
@@ -406,6 +527,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 0, b: 255 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let thin_sphere = Shape::CSG {
         op: CSGOperation::Difference,
@@ -418,6 +540,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let cutoff_from_right = Shape::CSG {
         op: CSGOperation::Difference,
@@ -430,6 +553,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let cutoff_from_left = Shape::CSG {
         op: CSGOperation::Difference,
@@ -442,6 +566,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let cutoff_from_top = Shape::CSG {
         op: CSGOperation::Difference,
@@ -454,6 +579,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let cutoff_from_bottom = Shape::CSG {
         op: CSGOperation::Difference,
@@ -466,6 +592,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     let cutoff_from_front = Shape::CSG {
         op: CSGOperation::Difference,
@@ -478,6 +605,7 @@ fn create_complex_shape() -> Shape {
         color: Color { r: 0, g: 255, b: 0 },
         specular: 200,
         reflective: 0.0,
+        roughness: 0.0,
     });
     Shape::CSG {
         op: CSGOperation::Difference,